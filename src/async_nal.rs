@@ -0,0 +1,466 @@
+//! Implementations of the [embedded_nal_async] traits.
+//!
+//! The traits take `&self`, so the [Modem] is shared through an [AsyncModem].
+//! Operations that would block wait for the IPC interrupt of the modem instead of spinning,
+//! so `ipc_irq_handler` has to be called from the IPC interrupt.
+
+use crate::{
+    backend::{ModemBackend, WakerSlot},
+    command::{ReadRegistrationMode, SetRegistrationMode, MAX_RESPONSE_SIZE},
+    error::Error,
+    log, lte_wait_result,
+    tcp::TcpSocket,
+    to_nb_result,
+    udp::UdpSocket,
+    urc::{lines, Urc},
+    Modem,
+};
+use core::{
+    cell::RefCell,
+    future::poll_fn,
+    net::{IpAddr, SocketAddr},
+    task::Poll,
+};
+use embedded_nal::{nb, Dns, TcpClientStack, UdpClientStack, UdpFullStack};
+
+/// A [Modem] that can be used with the [embedded_nal_async] traits
+pub struct AsyncModem<B: ModemBackend> {
+    modem: RefCell<Modem<B>>,
+}
+
+impl<B: ModemBackend> AsyncModem<B> {
+    pub fn new(modem: Modem<B>) -> Self {
+        Self {
+            modem: RefCell::new(modem),
+        }
+    }
+
+    /// Get the modem back
+    pub fn into_inner(self) -> Modem<B> {
+        self.modem.into_inner()
+    }
+
+    /// Use the modem directly, e.g. to send AT commands.
+    ///
+    /// The modem must not be borrowed while an operation of the async traits is awaited.
+    pub fn with_modem<R>(&self, f: impl FnOnce(&mut Modem<B>) -> R) -> R {
+        f(&mut self.modem.borrow_mut())
+    }
+
+    /// Keep calling the operation until it doesn't return `WouldBlock`,
+    /// waiting for news from the modem in between
+    async fn poll_modem<T>(
+        &self,
+        mut operation: impl FnMut(&mut Modem<B>) -> nb::Result<T, Error>,
+    ) -> Result<T, Error> {
+        // Every waiting task has its own slot, so tasks that wait at the same time are all woken
+        let slot = WakerSlot::acquire();
+
+        poll_fn(|cx| {
+            let mut modem = self.modem.borrow_mut();
+
+            // Register first, so an interrupt that comes in during the operation isn't missed
+            modem.backend.register_waker(&slot, cx.waker());
+
+            match operation(&mut modem) {
+                Ok(value) => Poll::Ready(Ok(value)),
+                Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+                Err(nb::Error::WouldBlock) => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    /// Resolve the hostname to all of its addresses, like [Modem::get_hosts_by_name]
+    pub async fn get_hosts_by_name<const N: usize>(
+        &self,
+        host: &str,
+        addr_type: embedded_nal_async::AddrType,
+    ) -> Result<heapless::Vec<IpAddr, N>, Error> {
+        let addr_type = to_nal_addr_type(addr_type);
+
+        let addresses = self
+            .poll_modem(|modem| modem.get_hosts_by_name::<N>(host, addr_type.clone()))
+            .await?;
+
+        Ok(addresses.into_iter().map(from_nal_ip).collect())
+    }
+
+    /// Wait until the modem is registered to the network.
+    ///
+    /// Registration notifications are turned on during the wait if they are off, so every change wakes the task.
+    /// The task reads the new status from the notifications, so no commands are sent while waiting.
+    /// The notification mode is restored afterwards.
+    async fn wait_for_lte(&self) -> Result<(), Error> {
+        log::trace!("Waiting for LTE");
+
+        // Usually the modem is registered already
+        match self.with_modem(|modem| modem.wait_for_lte()) {
+            Ok(()) => return Ok(()),
+            Err(nb::Error::Other(e)) => return Err(e),
+            Err(nb::Error::WouldBlock) => {}
+        }
+
+        let (mut at, mode) = self.with_modem(|modem| -> Result<_, Error> {
+            let mut at = modem.at_socket()?;
+            let result = modem.at_connect(&mut at).and_then(|_| {
+                let mode = modem.execute_on(&mut at, &ReadRegistrationMode)?;
+                if mode == 0 {
+                    modem.execute_on(&mut at, &SetRegistrationMode(1))?;
+                }
+                Ok(mode)
+            });
+
+            match result {
+                Ok(mode) => Ok((at, mode)),
+                Err(e) => {
+                    modem.at_close(at)?;
+                    Err(e)
+                }
+            }
+        })?;
+
+        // Checked once after the notifications are turned on, so no change is missed.
+        // After that, only the notifications are read, so waiting doesn't send any commands.
+        let result = match self.with_modem(|modem| modem.wait_for_lte()) {
+            Err(nb::Error::WouldBlock) => {
+                self.poll_modem(|modem| {
+                    let mut buffer = [0; MAX_RESPONSE_SIZE];
+                    let mut result = Err(nb::Error::WouldBlock);
+
+                    loop {
+                        let length = match modem.at_receive(&mut at, &mut buffer) {
+                            Ok(length) => length,
+                            Err(nb::Error::WouldBlock) => return result,
+                            Err(e) => return Err(e),
+                        };
+
+                        for line in to_nb_result(lines(&buffer[..length]))? {
+                            if let Some(Ok(Urc::Registration(status))) = Urc::parse(line) {
+                                result = lte_wait_result(status.state);
+                            }
+                        }
+                    }
+                })
+                .await
+            }
+            Err(nb::Error::Other(e)) => Err(e),
+            Ok(()) => Ok(()),
+        };
+
+        self.with_modem(|modem| {
+            let restore_result = if mode == 0 {
+                modem.execute_on(&mut at, &SetRegistrationMode(mode))
+            } else {
+                Ok(())
+            };
+            modem.at_close(at)?;
+            result.and(restore_result)
+        })
+    }
+}
+
+/// Convert the address to the type of [embedded_nal]
+fn to_nal_address(address: SocketAddr) -> embedded_nal::SocketAddr {
+    match address {
+        SocketAddr::V4(address) => embedded_nal::SocketAddr::new(
+            embedded_nal::Ipv4Addr::from(address.ip().octets()).into(),
+            address.port(),
+        ),
+        SocketAddr::V6(address) => embedded_nal::SocketAddr::new(
+            embedded_nal::Ipv6Addr::from(address.ip().octets()).into(),
+            address.port(),
+        ),
+    }
+}
+
+/// Convert the address from the type of [embedded_nal]
+fn from_nal_address(address: embedded_nal::SocketAddr) -> SocketAddr {
+    SocketAddr::new(from_nal_ip(address.ip()), address.port())
+}
+
+/// Convert the address from the type of [embedded_nal]
+fn from_nal_ip(address: embedded_nal::IpAddr) -> IpAddr {
+    match address {
+        embedded_nal::IpAddr::V4(address) => IpAddr::from(address.octets()),
+        embedded_nal::IpAddr::V6(address) => IpAddr::from(address.octets()),
+    }
+}
+
+/// Convert the address type to the one of [embedded_nal]
+fn to_nal_addr_type(addr_type: embedded_nal_async::AddrType) -> embedded_nal::AddrType {
+    match addr_type {
+        embedded_nal_async::AddrType::IPv4 => embedded_nal::AddrType::IPv4,
+        embedded_nal_async::AddrType::IPv6 => embedded_nal::AddrType::IPv6,
+        embedded_nal_async::AddrType::Either => embedded_nal::AddrType::Either,
+    }
+}
+
+/// A TCP connection of an [AsyncModem]. The socket is closed when this is dropped.
+pub struct TcpConnection<'a, B: ModemBackend> {
+    stack: &'a AsyncModem<B>,
+    socket: Option<TcpSocket>,
+}
+
+impl<B: ModemBackend> Drop for TcpConnection<'_, B> {
+    fn drop(&mut self) {
+        if let Some(socket) = self.socket.take() {
+            if let Err(_e) = self
+                .stack
+                .with_modem(|modem| TcpClientStack::close(modem, socket))
+            {
+                log::error!("Could not close TCP socket: {:?}", _e);
+            }
+        }
+    }
+}
+
+impl<B: ModemBackend> embedded_io_async::ErrorType for TcpConnection<'_, B> {
+    type Error = Error;
+}
+
+impl<B: ModemBackend> embedded_io_async::Read for TcpConnection<'_, B> {
+    async fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        let socket = self.socket.as_mut().unwrap();
+        self.stack
+            .poll_modem(|modem| TcpClientStack::receive(modem, socket, buffer))
+            .await
+    }
+}
+
+impl<B: ModemBackend> embedded_io_async::Write for TcpConnection<'_, B> {
+    async fn write(&mut self, buffer: &[u8]) -> Result<usize, Self::Error> {
+        let socket = self.socket.as_mut().unwrap();
+        self.stack
+            .poll_modem(|modem| TcpClientStack::send(modem, socket, buffer))
+            .await
+    }
+}
+
+impl<B: ModemBackend> embedded_nal_async::TcpConnect for AsyncModem<B> {
+    type Error = Error;
+    type Connection<'a>
+        = TcpConnection<'a, B>
+    where
+        Self: 'a;
+
+    async fn connect<'a>(
+        &'a self,
+        remote: SocketAddr,
+    ) -> Result<Self::Connection<'a>, Self::Error> {
+        let socket = self.with_modem(TcpClientStack::socket)?;
+
+        // Closes the socket if connecting fails
+        let mut connection = TcpConnection {
+            stack: self,
+            socket: Some(socket),
+        };
+        let socket = connection.socket.as_mut().unwrap();
+        let remote = to_nal_address(remote);
+
+        // Turn on LTE without waiting for the registration
+        self.with_modem(|modem| modem.tcp_turn_on_lte(socket))?;
+        self.wait_for_lte().await?;
+
+        self.poll_modem(|modem| modem.tcp_connect_nonblocking(socket, remote))
+            .await?;
+
+        Ok(connection)
+    }
+}
+
+/// A connected UDP socket of an [AsyncModem]. The socket is closed when this is dropped.
+pub struct ConnectedUdp<'a, B: ModemBackend> {
+    stack: &'a AsyncModem<B>,
+    socket: Option<UdpSocket>,
+}
+
+impl<B: ModemBackend> Drop for ConnectedUdp<'_, B> {
+    fn drop(&mut self) {
+        if let Some(socket) = self.socket.take() {
+            if let Err(_e) = self
+                .stack
+                .with_modem(|modem| UdpClientStack::close(modem, socket))
+            {
+                log::error!("Could not close UDP socket: {:?}", _e);
+            }
+        }
+    }
+}
+
+impl<B: ModemBackend> embedded_nal_async::ConnectedUdp for ConnectedUdp<'_, B> {
+    type Error = Error;
+
+    async fn send(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        let socket = self.socket.as_mut().unwrap();
+        self.stack
+            .poll_modem(|modem| UdpClientStack::send(modem, socket, data))
+            .await
+    }
+
+    async fn receive_into(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        let socket = self.socket.as_mut().unwrap();
+        self.stack
+            .poll_modem(|modem| UdpClientStack::receive(modem, socket, buffer))
+            .await
+            .map(|(length, _)| length)
+    }
+}
+
+/// A UDP socket of an [AsyncModem] that is bound to a local port. The socket is closed when this is dropped.
+///
+/// The modem only has one local address, so the local address given when sending is ignored.
+pub struct UnconnectedUdp<'a, B: ModemBackend> {
+    stack: &'a AsyncModem<B>,
+    socket: Option<UdpSocket>,
+    local: SocketAddr,
+}
+
+impl<B: ModemBackend> Drop for UnconnectedUdp<'_, B> {
+    fn drop(&mut self) {
+        if let Some(socket) = self.socket.take() {
+            if let Err(_e) = self
+                .stack
+                .with_modem(|modem| UdpClientStack::close(modem, socket))
+            {
+                log::error!("Could not close UDP socket: {:?}", _e);
+            }
+        }
+    }
+}
+
+impl<B: ModemBackend> embedded_nal_async::UnconnectedUdp for UnconnectedUdp<'_, B> {
+    type Error = Error;
+
+    async fn send(
+        &mut self,
+        _local: SocketAddr,
+        remote: SocketAddr,
+        data: &[u8],
+    ) -> Result<(), Self::Error> {
+        let socket = self.socket.as_mut().unwrap();
+        let remote = to_nal_address(remote);
+        self.stack
+            .poll_modem(|modem| UdpFullStack::send_to(modem, socket, remote, data))
+            .await
+    }
+
+    /// Receive a datagram. Datagrams that don't fit in the buffer are cut off,
+    /// and the returned length is the length that was received.
+    async fn receive_into(
+        &mut self,
+        buffer: &mut [u8],
+    ) -> Result<(usize, SocketAddr, SocketAddr), Self::Error> {
+        let socket = self.socket.as_mut().unwrap();
+        let (length, remote) = self
+            .stack
+            .poll_modem(|modem| UdpClientStack::receive(modem, socket, buffer))
+            .await?;
+
+        Ok((length, self.local, from_nal_address(remote)))
+    }
+}
+
+/// The UDP sockets borrow the modem, so the stack is implemented for a reference to it
+impl<'a, B: ModemBackend> embedded_nal_async::UdpStack for &'a AsyncModem<B> {
+    type Error = Error;
+    type Connected = ConnectedUdp<'a, B>;
+    type UniquelyBound = UnconnectedUdp<'a, B>;
+    type MultiplyBound = UnconnectedUdp<'a, B>;
+
+    /// Connect to the remote address.
+    ///
+    /// The modem doesn't tell which local address is used, so the returned local address is the given one.
+    async fn connect_from(
+        &self,
+        local: SocketAddr,
+        remote: SocketAddr,
+    ) -> Result<(SocketAddr, Self::Connected), Self::Error> {
+        let stack: &'a AsyncModem<B> = self;
+        let socket = stack.with_modem(UdpClientStack::socket)?;
+
+        // Closes the socket if connecting fails
+        let mut connection = ConnectedUdp {
+            stack,
+            socket: Some(socket),
+        };
+        let socket = connection.socket.as_mut().unwrap();
+        let remote = to_nal_address(remote);
+
+        // Turn on LTE without waiting for the registration
+        stack.with_modem(|modem| modem.udp_turn_on_lte(socket))?;
+        stack.wait_for_lte().await?;
+        stack.with_modem(|modem| UdpClientStack::connect(modem, socket, remote))?;
+
+        Ok((local, connection))
+    }
+
+    /// Bind to the port of the local address.
+    ///
+    /// The modem doesn't tell which local address is used, so the returned local address is the given one.
+    async fn bind_single(
+        &self,
+        local: SocketAddr,
+    ) -> Result<(SocketAddr, Self::UniquelyBound), Self::Error> {
+        let stack: &'a AsyncModem<B> = self;
+        let socket = stack.with_modem(UdpClientStack::socket)?;
+
+        // Closes the socket if binding fails
+        let mut bound = UnconnectedUdp {
+            stack,
+            socket: Some(socket),
+            local,
+        };
+        let socket = bound.socket.as_mut().unwrap();
+
+        // Turn on LTE without waiting for the registration
+        stack.with_modem(|modem| modem.udp_turn_on_lte(socket))?;
+        stack.wait_for_lte().await?;
+        stack.with_modem(|modem| UdpFullStack::bind(modem, socket, local.port()))?;
+
+        Ok((local, bound))
+    }
+
+    /// Bind to the port of the local address, like [bind_single](embedded_nal_async::UdpStack::bind_single)
+    async fn bind_multiple(&self, local: SocketAddr) -> Result<Self::MultiplyBound, Self::Error> {
+        self.bind_single(local).await.map(|(_, bound)| bound)
+    }
+}
+
+impl<B: ModemBackend> embedded_nal_async::Dns for AsyncModem<B> {
+    type Error = Error;
+
+    async fn get_host_by_name(
+        &self,
+        host: &str,
+        addr_type: embedded_nal_async::AddrType,
+    ) -> Result<IpAddr, Self::Error> {
+        let addr_type = to_nal_addr_type(addr_type);
+
+        self.poll_modem(|modem| modem.get_host_by_name(host, addr_type.clone()))
+            .await
+            .map(from_nal_ip)
+    }
+
+    async fn get_host_by_address(
+        &self,
+        addr: IpAddr,
+        result: &mut [u8],
+    ) -> Result<usize, Self::Error> {
+        let addr = match addr {
+            IpAddr::V4(addr) => embedded_nal::Ipv4Addr::from(addr.octets()).into(),
+            IpAddr::V6(addr) => embedded_nal::Ipv6Addr::from(addr.octets()).into(),
+        };
+
+        let name = self
+            .poll_modem(|modem| modem.get_host_by_address(addr))
+            .await?;
+
+        let destination = result
+            .get_mut(..name.len())
+            .ok_or(Error::BufferTooSmall(Some(name.len())))?;
+        destination.copy_from_slice(name.as_bytes());
+
+        Ok(name.len())
+    }
+}
@@ -1,12 +1,16 @@
-use crate::{error::Error, log, Modem, SocketState};
+use crate::{
+    backend::{ModemBackend, SocketHandle, SocketKind},
+    error::{AtError, CmeError, CmsError, Error},
+    log, Modem, SocketState,
+};
 use embedded_nal::nb;
 
-impl Modem {
+impl<B: ModemBackend> Modem<B> {
     /// Create an AT socket with which you can communicate with the modem directly
     pub fn at_socket(&mut self) -> Result<AtSocket, Error> {
         log::debug!("Creating AT socket");
         Ok(AtSocket {
-            inner: nrfxlib::at::AtSocket::new()?,
+            inner: self.backend.socket(SocketKind::At)?,
             state: SocketState::Closed,
         })
     }
@@ -26,15 +30,7 @@ impl Modem {
     }
 
     pub fn at_send(&mut self, socket: &mut AtSocket, data: &str) -> Result<(), Error> {
-        log::trace!("Sending on AT socket");
-
-        if !socket.state.is_connected() {
-            return Err(Error::SocketClosed);
-        }
-
-        socket.inner.send_command(data)?;
-
-        Ok(())
+        self.at_send_raw(socket, data.as_bytes())
     }
 
     pub fn at_send_raw(&mut self, socket: &mut AtSocket, data: &[u8]) -> Result<(), Error> {
@@ -44,22 +40,47 @@ impl Modem {
             return Err(Error::SocketClosed);
         }
 
-        socket.inner.write(data)?;
+        self.backend.write(socket.inner, data)?;
 
         Ok(())
     }
 
+    /// Read from the AT socket until we get something that indicates the command has completed.
+    ///
+    /// Commands are completed by `OK`, `ERROR`, `+CME ERROR:xxx` or `+CMS ERROR:xxx`.
+    /// Any other line received is passed to the callback function.
     pub fn at_poll_response<F>(
         &mut self,
         socket: &mut AtSocket,
-        callback_function: F,
+        mut callback_function: F,
     ) -> Result<(), Error>
     where
         F: FnMut(&str),
     {
         log::trace!("Polling response on AT socket");
-        socket.inner.poll_response(callback_function)?;
-        Ok(())
+
+        loop {
+            let mut buffer = [0u8; 256];
+            let length = loop {
+                if let Some(length) = self.backend.receive(socket.inner, &mut buffer)? {
+                    break length;
+                }
+            };
+
+            let response = core::str::from_utf8(&buffer[..length])
+                .map_err(|_| Error::UnexpectedAtResponse)?
+                .trim_end_matches('\0');
+
+            for line in response.lines() {
+                match line.trim() {
+                    "" => {}
+                    line => match final_result(line) {
+                        Some(result) => return result,
+                        None => callback_function(line),
+                    },
+                }
+            }
+        }
     }
 
     pub fn at_receive(
@@ -73,10 +94,10 @@ impl Modem {
             return nb::Result::Err(nb::Error::Other(Error::SocketClosed));
         }
 
-        match socket.inner.recv(buffer) {
+        match self.backend.receive(socket.inner, buffer) {
             Ok(Some(amount)) => nb::Result::Ok(amount),
             Ok(None) => nb::Result::Err(nb::Error::WouldBlock),
-            Err(e) => nb::Result::Err(nb::Error::Other(e.into())),
+            Err(e) => nb::Result::Err(nb::Error::Other(e)),
         }
     }
 
@@ -84,14 +105,103 @@ impl Modem {
         log::debug!("Closing AT socket");
 
         socket.state = SocketState::Closed;
+        self.backend.close(socket.inner)?;
         drop(socket);
 
         Ok(())
     }
+
+    /// Sends an AT command on a new AT socket and calls the callback with every line of the response.
+    /// This blocks until the modem has responded with `OK` or an error.
+    pub(crate) fn send_at_command<C, F>(&mut self, command: &C, callback: F) -> Result<(), Error>
+    where
+        C: AsRef<[u8]> + ?Sized,
+        F: FnMut(&str),
+    {
+        let mut socket = self.at_socket()?;
+
+        let result = self
+            .at_connect(&mut socket)
+            .and_then(|_| self.at_send_raw(&mut socket, command.as_ref()))
+            .and_then(|_| self.at_poll_response(&mut socket, callback));
+
+        self.at_close(socket)?;
+
+        result
+    }
+
+    /// Sends an AT command on a new AT socket and receives the whole response into the buffer,
+    /// for responses that are too long for [Self::send_at_command].
+    ///
+    /// Returns the response without the final result code.
+    pub(crate) fn send_at_command_buffered<'b, C>(
+        &mut self,
+        command: &C,
+        buffer: &'b mut [u8],
+    ) -> Result<&'b str, Error>
+    where
+        C: AsRef<[u8]> + ?Sized,
+    {
+        let mut socket = self.at_socket()?;
+
+        let result = self
+            .at_connect(&mut socket)
+            .and_then(|_| self.at_send_raw(&mut socket, command.as_ref()))
+            .and_then(|_| loop {
+                // The modem gives the response in one go
+                if let Some(length) = self.backend.receive(socket.inner, buffer)? {
+                    break Ok(length);
+                }
+            });
+
+        self.at_close(socket)?;
+
+        let length = result?;
+        let response = core::str::from_utf8(&buffer[..length])
+            .map_err(|_| Error::UnexpectedAtResponse)?
+            .trim_end_matches('\0');
+
+        match split_final_result(response) {
+            Some(result) => result,
+            None if length == buffer.len() => Err(Error::BufferTooSmall(None)),
+            None => Err(Error::NoAtResponse),
+        }
+    }
+}
+
+/// Returns the response without the final result code, or the error of the final result code.
+///
+/// Returns `None` if the response has no final result code.
+pub(crate) fn split_final_result(response: &str) -> Option<Result<&str, Error>> {
+    for line in response.lines() {
+        if let Some(result) = final_result(line.trim()) {
+            let end = line.as_ptr() as usize - response.as_ptr() as usize;
+            return Some(result.map(|_| &response[..end]));
+        }
+    }
+
+    None
+}
+
+/// Returns the result if the line is a final result code: `OK`, `ERROR`, `+CME ERROR:xxx` or `+CMS ERROR:xxx`
+pub(crate) fn final_result(line: &str) -> Option<Result<(), Error>> {
+    match line {
+        "OK" => Some(Ok(())),
+        "ERROR" => Some(Err(AtError::Error.into())),
+        line if line.starts_with("+CME ERROR:") => {
+            let code = line[11..].trim().parse().unwrap_or(-1);
+            Some(Err(AtError::CmeError(CmeError::from_code(code)).into()))
+        }
+        line if line.starts_with("+CMS ERROR:") => {
+            let code = line[11..].trim().parse().unwrap_or(-1);
+            Some(Err(AtError::CmsError(CmsError::from_code(code)).into()))
+        }
+        _ => None,
+    }
 }
 
 pub struct AtSocket {
-    inner: nrfxlib::at::AtSocket,
+    inner: SocketHandle,
     state: SocketState,
 }
 
@@ -0,0 +1,837 @@
+//! The modem library the [Modem](crate::Modem) runs on top of.
+//!
+//! All interaction with the modem goes through the [ModemBackend] trait.
+//! On the nRF9160 this is the `NrfxlibBackend`, but any other implementation can be plugged in,
+//! for example to run the same code against a simulated modem on the host.
+
+#[cfg(feature = "nrfxlib")]
+use crate::error::Errno;
+#[cfg(any(feature = "nrfxlib", feature = "async"))]
+use crate::log;
+use crate::{
+    dtls::DtlsOptions,
+    error::Error,
+    gnss::{GnssData, GnssOptions},
+    tls::TlsOptions,
+};
+use embedded_nal::{AddrType, IpAddr, SocketAddr};
+
+/// A handle to a socket that is owned by a [ModemBackend]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SocketHandle(pub i32);
+
+/// The kind of socket a [ModemBackend] can create
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketKind {
+    /// A socket to send AT commands to the modem
+    At,
+    /// A plain TCP socket
+    Tcp,
+    /// A plain UDP socket
+    Udp,
+    /// A TCP socket with TLS
+    Tls,
+    /// A UDP socket with DTLS
+    Dtls,
+    /// A socket to the GNSS subsystem
+    Gnss,
+}
+
+/// The interface to the modem library
+///
+/// All socket operations are non-blocking unless documented otherwise.
+/// Operations that would block return `Ok(None)`.
+pub trait ModemBackend {
+    /// Initialize the modem library. The radios must be turned off after this.
+    fn init(&mut self) -> Result<(), Error>;
+
+    /// Create a new socket
+    fn socket(&mut self, kind: SocketKind) -> Result<SocketHandle, Error>;
+
+    /// Connect a TCP or UDP socket to the remote address. This blocks until the connection is made.
+    fn connect(&mut self, socket: SocketHandle, remote: SocketAddr) -> Result<(), Error>;
+
+    /// Connect a TCP socket to the remote address without waiting for the connection.
+    ///
+    /// This has to be called again with the same address until it returns `Ok(Some(()))`.
+    /// By default, this blocks like [ModemBackend::connect].
+    fn connect_nonblocking(
+        &mut self,
+        socket: SocketHandle,
+        remote: SocketAddr,
+    ) -> Result<Option<()>, Error> {
+        self.connect(socket, remote).map(Some)
+    }
+
+    /// Bind a TCP or UDP socket to the local port on all addresses
+    fn bind(&mut self, socket: SocketHandle, local_port: u16) -> Result<(), Error>;
+
+    /// Let a bound TCP socket accept incoming connections
+    fn listen(&mut self, socket: SocketHandle, backlog: usize) -> Result<(), Error>;
+
+    /// Accept an incoming connection on a listening socket.
+    /// Returns the new, connected socket and the address of the remote.
+    fn accept(&mut self, socket: SocketHandle)
+        -> Result<Option<(SocketHandle, SocketAddr)>, Error>;
+
+    /// Send data over the socket
+    fn send(&mut self, socket: SocketHandle, buffer: &[u8]) -> Result<Option<usize>, Error>;
+
+    /// Send a datagram over a UDP socket to the remote address
+    fn send_to(
+        &mut self,
+        socket: SocketHandle,
+        remote: SocketAddr,
+        buffer: &[u8],
+    ) -> Result<Option<usize>, Error>;
+
+    /// Write all data to the socket. This blocks until the data has been written.
+    ///
+    /// This is used to send AT commands.
+    fn write(&mut self, socket: SocketHandle, buffer: &[u8]) -> Result<usize, Error>;
+
+    /// Receive data from the socket
+    fn receive(&mut self, socket: SocketHandle, buffer: &mut [u8]) -> Result<Option<usize>, Error>;
+
+    /// Receive a datagram from a UDP socket, together with the address it came from if that's known
+    fn receive_from(
+        &mut self,
+        socket: SocketHandle,
+        buffer: &mut [u8],
+    ) -> Result<Option<(usize, Option<SocketAddr>)>, Error>;
+
+    /// Close the socket. The handle may not be used anymore afterwards.
+    fn close(&mut self, socket: SocketHandle) -> Result<(), Error>;
+
+    /// Resolve the hostname. The callback is called for every address that has been found.
+    ///
+    /// With [AddrType::Either], the addresses of both families are looked up.
+    /// Errors are the errno of `nrf_getaddrinfo`, e.g. [InvalidArgument](crate::error::Errno::InvalidArgument)
+    /// for a name that doesn't exist.
+    fn get_addr_info<F>(
+        &mut self,
+        hostname: &str,
+        addr_type: AddrType,
+        callback: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(IpAddr);
+
+    /// Let the socket use the PDN connection of the APN instead of the default one. This must be done before it connects.
+    fn bind_to_pdn(&mut self, socket: SocketHandle, apn: &str) -> Result<(), Error>;
+
+    /// Set the credentials, peer verification and hostname of a TLS socket before it connects
+    fn tls_configure(&mut self, socket: SocketHandle, options: &TlsOptions) -> Result<(), Error>;
+
+    /// Set the credentials, peer verification, hostname and DTLS settings of a DTLS socket before it connects
+    fn dtls_configure(&mut self, socket: SocketHandle, options: &DtlsOptions) -> Result<(), Error>;
+
+    /// Configure the GNSS socket and start the GNSS subsystem
+    fn gnss_start(&mut self, socket: SocketHandle, options: &GnssOptions) -> Result<(), Error>;
+
+    /// Get the next fix or NMEA string from the GNSS socket
+    fn gnss_receive(&mut self, socket: SocketHandle) -> Result<Option<GnssData>, Error>;
+
+    /// Wake the task when there is news from the modem, so an operation that would block can be retried.
+    ///
+    /// Every waiting task has its own slot. By default the task is woken by the next IPC interrupt, see `ipc_irq_handler`.
+    #[cfg(feature = "async")]
+    fn register_waker(&mut self, slot: &WakerSlot, waker: &core::task::Waker) {
+        slot.register(waker);
+    }
+}
+
+/// The amount of tasks that can wait for the modem at the same time.
+/// Tasks that don't get a slot are woken right away, so they poll until a slot is free.
+#[cfg(feature = "async")]
+pub const WAKER_SLOTS: usize = 8;
+
+#[cfg(feature = "async")]
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_WAKER: atomic_waker::AtomicWaker = atomic_waker::AtomicWaker::new();
+
+/// The tasks that wait for the next IPC interrupt
+#[cfg(feature = "async")]
+static IPC_WAKERS: [atomic_waker::AtomicWaker; WAKER_SLOTS] = [EMPTY_WAKER; WAKER_SLOTS];
+
+/// The slots of [IPC_WAKERS] that are in use, one bit per slot
+#[cfg(feature = "async")]
+static USED_WAKER_SLOTS: core::sync::atomic::AtomicU32 = core::sync::atomic::AtomicU32::new(0);
+
+/// The waker slot of a task that waits for the modem. The slot is freed when this is dropped.
+#[cfg(feature = "async")]
+#[derive(Debug)]
+pub struct WakerSlot {
+    index: Option<usize>,
+}
+
+#[cfg(feature = "async")]
+impl WakerSlot {
+    /// Take a free slot
+    pub fn acquire() -> Self {
+        use core::sync::atomic::Ordering;
+
+        let mut index = None;
+        let _ = USED_WAKER_SLOTS.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+            let free = (!used).trailing_zeros() as usize;
+            index = (free < WAKER_SLOTS).then_some(free);
+            index.map(|free| used | (1 << free))
+        });
+
+        if index.is_none() {
+            log::warning!("All waker slots are in use");
+        }
+
+        Self { index }
+    }
+
+    /// Wake the task with the next call of [wake_modem_tasks]
+    pub fn register(&self, waker: &core::task::Waker) {
+        match self.index {
+            Some(index) => IPC_WAKERS[index].register(waker),
+            None => waker.wake_by_ref(),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for WakerSlot {
+    fn drop(&mut self) {
+        if let Some(index) = self.index {
+            IPC_WAKERS[index].take();
+            USED_WAKER_SLOTS.fetch_and(!(1 << index), core::sync::atomic::Ordering::AcqRel);
+        }
+    }
+}
+
+/// Wake all tasks that wait for the modem.
+///
+/// `ipc_irq_handler` calls this. Backends that don't run on the IPC interrupt call this when there is news from the modem.
+#[cfg(feature = "async")]
+pub fn wake_modem_tasks() {
+    for waker in IPC_WAKERS.iter() {
+        waker.wake();
+    }
+}
+
+/// Storage that is big enough for both IPv4 and IPv6 addresses
+#[cfg(feature = "nrfxlib")]
+type SocketAddrStorage = nrfxlib_sys::nrf_sockaddr_in6;
+
+/// The backend that uses the nrfxlib modem library of the nRF9160
+#[cfg(feature = "nrfxlib")]
+#[derive(Debug, Default)]
+pub struct NrfxlibBackend {
+    _private: (),
+}
+
+#[cfg(feature = "nrfxlib")]
+impl NrfxlibBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Turns the result of a nrfxlib-sys call into an error if it failed
+    // The function is only logged
+    #[cfg_attr(not(feature = "log"), allow(unused_variables))]
+    fn check(function: &'static str, result: i32) -> Result<i32, Error> {
+        if result < 0 {
+            let errno = Errno::from_code(nrfxlib::get_last_error());
+            log::debug!("{} failed: {}", function, errno);
+            Err(Error::NrfSys(errno))
+        } else {
+            Ok(result)
+        }
+    }
+
+    /// Turns the result of a non-blocking nrfxlib-sys call into `None` if it would have blocked
+    fn check_nonblocking(function: &'static str, result: i32) -> Result<Option<usize>, Error> {
+        if result == -1 && nrfxlib::get_last_error() == nrfxlib_sys::NRF_EAGAIN as i32 {
+            Ok(None)
+        } else {
+            Self::check(function, result).map(|length| Some(length as usize))
+        }
+    }
+
+    fn set_nonblocking(socket: SocketHandle, nonblocking: bool) -> Result<(), Error> {
+        let flags = if nonblocking {
+            nrfxlib_sys::NRF_O_NONBLOCK as i32
+        } else {
+            0
+        };
+
+        let result =
+            unsafe { nrfxlib_sys::nrf_fcntl(socket.0, nrfxlib_sys::NRF_F_SETFL as i32, flags) };
+        Self::check("fcntl", result).map(|_| ())
+    }
+
+    fn set_option<T>(socket: SocketHandle, level: u32, name: u32, value: &T) -> Result<(), Error> {
+        Self::set_option_slice(socket, level, name, core::slice::from_ref(value))
+    }
+
+    fn set_option_slice<T>(
+        socket: SocketHandle,
+        level: u32,
+        name: u32,
+        value: &[T],
+    ) -> Result<(), Error> {
+        let result = unsafe {
+            nrfxlib_sys::nrf_setsockopt(
+                socket.0,
+                level as i32,
+                name as i32,
+                value.as_ptr() as *const _,
+                core::mem::size_of_val(value) as u32,
+            )
+        };
+        Self::check("setsockopt", result).map(|_| ())
+    }
+
+    /// Call the function with the address in the format of the modem library, and its length
+    fn with_address<R>(
+        address: SocketAddr,
+        function: impl FnOnce(*const core::ffi::c_void, u32) -> R,
+    ) -> R {
+        match address {
+            SocketAddr::V4(address) => {
+                let address = nrfxlib_sys::nrf_sockaddr_in {
+                    sin_len: core::mem::size_of::<nrfxlib_sys::nrf_sockaddr_in>() as u8,
+                    sin_family: nrfxlib_sys::NRF_AF_INET as i32,
+                    sin_port: address.port().to_be(),
+                    sin_addr: nrfxlib_sys::nrf_in_addr {
+                        s_addr: u32::from_ne_bytes(address.ip().octets()),
+                    },
+                };
+
+                function(&address as *const _ as *const _, address.sin_len as u32)
+            }
+            SocketAddr::V6(address) => {
+                let address = nrfxlib_sys::nrf_sockaddr_in6 {
+                    sin6_len: core::mem::size_of::<nrfxlib_sys::nrf_sockaddr_in6>() as u8,
+                    sin6_family: nrfxlib_sys::NRF_AF_INET6 as i32,
+                    sin6_port: address.port().to_be(),
+                    sin6_flowinfo: address.flowinfo(),
+                    sin6_addr: nrfxlib_sys::nrf_in6_addr {
+                        s6_addr: address.ip().octets(),
+                    },
+                    sin6_scope_id: address.scope_id(),
+                };
+
+                function(&address as *const _ as *const _, address.sin6_len as u32)
+            }
+        }
+    }
+
+    /// Read an address that was filled in by the modem library
+    fn read_address(address: &SocketAddrStorage) -> Result<SocketAddr, Error> {
+        use embedded_nal::{Ipv4Addr, Ipv6Addr};
+
+        if address.sin6_family == nrfxlib_sys::NRF_AF_INET as i32 {
+            // Safety: The family says it's an IPv4 address, which is smaller than the storage
+            let address = unsafe {
+                &*(address as *const SocketAddrStorage as *const nrfxlib_sys::nrf_sockaddr_in)
+            };
+            Ok(SocketAddr::new(
+                Ipv4Addr::from(address.sin_addr.s_addr.to_ne_bytes()).into(),
+                u16::from_be(address.sin_port),
+            ))
+        } else if address.sin6_family == nrfxlib_sys::NRF_AF_INET6 as i32 {
+            Ok(SocketAddr::new(
+                Ipv6Addr::from(address.sin6_addr.s6_addr).into(),
+                u16::from_be(address.sin6_port),
+            ))
+        } else {
+            Err(nrfxlib::Error::BadDataFormat.into())
+        }
+    }
+
+    /// Set the security tags, peer verification and hostname of a TLS or DTLS socket
+    fn set_security_options(socket: SocketHandle, options: &TlsOptions) -> Result<(), Error> {
+        Self::set_option_slice(
+            socket,
+            nrfxlib_sys::NRF_SOL_SECURE,
+            nrfxlib_sys::NRF_SO_SEC_TAG_LIST,
+            options.sec_tags,
+        )?;
+        Self::set_option(
+            socket,
+            nrfxlib_sys::NRF_SOL_SECURE,
+            nrfxlib_sys::NRF_SO_SEC_PEER_VERIFY,
+            &(options.peer_verification as i32),
+        )?;
+
+        // The hostname is given without a null terminator
+        if let Some(hostname) = options.hostname {
+            Self::set_option_slice(
+                socket,
+                nrfxlib_sys::NRF_SOL_SECURE,
+                nrfxlib_sys::NRF_SO_HOSTNAME,
+                hostname.as_bytes(),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "nrfxlib")]
+impl ModemBackend for NrfxlibBackend {
+    fn init(&mut self) -> Result<(), Error> {
+        nrfxlib::init()?;
+        nrfxlib::modem::off()?;
+        Ok(())
+    }
+
+    fn socket(&mut self, kind: SocketKind) -> Result<SocketHandle, Error> {
+        let (family, socket_type, protocol) = match kind {
+            SocketKind::At => (
+                nrfxlib_sys::NRF_AF_LTE,
+                nrfxlib_sys::NRF_SOCK_DGRAM,
+                nrfxlib_sys::NRF_PROTO_AT,
+            ),
+            SocketKind::Tcp => (
+                nrfxlib_sys::NRF_AF_INET,
+                nrfxlib_sys::NRF_SOCK_STREAM,
+                nrfxlib_sys::NRF_IPPROTO_TCP,
+            ),
+            SocketKind::Udp => (
+                nrfxlib_sys::NRF_AF_INET,
+                nrfxlib_sys::NRF_SOCK_DGRAM,
+                nrfxlib_sys::NRF_IPPROTO_UDP,
+            ),
+            SocketKind::Tls => (
+                nrfxlib_sys::NRF_AF_INET,
+                nrfxlib_sys::NRF_SOCK_STREAM,
+                nrfxlib_sys::NRF_SPROTO_TLS1v2,
+            ),
+            SocketKind::Dtls => (
+                nrfxlib_sys::NRF_AF_INET,
+                nrfxlib_sys::NRF_SOCK_DGRAM,
+                nrfxlib_sys::NRF_SPROTO_DTLS1v2,
+            ),
+            SocketKind::Gnss => (
+                nrfxlib_sys::NRF_AF_LOCAL,
+                nrfxlib_sys::NRF_SOCK_DGRAM,
+                nrfxlib_sys::NRF_PROTO_GNSS,
+            ),
+        };
+
+        let fd =
+            unsafe { nrfxlib_sys::nrf_socket(family as i32, socket_type as i32, protocol as i32) };
+
+        Self::check("socket", fd).map(SocketHandle)
+    }
+
+    fn connect(&mut self, socket: SocketHandle, remote: SocketAddr) -> Result<(), Error> {
+        let result = Self::with_address(remote, |address, length| unsafe {
+            nrfxlib_sys::nrf_connect(socket.0, address as *const _, length)
+        });
+
+        Self::check("connect", result).map(|_| ())
+    }
+
+    fn connect_nonblocking(
+        &mut self,
+        socket: SocketHandle,
+        remote: SocketAddr,
+    ) -> Result<Option<()>, Error> {
+        Self::set_nonblocking(socket, true)?;
+
+        let result = Self::with_address(remote, |address, length| unsafe {
+            nrfxlib_sys::nrf_connect(socket.0, address as *const _, length)
+        });
+
+        // The connection is made in the background, and calling connect again tells whether it's done
+        let result = if result >= 0 {
+            Ok(Some(()))
+        } else {
+            match nrfxlib::get_last_error() as u32 {
+                nrfxlib_sys::NRF_EINPROGRESS | nrfxlib_sys::NRF_EALREADY => return Ok(None),
+                nrfxlib_sys::NRF_EISCONN => Ok(Some(())),
+                _ => Self::check("connect", result).map(|_| None),
+            }
+        };
+
+        // The other operations expect a blocking socket
+        Self::set_nonblocking(socket, false)?;
+
+        result
+    }
+
+    fn bind(&mut self, socket: SocketHandle, local_port: u16) -> Result<(), Error> {
+        let address = nrfxlib_sys::nrf_sockaddr_in {
+            sin_len: core::mem::size_of::<nrfxlib_sys::nrf_sockaddr_in>() as u8,
+            sin_family: nrfxlib_sys::NRF_AF_INET as i32,
+            sin_port: local_port.to_be(),
+            sin_addr: nrfxlib_sys::nrf_in_addr { s_addr: 0 },
+        };
+
+        let result = unsafe {
+            nrfxlib_sys::nrf_bind(
+                socket.0,
+                &address as *const _ as *const _,
+                address.sin_len as u32,
+            )
+        };
+
+        Self::check("bind", result).map(|_| ())
+    }
+
+    fn listen(&mut self, socket: SocketHandle, backlog: usize) -> Result<(), Error> {
+        let result = unsafe { nrfxlib_sys::nrf_listen(socket.0, backlog as i32) };
+        Self::check("listen", result)?;
+
+        // Accept can't be told not to wait, so the socket itself is made non-blocking
+        Self::set_nonblocking(socket, true)
+    }
+
+    fn accept(
+        &mut self,
+        socket: SocketHandle,
+    ) -> Result<Option<(SocketHandle, SocketAddr)>, Error> {
+        let mut address = core::mem::MaybeUninit::<SocketAddrStorage>::zeroed();
+        let mut length = core::mem::size_of::<SocketAddrStorage>() as u32;
+
+        let result = unsafe {
+            nrfxlib_sys::nrf_accept(
+                socket.0,
+                address.as_mut_ptr() as *mut _,
+                &mut length as *mut _,
+            )
+        };
+
+        match Self::check_nonblocking("accept", result)? {
+            None => Ok(None),
+            Some(fd) => {
+                let connection = SocketHandle(fd as i32);
+
+                // Safety: The storage was zeroed, which is a valid address, and filled in by the modem library
+                let address = unsafe { address.assume_init() };
+                match Self::read_address(&address) {
+                    Ok(remote) => Ok(Some((connection, remote))),
+                    Err(e) => {
+                        self.close(connection)?;
+                        Err(e)
+                    }
+                }
+            }
+        }
+    }
+
+    fn send(&mut self, socket: SocketHandle, buffer: &[u8]) -> Result<Option<usize>, Error> {
+        let result = unsafe {
+            nrfxlib_sys::nrf_send(
+                socket.0,
+                buffer.as_ptr() as *const _,
+                buffer.len() as u32,
+                nrfxlib_sys::NRF_MSG_DONTWAIT as i32,
+            )
+        };
+
+        Self::check_nonblocking("send", result)
+    }
+
+    fn send_to(
+        &mut self,
+        socket: SocketHandle,
+        remote: SocketAddr,
+        buffer: &[u8],
+    ) -> Result<Option<usize>, Error> {
+        let result = Self::with_address(remote, |address, length| unsafe {
+            nrfxlib_sys::nrf_sendto(
+                socket.0,
+                buffer.as_ptr() as *const _,
+                buffer.len() as u32,
+                nrfxlib_sys::NRF_MSG_DONTWAIT as i32,
+                address as *const _,
+                length,
+            )
+        });
+
+        Self::check_nonblocking("sendto", result)
+    }
+
+    fn write(&mut self, socket: SocketHandle, buffer: &[u8]) -> Result<usize, Error> {
+        let result = unsafe {
+            nrfxlib_sys::nrf_write(socket.0, buffer.as_ptr() as *const _, buffer.len() as u32)
+        };
+
+        Self::check("write", result).map(|length| length as usize)
+    }
+
+    fn receive(&mut self, socket: SocketHandle, buffer: &mut [u8]) -> Result<Option<usize>, Error> {
+        let result = unsafe {
+            nrfxlib_sys::nrf_recv(
+                socket.0,
+                buffer.as_mut_ptr() as *mut _,
+                buffer.len() as u32,
+                nrfxlib_sys::NRF_MSG_DONTWAIT as i32,
+            )
+        };
+
+        Self::check_nonblocking("recv", result)
+    }
+
+    fn receive_from(
+        &mut self,
+        socket: SocketHandle,
+        buffer: &mut [u8],
+    ) -> Result<Option<(usize, Option<SocketAddr>)>, Error> {
+        let mut address = core::mem::MaybeUninit::<SocketAddrStorage>::zeroed();
+        let mut address_length = core::mem::size_of::<SocketAddrStorage>() as u32;
+
+        let result = unsafe {
+            nrfxlib_sys::nrf_recvfrom(
+                socket.0,
+                buffer.as_mut_ptr() as *mut _,
+                buffer.len() as u32,
+                nrfxlib_sys::NRF_MSG_DONTWAIT as i32,
+                address.as_mut_ptr() as *mut _,
+                &mut address_length as *mut _,
+            )
+        };
+
+        let Some(length) = Self::check_nonblocking("recvfrom", result)? else {
+            return Ok(None);
+        };
+
+        // Safety: The storage was zeroed, which is a valid address, and may have been filled in by the modem library
+        let address = unsafe { address.assume_init() };
+        // Not every socket reports the sender, e.g. DTLS sockets
+        let sender = match address_length {
+            0 => None,
+            _ => Self::read_address(&address).ok(),
+        };
+
+        Ok(Some((length, sender)))
+    }
+
+    fn close(&mut self, socket: SocketHandle) -> Result<(), Error> {
+        let result = unsafe { nrfxlib_sys::nrf_close(socket.0) };
+        Self::check("close", result).map(|_| ())
+    }
+
+    fn get_addr_info<F>(
+        &mut self,
+        hostname: &str,
+        addr_type: AddrType,
+        mut callback: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(IpAddr),
+    {
+        use core::str::FromStr;
+        use embedded_nal::{Ipv4Addr, Ipv6Addr};
+
+        let target_families: &[u32] = match addr_type {
+            AddrType::IPv4 => &[nrfxlib_sys::NRF_AF_INET],
+            AddrType::IPv6 => &[nrfxlib_sys::NRF_AF_INET6],
+            // The modem library can't look up both families at once
+            AddrType::Either => &[nrfxlib_sys::NRF_AF_INET, nrfxlib_sys::NRF_AF_INET6],
+        };
+
+        // A hostname should at most be 256 chars, but we have a null char as well, so we add one
+        let mut hostname =
+            heapless::String::<257>::from_str(hostname).map_err(|_| Error::HostnameTooLong)?;
+        hostname.push('\0').map_err(|_| Error::HostnameTooLong)?;
+
+        let mut first_error = None;
+        let mut any_found = false;
+
+        for &target_family in target_families {
+            unsafe {
+                let hints = nrfxlib_sys::nrf_addrinfo {
+                    ai_family: target_family as _,
+                    ai_socktype: nrfxlib_sys::NRF_SOCK_STREAM as _,
+
+                    ai_flags: 0,
+                    ai_protocol: 0,
+                    ai_addrlen: 0,
+                    ai_addr: core::ptr::null_mut(),
+                    ai_canonname: core::ptr::null_mut(),
+                    ai_next: core::ptr::null_mut(),
+                };
+
+                let mut result: *mut nrfxlib_sys::nrf_addrinfo = core::ptr::null_mut();
+
+                let err = nrfxlib_sys::nrf_getaddrinfo(
+                    hostname.as_ptr(),
+                    core::ptr::null(),
+                    &hints as *const _,
+                    &mut result as *mut *mut _,
+                );
+
+                // The errno is returned instead of set
+                if err != 0 {
+                    first_error.get_or_insert(Error::NrfSys(Errno::from_code(err)));
+                    continue;
+                }
+                any_found = true;
+
+                let mut result_iter = result;
+
+                while !result_iter.is_null() {
+                    let address = (*result_iter).ai_addr;
+
+                    if (*address).sa_family == nrfxlib_sys::NRF_AF_INET as i32 {
+                        let dns_addr: &nrfxlib_sys::nrf_sockaddr_in =
+                            &*(address as *const nrfxlib_sys::nrf_sockaddr_in);
+
+                        callback(IpAddr::V4(Ipv4Addr::from(
+                            dns_addr.sin_addr.s_addr.to_ne_bytes(),
+                        )));
+                    } else if (*address).sa_family == nrfxlib_sys::NRF_AF_INET6 as i32 {
+                        let dns_addr: &nrfxlib_sys::nrf_sockaddr_in6 =
+                            &*(address as *const nrfxlib_sys::nrf_sockaddr_in6);
+
+                        callback(IpAddr::V6(Ipv6Addr::from(dns_addr.sin6_addr.s6_addr)));
+                    }
+
+                    result_iter = (*result_iter).ai_next;
+                }
+
+                if !result.is_null() {
+                    nrfxlib_sys::nrf_freeaddrinfo(result);
+                }
+            }
+        }
+
+        match first_error {
+            // Only fail if none of the families could be looked up
+            Some(e) if !any_found => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn bind_to_pdn(&mut self, socket: SocketHandle, apn: &str) -> Result<(), Error> {
+        Self::set_option_slice(
+            socket,
+            nrfxlib_sys::NRF_SOL_SOCKET,
+            nrfxlib_sys::NRF_SO_BINDTODEVICE,
+            apn.as_bytes(),
+        )
+    }
+
+    fn tls_configure(&mut self, socket: SocketHandle, options: &TlsOptions) -> Result<(), Error> {
+        Self::set_security_options(socket, options)
+    }
+
+    fn dtls_configure(&mut self, socket: SocketHandle, options: &DtlsOptions) -> Result<(), Error> {
+        Self::set_security_options(socket, &options.security)
+    }
+
+    fn gnss_start(&mut self, socket: SocketHandle, options: &GnssOptions) -> Result<(), Error> {
+        Self::set_option(
+            socket,
+            nrfxlib_sys::NRF_SOL_GNSS,
+            nrfxlib_sys::NRF_SO_GNSS_FIX_INTERVAL,
+            &options.fix_interval,
+        )?;
+        Self::set_option(
+            socket,
+            nrfxlib_sys::NRF_SOL_GNSS,
+            nrfxlib_sys::NRF_SO_GNSS_FIX_RETRY,
+            &options.fix_retry,
+        )?;
+        Self::set_option(
+            socket,
+            nrfxlib_sys::NRF_SOL_GNSS,
+            nrfxlib_sys::NRF_SO_GNSS_NMEA_MASK,
+            &options.nmea_mask.as_u16(),
+        )?;
+        Self::set_option(
+            socket,
+            nrfxlib_sys::NRF_SOL_GNSS,
+            nrfxlib_sys::NRF_SO_GNSS_START,
+            &options.delete_mask.as_u32(),
+        )
+    }
+
+    fn gnss_receive(&mut self, socket: SocketHandle) -> Result<Option<GnssData>, Error> {
+        use crate::gnss::{AgpsRequest, GnssDateTime, GnssPosition, GnssSatellite};
+
+        let mut frame = core::mem::MaybeUninit::<nrfxlib_sys::nrf_gnss_data_frame_t>::uninit();
+        let result = unsafe {
+            nrfxlib_sys::nrf_recv(
+                socket.0,
+                frame.as_mut_ptr() as *mut _,
+                core::mem::size_of::<nrfxlib_sys::nrf_gnss_data_frame_t>() as u32,
+                nrfxlib_sys::NRF_MSG_DONTWAIT as i32,
+            )
+        };
+
+        match Self::check_nonblocking("gnss_recv", result)? {
+            None | Some(0) => return Ok(None),
+            Some(_) => {}
+        }
+
+        // Safety: The modem library has filled in the frame
+        let frame = unsafe { frame.assume_init() };
+
+        let data = match frame.data_id as u32 {
+            nrfxlib_sys::NRF_GNSS_PVT_DATA_ID => {
+                let pvt = unsafe { frame.__bindgen_anon_1.pvt };
+                let mut sv = [GnssSatellite::default(); 12];
+                for (sv, pvt_sv) in sv.iter_mut().zip(pvt.sv.iter()) {
+                    *sv = GnssSatellite {
+                        sv: pvt_sv.sv,
+                        signal: pvt_sv.signal,
+                        cn0: pvt_sv.cn0,
+                        elevation: pvt_sv.elevation,
+                        azimuth: pvt_sv.azimuth,
+                        flags: pvt_sv.flags,
+                    };
+                }
+
+                GnssData::Position(GnssPosition {
+                    latitude: pvt.latitude,
+                    longitude: pvt.longitude,
+                    altitude: pvt.altitude,
+                    accuracy: pvt.accuracy,
+                    speed: pvt.speed,
+                    heading: pvt.heading,
+                    datetime: GnssDateTime {
+                        year: pvt.datetime.year,
+                        month: pvt.datetime.month,
+                        day: pvt.datetime.day,
+                        hour: pvt.datetime.hour,
+                        minute: pvt.datetime.minute,
+                        seconds: pvt.datetime.seconds,
+                        ms: pvt.datetime.ms,
+                    },
+                    pdop: pvt.pdop,
+                    hdop: pvt.hdop,
+                    vdop: pvt.vdop,
+                    tdop: pvt.tdop,
+                    flags: pvt.flags,
+                    sv,
+                })
+            }
+            nrfxlib_sys::NRF_GNSS_NMEA_DATA_ID => {
+                let nmea = unsafe { frame.__bindgen_anon_1.nmea };
+                let mut buffer = [0; 83];
+                let mut length = 0;
+                for (target, source) in buffer.iter_mut().zip(nmea.iter()) {
+                    if *source == 0 {
+                        break;
+                    }
+                    *target = *source;
+                    length += 1;
+                }
+
+                GnssData::Nmea { buffer, length }
+            }
+            nrfxlib_sys::NRF_GNSS_AGPS_DATA_ID => {
+                let agps = unsafe { frame.__bindgen_anon_1.agps };
+                GnssData::Agps(AgpsRequest {
+                    sv_mask_ephe: agps.sv_mask_ephe,
+                    sv_mask_alm: agps.sv_mask_alm,
+                    data_flags: agps.data_flags,
+                })
+            }
+            _ => return Err(nrfxlib::Error::BadDataFormat.into()),
+        };
+
+        Ok(Some(data))
+    }
+}
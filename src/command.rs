@@ -0,0 +1,238 @@
+//! Typed AT commands
+//!
+//! An [AtCommand] knows how to write itself and how to parse its response, so it can be tested against
+//! captured responses without a modem. [Modem::execute] sends it and parses the response.
+
+use crate::{
+    at::{split_final_result, AtSocket},
+    backend::ModemBackend,
+    error::{AtError, CmeError, Error},
+    lte::ClockTime,
+    registration::RegistrationStatus,
+    Modem, SystemMode,
+};
+use at_commands::builder::CommandBuilder;
+use embedded_nal::nb;
+
+/// The largest [AtCommand::COMMAND_SIZE] that can be executed
+pub const MAX_COMMAND_SIZE: usize = 256;
+/// The largest [AtCommand::RESPONSE_SIZE] that can be executed
+pub const MAX_RESPONSE_SIZE: usize = 1024;
+
+/// An AT command with a parsed response
+pub trait AtCommand {
+    type Response;
+
+    /// The size of the buffer the command is written into
+    const COMMAND_SIZE: usize = 64;
+    /// The size of the buffer the response is received into, including the final result code
+    const RESPONSE_SIZE: usize = 256;
+
+    /// Write the command into the buffer and return the part that was written
+    fn write<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b [u8], Error>;
+
+    /// Parse the response, which has every line except the final result code
+    fn parse(response: &str) -> Result<Self::Response, Error>;
+
+    /// Turn an error of the final result code, like `+CME ERROR: 518`, into a more specific error
+    fn map_error(error: Error) -> Error {
+        error
+    }
+}
+
+impl<B: ModemBackend> Modem<B> {
+    /// Execute the command on a new AT socket.
+    ///
+    /// This blocks until the modem has responded with `OK` or an error.
+    pub fn execute<C: AtCommand>(&mut self, command: &C) -> Result<C::Response, Error> {
+        let mut socket = self.at_socket()?;
+
+        let result = self
+            .at_connect(&mut socket)
+            .and_then(|_| self.execute_on(&mut socket, command));
+
+        self.at_close(socket)?;
+
+        result
+    }
+
+    /// Execute the command on an existing AT socket
+    pub fn execute_on<C: AtCommand>(
+        &mut self,
+        socket: &mut AtSocket,
+        command: &C,
+    ) -> Result<C::Response, Error> {
+        let mut command_buffer = [0; MAX_COMMAND_SIZE];
+        let command_buffer = command_buffer
+            .get_mut(..C::COMMAND_SIZE)
+            .ok_or(Error::BufferTooSmall(None))?;
+        let mut response_buffer = [0; MAX_RESPONSE_SIZE];
+        let response_buffer = response_buffer
+            .get_mut(..C::RESPONSE_SIZE)
+            .ok_or(Error::BufferTooSmall(None))?;
+
+        let data = command.write(command_buffer)?;
+        self.at_send_raw(socket, data)?;
+
+        // The modem gives the response in one go
+        let length = nb::block!(self.at_receive(socket, response_buffer))?;
+
+        let response = core::str::from_utf8(&response_buffer[..length])
+            .map_err(|_| Error::UnexpectedAtResponse)?
+            .trim_end_matches('\0');
+
+        match split_final_result(response) {
+            Some(Ok(response)) => C::parse(response),
+            Some(Err(e)) => Err(C::map_error(e)),
+            None if length == response_buffer.len() => Err(Error::BufferTooSmall(None)),
+            None => Err(Error::NoAtResponse),
+        }
+    }
+}
+
+/// Write a command that is a fixed string
+fn write_str<'b>(command: &str, buffer: &'b mut [u8]) -> Result<&'b [u8], Error> {
+    let buffer = buffer
+        .get_mut(..command.len())
+        .ok_or(Error::BufferTooSmall(None))?;
+    buffer.copy_from_slice(command.as_bytes());
+    Ok(buffer)
+}
+
+/// Parse the first line that starts with the prefix and parses.
+///
+/// Notifications with the same prefix can come in between, so a line that doesn't parse is skipped.
+fn parse_line<T>(
+    response: &str,
+    prefix: &str,
+    parse: impl Fn(&str) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let mut result = Err(Error::NoAtResponse);
+
+    for line in response.lines().map(str::trim) {
+        if line.starts_with(prefix) {
+            result = parse(line);
+            if result.is_ok() {
+                break;
+            }
+        }
+    }
+
+    result
+}
+
+/// `AT%XSYSTEMMODE`, which sets the [SystemMode]
+#[derive(Debug, Clone, Copy)]
+pub struct SetSystemMode(pub SystemMode);
+
+impl AtCommand for SetSystemMode {
+    type Response = ();
+
+    const COMMAND_SIZE: usize = 32;
+
+    fn write<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b [u8], Error> {
+        let mode = &self.0;
+        CommandBuilder::create_set(buffer, true)
+            .named("%XSYSTEMMODE")
+            .with_int_parameter(mode.lte_support as u8)
+            .with_int_parameter(mode.nbiot_support as u8)
+            .with_int_parameter(mode.gnss_support as u8)
+            .with_int_parameter(mode.preference as u8)
+            .finish()
+            .map_err(|e| Error::BufferTooSmall(Some(e)))
+    }
+
+    fn parse(_response: &str) -> Result<Self::Response, Error> {
+        Ok(())
+    }
+
+    fn map_error(error: Error) -> Error {
+        match error {
+            Error::AtError(AtError::CmeError(CmeError::NotAllowedInActiveState)) => {
+                Error::NotAllowedInActiveState
+            }
+            Error::AtError(AtError::CmeError(CmeError::InvalidBandConfiguration)) => {
+                Error::InvalidBandConfiguration
+            }
+            error => error,
+        }
+    }
+}
+
+/// `AT+CEREG?`, which reads the [RegistrationStatus].
+///
+/// The values that are reported depend on the mode that was set with `AT+CEREG=<n>`.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadRegistration;
+
+impl AtCommand for ReadRegistration {
+    type Response = RegistrationStatus;
+
+    fn write<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b [u8], Error> {
+        write_str("AT+CEREG?", buffer)
+    }
+
+    fn parse(response: &str) -> Result<Self::Response, Error> {
+        parse_line(response, "+CEREG:", RegistrationStatus::parse_read_response)
+    }
+}
+
+/// `AT+CEREG?`, which reads the `<n>` mode of the `+CEREG` notifications
+#[derive(Debug, Clone, Copy)]
+pub struct ReadRegistrationMode;
+
+impl AtCommand for ReadRegistrationMode {
+    type Response = u8;
+
+    fn write<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b [u8], Error> {
+        write_str("AT+CEREG?", buffer)
+    }
+
+    fn parse(response: &str) -> Result<Self::Response, Error> {
+        parse_line(response, "+CEREG:", |line| {
+            let (mode, _) = line
+                .strip_prefix("+CEREG:")
+                .and_then(|parameters| parameters.split_once(','))
+                .ok_or(Error::UnexpectedAtResponse)?;
+            mode.trim().parse().map_err(|_| Error::UnexpectedAtResponse)
+        })
+    }
+}
+
+/// `AT+CEREG=<n>`, which sets the mode of the `+CEREG` notifications. `0` turns them off.
+#[derive(Debug, Clone, Copy)]
+pub struct SetRegistrationMode(pub u8);
+
+impl AtCommand for SetRegistrationMode {
+    type Response = ();
+
+    const COMMAND_SIZE: usize = 16;
+
+    fn write<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b [u8], Error> {
+        CommandBuilder::create_set(buffer, true)
+            .named("+CEREG")
+            .with_int_parameter(self.0)
+            .finish()
+            .map_err(|e| Error::BufferTooSmall(Some(e)))
+    }
+
+    fn parse(_response: &str) -> Result<Self::Response, Error> {
+        Ok(())
+    }
+}
+
+/// `AT+CCLK?`, which reads the [ClockTime] of the modem
+#[derive(Debug, Clone, Copy)]
+pub struct ReadClock;
+
+impl AtCommand for ReadClock {
+    type Response = ClockTime;
+
+    fn write<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b [u8], Error> {
+        write_str("AT+CCLK?", buffer)
+    }
+
+    fn parse(response: &str) -> Result<Self::Response, Error> {
+        parse_line(response, "+CCLK:", str::parse)
+    }
+}
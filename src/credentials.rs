@@ -0,0 +1,239 @@
+//! Managing the credentials that are stored in the modem, using `AT%CMNG`
+//!
+//! Credentials are stored under a security tag, which is what TLS and DTLS sockets refer to.
+//! The modem only allows changing them while LTE and GNSS are off, so these functions fail with
+//! [Error::NotAllowedInActiveState] while there are LTE or GNSS sockets.
+
+use crate::{backend::ModemBackend, error::Error, log, Modem};
+use at_commands::{builder::CommandBuilder, parser::CommandParser};
+
+/// The kinds of credentials that can be stored under a security tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialType {
+    /// A root CA certificate in PEM format
+    RootCaCertificate = 0,
+    /// A client certificate in PEM format
+    ClientCertificate = 1,
+    /// A client private key in PEM format
+    ClientPrivateKey = 2,
+    /// A pre-shared key as a hexadecimal string
+    PresharedKey = 3,
+    /// The identity that goes with the pre-shared key
+    PskIdentity = 4,
+    /// A public key in PEM format
+    PublicKey = 5,
+}
+
+impl CredentialType {
+    fn from_int(value: i32) -> Result<Self, Error> {
+        match value {
+            0 => Ok(Self::RootCaCertificate),
+            1 => Ok(Self::ClientCertificate),
+            2 => Ok(Self::ClientPrivateKey),
+            3 => Ok(Self::PresharedKey),
+            4 => Ok(Self::PskIdentity),
+            5 => Ok(Self::PublicKey),
+            _ => Err(Error::UnexpectedAtResponse),
+        }
+    }
+}
+
+/// A credential that is stored in the modem, as listed by [Modem::list_credentials]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CredentialInfo {
+    pub sec_tag: u32,
+    pub credential_type: CredentialType,
+    /// The SHA-256 hash of the credential, if the modem reports it
+    pub sha256: Option<[u8; 32]>,
+}
+
+impl CredentialInfo {
+    /// Parse a line like `%CMNG: 42,0,"2C43952EE9E000FF2ACC4E2ED0897C0A72AD5FA72C3D934E81741CBD54F05BD1"`
+    fn parse(line: &str) -> Result<Self, Error> {
+        let (sec_tag, credential_type, sha256) = CommandParser::parse(line.trim().as_bytes())
+            .expect_identifier(b"%CMNG:")
+            .expect_int_parameter()
+            .expect_int_parameter()
+            .expect_optional_string_parameter()
+            .finish()?;
+
+        Ok(Self {
+            sec_tag: u32::try_from(sec_tag).map_err(|_| Error::UnexpectedAtResponse)?,
+            credential_type: CredentialType::from_int(credential_type)?,
+            sha256: sha256
+                .filter(|sha256| !sha256.is_empty())
+                .map(parse_sha256)
+                .transpose()?,
+        })
+    }
+}
+
+/// Parse a SHA-256 hash in hexadecimal
+fn parse_sha256(hex: &str) -> Result<[u8; 32], Error> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(Error::UnexpectedAtResponse);
+    }
+
+    let mut hash = [0; 32];
+    for (byte, digits) in hash.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let digits = core::str::from_utf8(digits).map_err(|_| Error::UnexpectedAtResponse)?;
+        *byte = u8::from_str_radix(digits, 16).map_err(|_| Error::UnexpectedAtResponse)?;
+    }
+
+    Ok(hash)
+}
+
+/// The modem accepts security tags up to `i32::MAX`
+fn sec_tag_parameter(sec_tag: u32) -> Result<i32, Error> {
+    i32::try_from(sec_tag).map_err(|_| Error::InvalidConfiguration)
+}
+
+impl<B: ModemBackend> Modem<B> {
+    /// Returns an error if LTE or GNSS is active, because the credentials can't be managed then
+    fn check_credentials_allowed(&self) -> Result<(), Error> {
+        if self.state.lte_users() > 0 || self.state.active_gnss_sockets > 0 {
+            return Err(Error::NotAllowedInActiveState);
+        }
+
+        Ok(())
+    }
+
+    /// Store a credential under the security tag, replacing the one that was there.
+    ///
+    /// The content is sent as it is, so PEM files can be given including their line breaks.
+    /// The AT command is built in the buffer, so it must be a bit bigger than the content.
+    pub fn write_credential(
+        &mut self,
+        sec_tag: u32,
+        credential_type: CredentialType,
+        content: &str,
+        buffer: &mut [u8],
+    ) -> Result<(), Error> {
+        log::debug!(
+            "Writing credential {:?} of tag {}",
+            credential_type,
+            sec_tag
+        );
+        self.check_credentials_allowed()?;
+
+        // The content is quoted, so it can't contain quotes itself
+        if content.contains('"') {
+            return Err(Error::InvalidConfiguration);
+        }
+
+        let command = CommandBuilder::create_set(buffer, true)
+            .named("%CMNG")
+            .with_int_parameter(0)
+            .with_int_parameter(sec_tag_parameter(sec_tag)?)
+            .with_int_parameter(credential_type as i32)
+            .with_string_parameter(content)
+            .finish()
+            .map_err(|e| Error::BufferTooSmall(Some(e)))?;
+
+        self.send_at_command(command, |_| {})
+    }
+
+    /// Read a credential from the modem into the buffer. The content is returned without the quotes.
+    ///
+    /// The modem does not allow reading private keys.
+    pub fn read_credential<'b>(
+        &mut self,
+        sec_tag: u32,
+        credential_type: CredentialType,
+        buffer: &'b mut [u8],
+    ) -> Result<&'b str, Error> {
+        log::debug!(
+            "Reading credential {:?} of tag {}",
+            credential_type,
+            sec_tag
+        );
+        self.check_credentials_allowed()?;
+
+        let mut command_buffer = [0; 32];
+        let command = CommandBuilder::create_set(&mut command_buffer, true)
+            .named("%CMNG")
+            .with_int_parameter(2)
+            .with_int_parameter(sec_tag_parameter(sec_tag)?)
+            .with_int_parameter(credential_type as i32)
+            .finish()
+            .map_err(|e| Error::BufferTooSmall(Some(e)))?;
+
+        let response = self.send_at_command_buffered(command, buffer)?;
+
+        // %CMNG: <tag>,<type>,"<sha256>","<content>"
+        // The content spans multiple lines, so it's taken from between its quotes
+        let (_, parameters) = response
+            .split_once("%CMNG:")
+            .ok_or(Error::UnexpectedAtResponse)?;
+        let content = parameters
+            .splitn(4, ',')
+            .nth(3)
+            .and_then(|content| content.trim_start().strip_prefix('"'))
+            .ok_or(Error::UnexpectedAtResponse)?;
+        let end = content.find('"').ok_or(Error::UnexpectedAtResponse)?;
+
+        Ok(&content[..end])
+    }
+
+    /// Call the callback for every credential that is stored in the modem,
+    /// or only for the ones of the security tag if one is given.
+    ///
+    /// The whole response is received in the buffer, which needs about 90 bytes per credential.
+    pub fn list_credentials<F>(
+        &mut self,
+        sec_tag: Option<u32>,
+        buffer: &mut [u8],
+        mut callback: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(CredentialInfo),
+    {
+        log::debug!("Listing credentials");
+        self.check_credentials_allowed()?;
+
+        let mut command_buffer = [0; 32];
+        let builder = CommandBuilder::create_set(&mut command_buffer, true)
+            .named("%CMNG")
+            .with_int_parameter(1);
+        let builder = match sec_tag {
+            Some(sec_tag) => builder.with_int_parameter(sec_tag_parameter(sec_tag)?),
+            None => builder,
+        };
+        let command = builder
+            .finish()
+            .map_err(|e| Error::BufferTooSmall(Some(e)))?;
+
+        let response = self.send_at_command_buffered(command, buffer)?;
+
+        for line in response.lines().filter(|line| line.starts_with("%CMNG:")) {
+            callback(CredentialInfo::parse(line)?);
+        }
+
+        Ok(())
+    }
+
+    /// Delete a credential from the modem
+    pub fn delete_credential(
+        &mut self,
+        sec_tag: u32,
+        credential_type: CredentialType,
+    ) -> Result<(), Error> {
+        log::debug!(
+            "Deleting credential {:?} of tag {}",
+            credential_type,
+            sec_tag
+        );
+        self.check_credentials_allowed()?;
+
+        let mut buffer = [0; 32];
+        let command = CommandBuilder::create_set(&mut buffer, true)
+            .named("%CMNG")
+            .with_int_parameter(3)
+            .with_int_parameter(sec_tag_parameter(sec_tag)?)
+            .with_int_parameter(credential_type as i32)
+            .finish()
+            .map_err(|e| Error::BufferTooSmall(Some(e)))?;
+
+        self.send_at_command(command, |_| {})
+    }
+}
@@ -1,110 +1,969 @@
-use crate::{log, to_nb_result, Modem};
-use core::str::FromStr;
-use embedded_nal::{Ipv4Addr, Ipv6Addr};
+//! Resolving hostnames and addresses
+//!
+//! Hostnames are resolved by the modem, see [Modem::get_hosts_by_name]. The modem library can't do reverse lookups,
+//! so those are done with a PTR query over UDP to the DNS server the network has given.
+//! With our own DNS servers, see [Modem::set_dns_servers], hostnames are resolved with queries over UDP as well.
+//!
+//! Resolved hostnames can be cached, so a device that wakes up from PSM doesn't need the network
+//! to resolve the same hostnames again, see [Modem::set_dns_cache].
 
-impl embedded_nal::Dns for Modem {
-    type Error = crate::Error;
+use crate::{
+    backend::{ModemBackend, SocketHandle, SocketKind},
+    error::{Errno, Error},
+    log, to_nb_result, Modem,
+};
+use core::{fmt::Write, time::Duration};
+use embedded_nal::{nb, AddrType, IpAddr, SocketAddr};
 
-    fn get_host_by_name(
+const DNS_PORT: u16 = 53;
+const DNS_HEADER_LENGTH: usize = 12;
+/// The largest DNS message over UDP
+const DNS_MESSAGE_SIZE: usize = 512;
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+const RCODE_NAME_ERROR: u8 = 3;
+/// Limits how often a compressed name may jump, so a malicious response can't make us loop
+const MAX_NAME_POINTERS: usize = 16;
+/// The number of addresses per family that are kept of the answer of a DNS server
+const MAX_ANSWER_ADDRESSES: usize = 8;
+
+type Query = heapless::Vec<u8, DNS_MESSAGE_SIZE>;
+
+/// The number of hostnames the DNS cache holds. When it's full, the entry that expires first is replaced.
+pub const DNS_CACHE_CAPACITY: usize = 4;
+/// The number of addresses the DNS cache holds per hostname
+pub const DNS_CACHE_ADDRESSES: usize = 4;
+/// Longer hostnames are not cached
+pub const DNS_CACHE_MAX_HOSTNAME_LENGTH: usize = 64;
+
+/// The settings of the DNS cache
+#[derive(Debug, Clone, Copy)]
+pub struct DnsCacheConfig {
+    /// Returns the current time, counted from any fixed point like the boot of the device.
+    /// It must keep counting while the device sleeps.
+    pub clock: fn() -> Duration,
+    /// How long the addresses of a hostname are used.
+    /// The modem doesn't report the TTL of the DNS records, so it's the same for all hostnames.
+    pub ttl: Duration,
+    /// How long it's remembered that a hostname has no addresses
+    pub negative_ttl: Duration,
+}
+
+#[derive(Debug)]
+struct DnsCache {
+    config: DnsCacheConfig,
+    entries: heapless::Vec<DnsCacheEntry, DNS_CACHE_CAPACITY>,
+}
+
+#[derive(Debug)]
+struct DnsCacheEntry {
+    hostname: heapless::String<DNS_CACHE_MAX_HOSTNAME_LENGTH>,
+    addr_type: AddrType,
+    /// Empty if the hostname has no addresses
+    addresses: heapless::Vec<IpAddr, DNS_CACHE_ADDRESSES>,
+    /// `false` if the hostname has more addresses than are stored
+    complete: bool,
+    expires: Duration,
+}
+
+impl DnsCache {
+    /// Get the addresses of the hostname, if at least `count` of them are cached and they haven't expired
+    fn get(&mut self, hostname: &str, addr_type: &AddrType, count: usize) -> Option<&[IpAddr]> {
+        let now = (self.config.clock)();
+        self.entries.retain(|entry| entry.expires > now);
+
+        self.entries
+            .iter()
+            .find(|entry| {
+                entry.hostname.eq_ignore_ascii_case(hostname)
+                    && entry.addr_type == *addr_type
+                    && (entry.complete || entry.addresses.len() >= count)
+            })
+            .map(|entry| &entry.addresses[..])
+    }
+
+    fn insert(
         &mut self,
         hostname: &str,
-        addr_type: embedded_nal::AddrType,
-    ) -> embedded_nal::nb::Result<embedded_nal::IpAddr, Self::Error> {
+        addr_type: AddrType,
+        addresses: &[IpAddr],
+        complete: bool,
+    ) {
+        let mut entry = DnsCacheEntry {
+            hostname: heapless::String::new(),
+            addr_type,
+            addresses: heapless::Vec::new(),
+            complete: complete && addresses.len() <= DNS_CACHE_ADDRESSES,
+            expires: (self.config.clock)(),
+        };
+
+        if entry.hostname.push_str(hostname).is_err() {
+            return;
+        }
+
+        let length = addresses.len().min(DNS_CACHE_ADDRESSES);
+        // Can't fail, because it fits
+        let _ = entry.addresses.extend_from_slice(&addresses[..length]);
+        entry.expires += if addresses.is_empty() {
+            self.config.negative_ttl
+        } else {
+            self.config.ttl
+        };
+
+        self.entries.retain(|cached| {
+            !cached.hostname.eq_ignore_ascii_case(&entry.hostname)
+                || cached.addr_type != entry.addr_type
+        });
+
+        if self.entries.is_full() {
+            if let Some(index) = self
+                .entries
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, cached)| cached.expires)
+                .map(|(index, _)| index)
+            {
+                self.entries.swap_remove(index);
+            }
+        }
+
+        // Can't fail, because there's room now
+        let _ = self.entries.push(entry);
+    }
+}
+
+/// A primary and secondary DNS server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DnsServers {
+    pub primary: Option<IpAddr>,
+    pub secondary: Option<IpAddr>,
+}
+
+impl DnsServers {
+    fn iter(&self) -> impl Iterator<Item = IpAddr> {
+        self.primary.into_iter().chain(self.secondary)
+    }
+}
+
+/// The DNS state of the modem
+#[derive(Debug, Default)]
+pub(crate) struct DnsState {
+    servers: DnsServers,
+    cache: Option<DnsCache>,
+    lookup: Option<DnsLookup>,
+    random: Option<fn() -> u32>,
+    next_query_id: u16,
+}
+
+impl DnsState {
+    /// The id of the next lookup, which is hard to guess so a forged answer isn't taken for the real one
+    fn next_query_id(&mut self) -> u16 {
+        let counter = self.next_query_id;
+        // Every family has its own query
+        self.next_query_id = counter.wrapping_add(2);
+
+        match (self.random, &self.cache) {
+            (Some(random), _) => random() as u16,
+            // Without a random source, the time is at least not known from outside
+            (None, Some(cache)) => counter ^ (cache.config.clock)().subsec_micros() as u16,
+            (None, None) => counter,
+        }
+    }
+}
+
+/// What a lookup over UDP asks the DNS servers
+#[derive(Debug, PartialEq)]
+// There's only one lookup at a time, so the size doesn't matter
+#[allow(clippy::large_enum_variant)]
+enum Question {
+    /// The PTR record of the address
+    Address(IpAddr),
+    /// The A or AAAA records of the hostname, with a query per family in this order
+    Name {
+        hostname: heapless::String<256>,
+        families: heapless::Vec<AddrType, 2>,
+    },
+}
+
+/// The addresses a DNS server answered with for one family
+#[derive(Debug)]
+struct AddressAnswer {
+    addresses: heapless::Vec<IpAddr, MAX_ANSWER_ADDRESSES>,
+    /// `false` if there were more addresses than fit
+    complete: bool,
+}
+
+/// The answer to a [Question]
+#[derive(Debug)]
+enum LookupResult {
+    Name(heapless::String<256>),
+    /// The answers in the order of the families of the question
+    Addresses(heapless::Vec<Result<AddressAnswer, Error>, 2>),
+}
+
+/// A lookup over UDP that is in progress
+#[derive(Debug)]
+struct DnsLookup {
+    question: Question,
+    socket: SocketHandle,
+    /// The id of the first query. The query for the second family has the next id.
+    query_id: u16,
+    /// The servers the queries have been sent to, empty until they're sent
+    servers: heapless::Vec<IpAddr, 2>,
+    /// The answers that have come in for the families of a [Question::Name]
+    answers: [Option<Result<AddressAnswer, Error>>; 2],
+}
+
+impl DnsLookup {
+    fn query_count(&self) -> usize {
+        match &self.question {
+            Question::Address(_) => 1,
+            Question::Name { families, .. } => families.len(),
+        }
+    }
+
+    fn write_query(&self, index: usize, query: &mut Query) -> Result<(), Error> {
+        query.clear();
+
+        match &self.question {
+            Question::Address(address) => write_ptr_query(self.query_id, *address, query),
+            Question::Name { hostname, families } => write_address_query(
+                self.query_id.wrapping_add(index as u16),
+                hostname,
+                &families[index],
+                query,
+            ),
+        }
+    }
+
+    /// Handle a response of one of the servers. Returns the result once all queries have been answered.
+    fn handle_response(&mut self, response: &[u8]) -> Result<Option<LookupResult>, Error> {
+        let families = match &self.question {
+            Question::Address(_) => {
+                return parse_ptr_response(self.query_id, response)
+                    .map(|name| name.map(LookupResult::Name))
+            }
+            Question::Name { families, .. } => families,
+        };
+
+        // The first answer of either server is used
+        for (index, family) in families.iter().enumerate() {
+            if self.answers[index].is_none() {
+                let query_id = self.query_id.wrapping_add(index as u16);
+                self.answers[index] =
+                    parse_address_response(query_id, response, family).transpose();
+            }
+        }
+
+        let answers = &mut self.answers[..families.len()];
+        if answers.iter().any(Option::is_none) {
+            return Ok(None);
+        }
+
+        Ok(Some(LookupResult::Addresses(
+            answers.iter_mut().filter_map(Option::take).collect(),
+        )))
+    }
+}
+
+impl<B: ModemBackend> Modem<B> {
+    /// Turn on the DNS cache with the settings, or turn it off with `None`.
+    ///
+    /// The cache is off by default. Changing the settings empties it.
+    pub fn set_dns_cache(&mut self, config: Option<DnsCacheConfig>) {
+        self.dns.cache = config.map(|config| DnsCache {
+            config,
+            entries: heapless::Vec::new(),
+        });
+    }
+
+    /// Set the random source for the ids of the DNS queries over UDP, e.g. the RNG of the CryptoCell.
+    ///
+    /// The modem library has no random source of its own. Without it the ids are counted up,
+    /// mixed with the clock of the DNS cache if it's turned on.
+    pub fn set_dns_random(&mut self, random: Option<fn() -> u32>) {
+        self.dns.random = random;
+    }
+
+    /// Forget all cached hostnames, e.g. after the network changed
+    pub fn flush_dns_cache(&mut self) {
+        if let Some(cache) = self.dns.cache.as_mut() {
+            cache.entries.clear();
+        }
+    }
+
+    /// Resolve the hostname to all of its addresses, so a connection can fall back to the next
+    /// address when the first one is unreachable.
+    ///
+    /// [AddrType::Either] only looks up IPv4 addresses, because the sockets can't connect to IPv6 addresses yet.
+    /// Only the first `N` addresses are returned. Fails with [Error::AddressNotFound] if there are none.
+    ///
+    /// If the DNS cache is on, it's used instead of the network when it has the addresses.
+    /// With our own DNS servers, this keeps LTE on until they have answered, see [Modem::cancel_dns_lookup].
+    pub fn get_hosts_by_name<const N: usize>(
+        &mut self,
+        hostname: &str,
+        addr_type: AddrType,
+    ) -> nb::Result<heapless::Vec<IpAddr, N>, Error> {
         log::info!("Resolving dns hostname for \"{}\"", hostname);
 
+        let mut addresses = heapless::Vec::new();
+
         if let Ok(ip) = hostname.parse() {
-            return Ok(ip);
+            // Only fails if N is 0, and then there's nothing to return anyway
+            let _ = addresses.push(ip);
+            return Ok(addresses);
         }
 
         if !hostname.is_ascii() {
-            return to_nb_result(Err(Self::Error::HostnameNotAscii));
+            return to_nb_result(Err(Error::HostnameNotAscii));
         }
 
-        let target_family = match addr_type {
-            embedded_nal::AddrType::IPv4 => nrfxlib_sys::NRF_AF_INET,
-            embedded_nal::AddrType::IPv6 => nrfxlib_sys::NRF_AF_INET6,
-            embedded_nal::AddrType::Either => nrfxlib_sys::NRF_AF_INET,
-        };
+        if let Some(cached) = self
+            .dns
+            .cache
+            .as_mut()
+            .and_then(|cache| cache.get(hostname, &addr_type, N))
+        {
+            log::debug!("Using cached addresses {:?}", cached);
 
-        let mut found_ip = None;
+            if cached.is_empty() {
+                return to_nb_result(Err(Error::AddressNotFound));
+            }
+
+            return Ok(cached.iter().take(N).copied().collect());
+        }
+
+        let families = match addr_type {
+            AddrType::IPv4 => &[AddrType::IPv4][..],
+            AddrType::IPv6 => &[AddrType::IPv6][..],
+            // The sockets are IPv4 only, so IPv6 addresses would only be a fallback that always fails
+            AddrType::Either => &[AddrType::IPv4][..],
+        };
 
-        unsafe {
-            let hints = nrfxlib_sys::nrf_addrinfo {
-                ai_family: target_family as _,
-                ai_socktype: nrfxlib_sys::NRF_SOCK_STREAM as _,
+        let mut first_error = None;
+        let mut complete = true;
 
-                ai_flags: 0,
-                ai_protocol: 0,
-                ai_addrlen: 0,
-                ai_addr: core::ptr::null_mut(),
-                ai_canonname: core::ptr::null_mut(),
-                ai_next: core::ptr::null_mut(),
+        if self.dns.servers.iter().next().is_some() {
+            // The modem would only fall back to our own servers, so they're asked directly
+            let mut name = heapless::String::new();
+            name.push_str(hostname)
+                .map_err(|_| nb::Error::Other(Error::HostnameTooLong))?;
+            let question = Question::Name {
+                hostname: name,
+                families: families.iter().cloned().collect(),
+            };
+            let LookupResult::Addresses(answers) = self.lookup(question)? else {
+                unreachable!("A hostname is answered with addresses");
             };
 
-            let mut result: *mut nrfxlib_sys::nrf_addrinfo = core::ptr::null_mut();
+            // A family without addresses is not an error as long as the other one has some
+            for answer in answers {
+                match answer {
+                    Ok(answer) => {
+                        complete &= answer.complete;
+                        for ip in answer.addresses {
+                            if addresses.push(ip).is_err() {
+                                log::trace!("Ignoring address {:?}", ip);
+                                complete = false;
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        first_error.get_or_insert(e);
+                    }
+                }
+            }
+        } else {
+            for family in families {
+                if addresses.is_full() {
+                    complete = false;
+                    break;
+                }
+
+                // A family without addresses is not an error as long as the other one has some
+                if let Err(e) = self.backend.get_addr_info(hostname, family.clone(), |ip| {
+                    if addresses.push(ip).is_err() {
+                        log::trace!("Ignoring address {:?}", ip);
+                        complete = false;
+                    }
+                }) {
+                    first_error.get_or_insert(modem_lookup_error(e));
+                }
+            }
+        }
+
+        log::info!("{addresses:?}");
+
+        // Other errors are not cached, because they may be gone on the next try
+        if let (Some(cache), None | Some(Error::AddressNotFound)) =
+            (self.dns.cache.as_mut(), &first_error)
+        {
+            cache.insert(hostname, addr_type, &addresses, complete);
+        }
+
+        if !addresses.is_empty() {
+            return Ok(addresses);
+        }
+
+        to_nb_result(Err(first_error.unwrap_or(Error::AddressNotFound)))
+    }
+
+    /// Use our own DNS servers, e.g. the resolvers of a private APN. `None` goes back to the servers of the network.
+    ///
+    /// The modem library can't replace the servers the network has given, so hostnames are then resolved
+    /// with queries over UDP to the servers given here instead of by the modem, like reverse lookups.
+    /// Both servers are asked at once and the first answer is used.
+    ///
+    /// Only IPv4 servers can be used, because the sockets are IPv4 only.
+    pub fn set_dns_servers(
+        &mut self,
+        primary: Option<IpAddr>,
+        secondary: Option<IpAddr>,
+    ) -> Result<(), Error> {
+        log::debug!("Setting DNS servers {:?} and {:?}", primary, secondary);
+
+        if primary
+            .iter()
+            .chain(&secondary)
+            .any(|server| server.is_ipv6())
+        {
+            return Err(Error::InvalidConfiguration);
+        }
+
+        // A lookup in progress has asked the old servers
+        self.cancel_dns_lookup()?;
+        self.dns.servers = DnsServers { primary, secondary };
+        // Addresses from the old servers may not be valid for the new ones
+        self.flush_dns_cache();
+
+        Ok(())
+    }
 
-            // A hostname should at most be 256 chars, but we have a null char as well, so we add one
-            let mut hostname = heapless::String::<257>::from_str(hostname)
-                .map_err(|_| Self::Error::HostnameTooLong)?;
-            hostname
-                .push('\0')
-                .map_err(|_| Self::Error::HostnameTooLong)?;
+    /// The DNS servers that have been set with [Modem::set_dns_servers]
+    pub fn dns_servers(&self) -> DnsServers {
+        self.dns.servers
+    }
+
+    /// Read the DNS servers the network has given with the default PDN.
+    ///
+    /// The network only gives them once LTE is connected.
+    pub fn network_dns_servers(&mut self) -> Result<DnsServers, Error> {
+        let mut servers = None;
 
-            let err = nrfxlib_sys::nrf_getaddrinfo(
-                hostname.as_ptr(),
-                core::ptr::null(),
-                &hints as *const _,
-                &mut result as *mut *mut _,
-            );
+        // +CGCONTRDP: <cid>,<bearer_id>,<apn>,<local_addr>,<gw_addr>,<dns_prim_addr>,<dns_sec_addr>,...
+        self.send_at_command("AT+CGCONTRDP=0", |line| {
+            if let Some(parameters) = line.trim().strip_prefix("+CGCONTRDP:") {
+                if servers.is_none() {
+                    let mut parameters = parameters
+                        .split(',')
+                        .skip(5)
+                        .map(|address| address.trim().trim_matches('"').parse().ok());
 
-            if err != 0 {
-                return to_nb_result(Err(crate::Error::NrfSys(nrfxlib::get_last_error())));
+                    servers = Some(DnsServers {
+                        primary: parameters.next().flatten(),
+                        secondary: parameters.next().flatten(),
+                    });
+                }
             }
+        })?;
 
-            if result.is_null() {
-                return to_nb_result(Err(crate::Error::AddressNotFound));
+        servers.ok_or(Error::UnexpectedAtResponse)
+    }
+
+    /// Stop the lookup over UDP that is in progress, if any.
+    ///
+    /// Reverse lookups, and lookups with our own DNS servers, keep a socket open and LTE on until they have an answer,
+    /// so this should be called when giving up on them, e.g. after a timeout.
+    pub fn cancel_dns_lookup(&mut self) -> Result<(), Error> {
+        let Some(lookup) = self.dns.lookup.take() else {
+            return Ok(());
+        };
+
+        log::debug!("Ending lookup of {:?}", lookup.question);
+
+        let close_result = self.backend.close(lookup.socket);
+
+        let mut new_state = self.state.clone();
+        new_state.active_lte_sockets -= 1;
+        self.change_state(new_state)?;
+
+        close_result
+    }
+
+    /// Ask the DNS servers over UDP. A different question than the one in progress replaces it.
+    fn lookup(&mut self, question: Question) -> nb::Result<LookupResult, Error> {
+        match &self.dns.lookup {
+            Some(lookup) if lookup.question == question => {}
+            Some(_) => {
+                to_nb_result(self.cancel_dns_lookup())?;
+                to_nb_result(self.start_lookup(question))?;
             }
+            None => to_nb_result(self.start_lookup(question))?,
+        }
 
-            let mut result_iter = result;
+        match self.poll_lookup() {
+            Err(nb::Error::WouldBlock) => Err(nb::Error::WouldBlock),
+            result => {
+                to_nb_result(self.cancel_dns_lookup())?;
+                result
+            }
+        }
+    }
 
-            while !result_iter.is_null() && found_ip.is_none() {
-                let address = (*result_iter).ai_addr;
+    fn start_lookup(&mut self, question: Question) -> Result<(), Error> {
+        log::debug!("Starting lookup of {:?}", question);
 
-                if (*address).sa_family == nrfxlib_sys::NRF_AF_INET as i32 {
-                    let dns_addr: &nrfxlib_sys::nrf_sockaddr_in =
-                        &*(address as *const nrfxlib_sys::nrf_sockaddr_in);
+        let socket = self.backend.socket(SocketKind::Udp)?;
 
-                    found_ip = Some(embedded_nal::IpAddr::V4(Ipv4Addr::from(
-                        dns_addr.sin_addr.s_addr.to_ne_bytes(),
-                    )));
-                } else if (*address).sa_family == nrfxlib_sys::NRF_AF_INET6 as i32 {
-                    let dns_addr: &nrfxlib_sys::nrf_sockaddr_in6 =
-                        &*(address as *const nrfxlib_sys::nrf_sockaddr_in6);
+        let mut new_state = self.state.clone();
+        new_state.active_lte_sockets += 1;
+        if let Err(e) = self.change_state(new_state) {
+            self.backend.close(socket)?;
+            return Err(e);
+        }
 
-                    found_ip = Some(embedded_nal::IpAddr::V6(Ipv6Addr::from(
-                        dns_addr.sin6_addr.s6_addr,
-                    )));
-                }
+        let query_id = self.dns.next_query_id();
+
+        self.dns.lookup = Some(DnsLookup {
+            question,
+            socket,
+            query_id,
+            servers: heapless::Vec::new(),
+            answers: [None, None],
+        });
 
-                result_iter = (*result_iter).ai_next;
+        Ok(())
+    }
+
+    /// Send the queries once LTE is connected and wait for the answers
+    fn poll_lookup(&mut self) -> nb::Result<LookupResult, Error> {
+        let Some(lookup) = self.dns.lookup.as_ref() else {
+            return to_nb_result(Err(Error::SocketClosed));
+        };
+        let socket = lookup.socket;
+
+        if lookup.servers.is_empty() {
+            self.send_queries()?;
+        }
+
+        // Everything that has come in is handled, because there's no news from the modem for it anymore
+        let mut response = [0; DNS_MESSAGE_SIZE];
+        loop {
+            let (length, sender) =
+                match to_nb_result(self.backend.receive_from(socket, &mut response))? {
+                    Some(received) => received,
+                    None => return Err(nb::Error::WouldBlock),
+                };
+
+            let Some(lookup) = self.dns.lookup.as_mut() else {
+                return to_nb_result(Err(Error::SocketClosed));
+            };
+
+            if !sender.is_some_and(|sender| {
+                sender.port() == DNS_PORT && lookup.servers.contains(&sender.ip())
+            }) {
+                log::debug!("Ignoring DNS response from {:?}", sender);
+                continue;
+            }
+
+            // A response that doesn't answer all queries keeps us waiting
+            if let Some(result) = to_nb_result(lookup.handle_response(&response[..length]))? {
+                return Ok(result);
             }
+        }
+    }
 
-            nrfxlib_sys::nrf_freeaddrinfo(result);
+    fn send_queries(&mut self) -> nb::Result<(), Error> {
+        self.wait_for_lte()?;
 
-            log::info!("{found_ip:?}");
+        // Our own servers replace the ones of the network
+        let mut servers = self.dns.servers;
+        if servers.primary.is_none() && servers.secondary.is_none() {
+            servers = to_nb_result(self.network_dns_servers())?;
+        }
+        if servers.primary.is_none() && servers.secondary.is_none() {
+            return to_nb_result(Err(Error::AddressNotFound));
+        }
 
-            if let Some(found_ip) = found_ip {
-                Ok(found_ip)
-            } else {
-                to_nb_result(Err(crate::Error::AddressNotFound))
+        let Some(lookup) = self.dns.lookup.as_mut() else {
+            return to_nb_result(Err(Error::SocketClosed));
+        };
+
+        // Both servers are asked at once, and the first answer is used
+        let mut query = Query::new();
+        let mut last_error = None;
+        for server in servers.iter() {
+            // The socket is IPv4 only, which a network with IPv6 servers may still give
+            if server.is_ipv6() {
+                log::warning!("Skipping IPv6 DNS server {:?}", server);
+                last_error = Some(Error::NrfSys(Errno::AddressFamilyNotSupported));
+                continue;
+            }
+
+            log::debug!("Sending DNS queries to {:?}", server);
+            let remote = SocketAddr::new(server, DNS_PORT);
+
+            let mut sent = true;
+            for index in 0..lookup.query_count() {
+                to_nb_result(lookup.write_query(index, &mut query))?;
+                match self.backend.send_to(lookup.socket, remote, &query) {
+                    Ok(Some(_)) => {}
+                    Ok(None) => sent = false,
+                    Err(e) => {
+                        log::warning!("Could not send DNS queries to {:?}: {:?}", server, e);
+                        last_error = Some(e);
+                        sent = false;
+                        break;
+                    }
+                }
+            }
+
+            if sent {
+                // Can't fail, because there are at most two servers
+                let _ = lookup.servers.push(server);
             }
         }
+
+        // The queries are sent again on the next poll, unless all servers have failed
+        if lookup.servers.is_empty() {
+            return match last_error {
+                Some(e) => to_nb_result(Err(e)),
+                None => Err(nb::Error::WouldBlock),
+            };
+        }
+
+        Ok(())
+    }
+}
+
+impl<B: ModemBackend> embedded_nal::Dns for Modem<B> {
+    type Error = crate::Error;
+
+    /// Resolve the hostname to its first address.
+    ///
+    /// With [AddrType::Either], this is an IPv4 address, see [Modem::get_hosts_by_name].
+    fn get_host_by_name(
+        &mut self,
+        hostname: &str,
+        addr_type: embedded_nal::AddrType,
+    ) -> embedded_nal::nb::Result<embedded_nal::IpAddr, Self::Error> {
+        self.get_hosts_by_name::<1>(hostname, addr_type)
+            .map(|addresses| addresses[0])
     }
 
+    /// Look up the hostname of the address with a PTR query. This turns on LTE if it isn't yet.
+    ///
+    /// Fails with [Error::NoPtrRecord] if the address has no hostname. See [Modem::cancel_dns_lookup] to give up.
     fn get_host_by_address(
         &mut self,
-        _addr: embedded_nal::IpAddr,
+        addr: embedded_nal::IpAddr,
     ) -> embedded_nal::nb::Result<heapless::String<256>, Self::Error> {
-        unimplemented!()
+        log::info!("Resolving dns address for {:?}", addr);
+
+        let LookupResult::Name(name) = self.lookup(Question::Address(addr))? else {
+            unreachable!("An address is answered with a name");
+        };
+
+        Ok(name)
+    }
+}
+
+/// The modem library reports a name that doesn't exist as `EINVAL`.
+/// Its own checks only give that for the service, which is never passed.
+fn modem_lookup_error(error: Error) -> Error {
+    match error {
+        Error::NrfSys(Errno::InvalidArgument) => Error::AddressNotFound,
+        error => error,
+    }
+}
+
+/// Write the header of a query with recursion desired and a single question
+fn write_header(query_id: u16, query: &mut Query) -> Result<(), Error> {
+    query
+        .extend_from_slice(&query_id.to_be_bytes())
+        .map_err(|_| Error::BufferTooSmall(None))?;
+    query
+        .extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0])
+        .map_err(|_| Error::BufferTooSmall(None))
+}
+
+/// Write the end of the name, and the type and class of the question
+fn write_question_end(record_type: u16, query: &mut Query) -> Result<(), Error> {
+    query.push(0).map_err(|_| Error::BufferTooSmall(None))?;
+    query
+        .extend_from_slice(&record_type.to_be_bytes())
+        .map_err(|_| Error::BufferTooSmall(None))?;
+    query
+        .extend_from_slice(&CLASS_IN.to_be_bytes())
+        .map_err(|_| Error::BufferTooSmall(None))
+}
+
+/// Write a DNS query for the PTR record of the address
+fn write_ptr_query(query_id: u16, address: IpAddr, query: &mut Query) -> Result<(), Error> {
+    write_header(query_id, query)?;
+
+    let mut label = heapless::String::<3>::new();
+    match address {
+        IpAddr::V4(address) => {
+            for octet in address.octets().iter().rev() {
+                label.clear();
+                write!(label, "{octet}")?;
+                write_label(query, &label)?;
+            }
+            write_label(query, "in-addr")?;
+        }
+        IpAddr::V6(address) => {
+            for octet in address.octets().iter().rev() {
+                for nibble in [octet & 0x0F, octet >> 4] {
+                    label.clear();
+                    write!(label, "{nibble:x}")?;
+                    write_label(query, &label)?;
+                }
+            }
+            write_label(query, "ip6")?;
+        }
+    }
+    write_label(query, "arpa")?;
+
+    write_question_end(TYPE_PTR, query)
+}
+
+/// Write a DNS query for the A or AAAA records of the hostname
+fn write_address_query(
+    query_id: u16,
+    hostname: &str,
+    family: &AddrType,
+    query: &mut Query,
+) -> Result<(), Error> {
+    write_header(query_id, query)?;
+
+    for label in hostname.trim_end_matches('.').split('.') {
+        if label.is_empty() {
+            return Err(Error::AddressNotFound);
+        }
+        write_label(query, label)?;
+    }
+
+    let record_type = match family {
+        AddrType::IPv6 => TYPE_AAAA,
+        _ => TYPE_A,
+    };
+    write_question_end(record_type, query)
+}
+
+fn write_label(query: &mut Query, label: &str) -> Result<(), Error> {
+    // Longer labels can't be encoded
+    if label.len() > 63 {
+        return Err(Error::HostnameTooLong);
+    }
+
+    query
+        .push(label.len() as u8)
+        .map_err(|_| Error::BufferTooSmall(None))?;
+    query
+        .extend_from_slice(label.as_bytes())
+        .map_err(|_| Error::BufferTooSmall(None))
+}
+
+/// The answers of a DNS response
+struct Answers<'m> {
+    message: &'m [u8],
+    rcode: u8,
+    /// The position of the first answer
+    position: usize,
+    count: u16,
+}
+
+impl Answers<'_> {
+    /// Call the function with the type and the position of the data of every answer,
+    /// until it returns something
+    fn find<T>(
+        &self,
+        mut f: impl FnMut(u16, core::ops::Range<usize>) -> Result<Option<T>, Error>,
+    ) -> Result<Option<T>, Error> {
+        let mut position = self.position;
+
+        for _ in 0..self.count {
+            position = skip_name(self.message, position)?;
+            let record_type = read_u16(self.message, position)?;
+            let data_length = read_u16(self.message, position + 8)? as usize;
+            // The type, class, ttl and data length
+            let data = position + 10;
+
+            if let Some(found) = f(record_type, data..data + data_length)? {
+                return Ok(Some(found));
+            }
+
+            position = data + data_length;
+        }
+
+        Ok(None)
+    }
+}
+
+/// Skip to the answers of a response.
+///
+/// Returns `None` if it's not a response to the query with the id.
+fn read_answers(query_id: u16, response: &[u8]) -> Result<Option<Answers<'_>>, Error> {
+    if response.len() < DNS_HEADER_LENGTH {
+        return Err(Error::InvalidDnsResponse);
+    }
+
+    let id = read_u16(response, 0)?;
+    let flags = read_u16(response, 2)?;
+    if id != query_id || flags & 0x8000 == 0 {
+        return Ok(None);
+    }
+
+    let question_count = read_u16(response, 4)?;
+    let answer_count = read_u16(response, 6)?;
+
+    let mut position = DNS_HEADER_LENGTH;
+    for _ in 0..question_count {
+        // The name, type and class
+        position = skip_name(response, position)? + 4;
+    }
+
+    Ok(Some(Answers {
+        message: response,
+        rcode: (flags & 0x000F) as u8,
+        position,
+        count: answer_count,
+    }))
+}
+
+/// Parse the response to a PTR query.
+///
+/// Returns `None` if it's not a response to the query with the id.
+fn parse_ptr_response(
+    query_id: u16,
+    response: &[u8],
+) -> Result<Option<heapless::String<256>>, Error> {
+    let Some(answers) = read_answers(query_id, response)? else {
+        return Ok(None);
+    };
+
+    match answers.rcode {
+        0 => {}
+        RCODE_NAME_ERROR => return Err(Error::NoPtrRecord),
+        rcode => return Err(Error::DnsServerError(rcode)),
+    }
+
+    answers
+        .find(|record_type, data| match record_type {
+            TYPE_PTR => read_name(response, data.start).map(Some),
+            _ => Ok(None),
+        })?
+        .ok_or(Error::NoPtrRecord)
+        .map(Some)
+}
+
+/// Parse the response to an A or AAAA query.
+///
+/// Returns `None` if it's not a response to the query with the id.
+fn parse_address_response(
+    query_id: u16,
+    response: &[u8],
+    family: &AddrType,
+) -> Result<Option<AddressAnswer>, Error> {
+    let Some(answers) = read_answers(query_id, response)? else {
+        return Ok(None);
+    };
+
+    match answers.rcode {
+        0 => {}
+        RCODE_NAME_ERROR => return Err(Error::AddressNotFound),
+        rcode => return Err(Error::DnsServerError(rcode)),
+    }
+
+    let mut answer = AddressAnswer {
+        addresses: heapless::Vec::new(),
+        complete: true,
+    };
+
+    // Other records, like the CNAME the hostname is an alias of, are skipped
+    answers.find(|record_type, data| {
+        let data = response.get(data).ok_or(Error::InvalidDnsResponse)?;
+        let ip = match (family, record_type) {
+            (AddrType::IPv6, TYPE_AAAA) => <[u8; 16]>::try_from(data)
+                .map(|octets| IpAddr::V6(octets.into()))
+                .map_err(|_| Error::InvalidDnsResponse)?,
+            (AddrType::IPv4 | AddrType::Either, TYPE_A) => <[u8; 4]>::try_from(data)
+                .map(|octets| IpAddr::V4(octets.into()))
+                .map_err(|_| Error::InvalidDnsResponse)?,
+            _ => return Ok(None::<()>),
+        };
+
+        if answer.addresses.push(ip).is_err() {
+            answer.complete = false;
+        }
+
+        Ok(None)
+    })?;
+
+    if answer.addresses.is_empty() {
+        return Err(Error::AddressNotFound);
+    }
+
+    Ok(Some(answer))
+}
+
+fn read_u16(message: &[u8], position: usize) -> Result<u16, Error> {
+    message
+        .get(position..position + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .ok_or(Error::InvalidDnsResponse)
+}
+
+/// Returns the position after the name
+fn skip_name(message: &[u8], mut position: usize) -> Result<usize, Error> {
+    loop {
+        let length = *message.get(position).ok_or(Error::InvalidDnsResponse)?;
+        match length {
+            0 => return Ok(position + 1),
+            // A pointer ends the name
+            length if length & 0xC0 == 0xC0 => return Ok(position + 2),
+            length => position += 1 + length as usize,
+        }
+    }
+}
+
+/// Read a name, following the pointers of compressed names
+fn read_name(message: &[u8], mut position: usize) -> Result<heapless::String<256>, Error> {
+    let mut name = heapless::String::new();
+    let mut pointers = 0;
+
+    loop {
+        let length = *message.get(position).ok_or(Error::InvalidDnsResponse)?;
+
+        if length & 0xC0 == 0xC0 {
+            pointers += 1;
+            if pointers > MAX_NAME_POINTERS {
+                return Err(Error::InvalidDnsResponse);
+            }
+            position = (read_u16(message, position)? & 0x3FFF) as usize;
+            continue;
+        }
+
+        if length == 0 {
+            return Ok(name);
+        }
+
+        let label = message
+            .get(position + 1..position + 1 + length as usize)
+            .and_then(|label| core::str::from_utf8(label).ok())
+            .filter(|label| label.is_ascii())
+            .ok_or(Error::InvalidDnsResponse)?;
+
+        if !name.is_empty() {
+            name.push('.').map_err(|_| Error::InvalidDnsResponse)?;
+        }
+        name.push_str(label)
+            .map_err(|_| Error::InvalidDnsResponse)?;
+
+        position += 1 + length as usize;
     }
 }
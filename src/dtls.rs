@@ -0,0 +1,126 @@
+//! DTLS client sockets
+//!
+//! Like with [TLS](crate::tls), the session is handled by the modem using the credentials of the security tags.
+//!
+//! ```ignore
+//! let options = DtlsOptions {
+//!     security: TlsOptions {
+//!         sec_tags: &[42],
+//!         peer_verification: PeerVerification::Required,
+//!         hostname: Some("coap.example.com"),
+//!     },
+//! };
+//!
+//! let mut stack = modem.dtls(options);
+//! let mut socket = stack.socket()?;
+//! stack.connect(&mut socket, remote)?;
+//! ```
+
+use crate::{
+    backend::{ModemBackend, SocketKind},
+    error::Error,
+    log,
+    tls::TlsOptions,
+    udp::UdpSocket,
+    Modem,
+};
+use embedded_nal::{nb, UdpClientStack};
+
+/// The settings of a DTLS socket
+///
+/// The handshake timeout and the DTLS Connection ID (RFC 9146) can't be set yet,
+/// because the modem library this crate is built on has no socket options for them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DtlsOptions<'a> {
+    /// The security tags, peer verification and hostname, like for TLS
+    pub security: TlsOptions<'a>,
+}
+
+impl<B: ModemBackend> Modem<B> {
+    /// Use the modem as a [UdpClientStack] that creates DTLS sockets with the given options
+    pub fn dtls<'o>(&mut self, options: DtlsOptions<'o>) -> DtlsStack<'_, 'o, B> {
+        DtlsStack {
+            modem: self,
+            options,
+        }
+    }
+
+    /// Create a DTLS socket.
+    ///
+    /// The options are applied right away, so they can't be changed after the socket has been created.
+    pub fn dtls_socket(&mut self, options: &DtlsOptions) -> Result<DtlsSocket, Error> {
+        log::debug!("Creating DTLS socket");
+
+        let socket = self.datagram_socket(SocketKind::Dtls)?;
+
+        match self.backend.dtls_configure(socket.handle(), options) {
+            Ok(()) => Ok(DtlsSocket { inner: socket }),
+            Err(e) => {
+                UdpClientStack::close(self, socket)?;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// A [UdpClientStack] that creates DTLS sockets, see [Modem::dtls]
+pub struct DtlsStack<'m, 'o, B: ModemBackend> {
+    modem: &'m mut Modem<B>,
+    options: DtlsOptions<'o>,
+}
+
+impl<B: ModemBackend> DtlsStack<'_, '_, B> {
+    /// Use the modem directly
+    pub fn modem(&mut self) -> &mut Modem<B> {
+        self.modem
+    }
+}
+
+impl<B: ModemBackend> UdpClientStack for DtlsStack<'_, '_, B> {
+    type UdpSocket = DtlsSocket;
+    type Error = Error;
+
+    fn socket(&mut self) -> Result<Self::UdpSocket, Self::Error> {
+        self.modem.dtls_socket(&self.options)
+    }
+
+    /// Connect to the server and do the DTLS handshake. This turns on LTE if it isn't yet.
+    fn connect(
+        &mut self,
+        socket: &mut Self::UdpSocket,
+        remote: embedded_nal::SocketAddr,
+    ) -> Result<(), Self::Error> {
+        self.modem.connect(&mut socket.inner, remote)
+    }
+
+    fn send(&mut self, socket: &mut Self::UdpSocket, buffer: &[u8]) -> nb::Result<(), Self::Error> {
+        self.modem.send(&mut socket.inner, buffer)
+    }
+
+    fn receive(
+        &mut self,
+        socket: &mut Self::UdpSocket,
+        buffer: &mut [u8],
+    ) -> nb::Result<(usize, embedded_nal::SocketAddr), Self::Error> {
+        self.modem.receive(&mut socket.inner, buffer)
+    }
+
+    fn close(&mut self, socket: Self::UdpSocket) -> Result<(), Self::Error> {
+        self.modem.close(socket.inner)
+    }
+}
+
+/// A UDP socket with DTLS. It must be closed like a [UdpSocket].
+pub struct DtlsSocket {
+    inner: UdpSocket,
+}
+
+impl DtlsSocket {
+    pub(crate) fn inner(&self) -> &UdpSocket {
+        &self.inner
+    }
+
+    pub(crate) fn into_inner(self) -> UdpSocket {
+        self.inner
+    }
+}
@@ -0,0 +1,285 @@
+//! Extended Discontinuous Reception (eDRX)
+//!
+//! With eDRX the modem only listens for paging at the end of every eDRX cycle, during the paging time window.
+//! Unlike with PSM, the device stays reachable for downlink data.
+
+use crate::{backend::ModemBackend, error::Error, log, Modem};
+use at_commands::{builder::CommandBuilder, parser::CommandParser};
+use core::time::Duration;
+
+/// The access technology an eDRX setting applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdrxAccessTechnology {
+    LteM = 4,
+    NbIot = 5,
+}
+
+impl EdrxAccessTechnology {
+    fn from_int(value: i32) -> Result<Option<Self>, Error> {
+        match value {
+            0 => Ok(None),
+            4 => Ok(Some(Self::LteM)),
+            5 => Ok(Some(Self::NbIot)),
+            _ => Err(Error::UnexpectedAtResponse),
+        }
+    }
+}
+
+/// The length of the eDRX cycle
+///
+/// The values are the ones of 3GPP TS 24.008 table 10.5.5.32.
+/// NB-IoT only supports the cycles of 20.48 seconds and up, excluding 61.44 and 102.4 to 143.36 seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdrxCycle {
+    Seconds5_12 = 0b0000,
+    Seconds10_24 = 0b0001,
+    Seconds20_48 = 0b0010,
+    Seconds40_96 = 0b0011,
+    Seconds61_44 = 0b0100,
+    Seconds81_92 = 0b0101,
+    Seconds102_4 = 0b0110,
+    Seconds122_88 = 0b0111,
+    Seconds143_36 = 0b1000,
+    Seconds163_84 = 0b1001,
+    Seconds327_68 = 0b1010,
+    Seconds655_36 = 0b1011,
+    Seconds1310_72 = 0b1100,
+    Seconds2621_44 = 0b1101,
+    Seconds5242_88 = 0b1110,
+    Seconds10485_76 = 0b1111,
+}
+
+impl EdrxCycle {
+    const ALL: [Self; 16] = [
+        Self::Seconds5_12,
+        Self::Seconds10_24,
+        Self::Seconds20_48,
+        Self::Seconds40_96,
+        Self::Seconds61_44,
+        Self::Seconds81_92,
+        Self::Seconds102_4,
+        Self::Seconds122_88,
+        Self::Seconds143_36,
+        Self::Seconds163_84,
+        Self::Seconds327_68,
+        Self::Seconds655_36,
+        Self::Seconds1310_72,
+        Self::Seconds2621_44,
+        Self::Seconds5242_88,
+        Self::Seconds10485_76,
+    ];
+
+    /// The length of the cycle
+    pub fn duration(&self) -> Duration {
+        // Every cycle is a multiple of 5.12 seconds
+        const MULTIPLES: [u64; 16] = [
+            1, 2, 4, 8, 12, 16, 20, 24, 28, 32, 64, 128, 256, 512, 1024, 2048,
+        ];
+
+        Duration::from_millis(5120 * MULTIPLES[*self as usize])
+    }
+
+    /// Returns `true` if the cycle can be used with the given access technology
+    pub fn is_supported_by(&self, access_technology: EdrxAccessTechnology) -> bool {
+        match access_technology {
+            EdrxAccessTechnology::LteM => true,
+            EdrxAccessTechnology::NbIot => matches!(
+                self,
+                Self::Seconds20_48
+                    | Self::Seconds40_96
+                    | Self::Seconds81_92
+                    | Self::Seconds163_84
+                    | Self::Seconds327_68
+                    | Self::Seconds655_36
+                    | Self::Seconds1310_72
+                    | Self::Seconds2621_44
+                    | Self::Seconds5242_88
+                    | Self::Seconds10485_76
+            ),
+        }
+    }
+
+    fn from_bits(bits: &str) -> Result<Self, Error> {
+        parse_bits(bits).map(|value| Self::ALL[value as usize])
+    }
+}
+
+/// The paging time window: how long the modem listens for paging at the end of every eDRX cycle
+///
+/// The value goes from 0 to 15. The length is `value + 1` times 1.28 seconds for LTE-M,
+/// or times 2.56 seconds for NB-IoT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PagingTimeWindow(u8);
+
+impl PagingTimeWindow {
+    /// Create a paging time window from its raw value. Returns `None` if the value is above 15.
+    pub fn new(value: u8) -> Option<Self> {
+        (value <= 0b1111).then_some(Self(value))
+    }
+
+    /// The raw value of the paging time window
+    pub fn value(&self) -> u8 {
+        self.0
+    }
+
+    /// The length of the paging time window for the given access technology
+    pub fn duration(&self, access_technology: EdrxAccessTechnology) -> Duration {
+        let unit = match access_technology {
+            EdrxAccessTechnology::LteM => 1280,
+            EdrxAccessTechnology::NbIot => 2560,
+        };
+
+        Duration::from_millis(unit * (self.0 as u64 + 1))
+    }
+
+    fn from_bits(bits: &str) -> Result<Self, Error> {
+        parse_bits(bits).map(Self)
+    }
+}
+
+/// Parse a 4 bit string like `"0101"`
+fn parse_bits(bits: &str) -> Result<u8, Error> {
+    if bits.len() != 4 {
+        return Err(Error::UnexpectedAtResponse);
+    }
+
+    u8::from_str_radix(bits, 2).map_err(|_| Error::UnexpectedAtResponse)
+}
+
+/// Treat an empty parameter like an absent one
+fn non_empty(bits: Option<&str>) -> Option<&str> {
+    bits.filter(|bits| !bits.is_empty())
+}
+
+/// Write the lowest 4 bits of the value as a bit string
+fn format_bits(value: u8, buffer: &mut [u8; 4]) -> &str {
+    for (i, bit) in buffer.iter_mut().enumerate() {
+        *bit = if value & (0b1000 >> i) != 0 {
+            b'1'
+        } else {
+            b'0'
+        };
+    }
+
+    core::str::from_utf8(buffer).unwrap()
+}
+
+/// The eDRX settings that are requested from the network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdrxConfig {
+    /// The requested eDRX cycle
+    pub cycle: EdrxCycle,
+    /// The requested paging time window. `None` leaves it for the modem to choose.
+    pub paging_time_window: Option<PagingTimeWindow>,
+}
+
+/// The eDRX values of the network, as reported by `+CEDRXP` notifications and `AT+CEDRXRDP`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdrxStatus {
+    /// The access technology the values apply to. `None` if the current cell doesn't use eDRX.
+    pub access_technology: Option<EdrxAccessTechnology>,
+    /// The eDRX cycle that was requested
+    pub requested_cycle: Option<EdrxCycle>,
+    /// The eDRX cycle the network provided
+    pub cycle: Option<EdrxCycle>,
+    /// The paging time window the network provided
+    pub paging_time_window: Option<PagingTimeWindow>,
+}
+
+impl EdrxStatus {
+    /// Parse a `+CEDRXP` notification, e.g. `+CEDRXP: 4,"1000","0101","1011"`
+    pub fn parse_notification(line: &str) -> Result<Self, Error> {
+        Self::parse(line, b"+CEDRXP:")
+    }
+
+    fn parse(line: &str, identifier: &[u8]) -> Result<Self, Error> {
+        let (access_technology, requested_cycle, cycle, paging_time_window) =
+            CommandParser::parse(line.trim().as_bytes())
+                .expect_identifier(identifier)
+                .expect_int_parameter()
+                .expect_optional_string_parameter()
+                .expect_optional_string_parameter()
+                .expect_optional_string_parameter()
+                .finish()?;
+
+        Ok(Self {
+            access_technology: EdrxAccessTechnology::from_int(access_technology)?,
+            requested_cycle: non_empty(requested_cycle)
+                .map(EdrxCycle::from_bits)
+                .transpose()?,
+            cycle: non_empty(cycle).map(EdrxCycle::from_bits).transpose()?,
+            paging_time_window: non_empty(paging_time_window)
+                .map(PagingTimeWindow::from_bits)
+                .transpose()?,
+        })
+    }
+}
+
+impl<B: ModemBackend> Modem<B> {
+    /// Request eDRX for every access technology that is enabled in the [SystemMode](crate::SystemMode).
+    ///
+    /// This also enables the `+CEDRXP` notifications, which can be parsed with [EdrxStatus::parse_notification].
+    pub fn set_edrx(&mut self, config: EdrxConfig) -> Result<(), Error> {
+        log::debug!("Setting eDRX: {:?}", config);
+
+        let access_technologies = [
+            (self.system_mode.lte_support, EdrxAccessTechnology::LteM),
+            (self.system_mode.nbiot_support, EdrxAccessTechnology::NbIot),
+        ];
+
+        if access_technologies
+            .iter()
+            .any(|(enabled, act)| *enabled && !config.cycle.is_supported_by(*act))
+        {
+            return Err(Error::InvalidConfiguration);
+        }
+
+        for (_, access_technology) in access_technologies.iter().filter(|(enabled, _)| *enabled) {
+            let mut buffer = [0; 32];
+            let mut bits = [0; 4];
+
+            let command = CommandBuilder::create_set(&mut buffer, true)
+                .named("+CEDRXS")
+                .with_int_parameter(2)
+                .with_int_parameter(*access_technology as u8)
+                .with_string_parameter(format_bits(config.cycle as u8, &mut bits))
+                .finish()
+                .map_err(|e| Error::BufferTooSmall(Some(e)))?;
+            self.send_at_command(command, |_| {})?;
+
+            if let Some(paging_time_window) = config.paging_time_window {
+                let command = CommandBuilder::create_set(&mut buffer, true)
+                    .named("%XPTW")
+                    .with_int_parameter(*access_technology as u8)
+                    .with_string_parameter(format_bits(paging_time_window.0, &mut bits))
+                    .finish()
+                    .map_err(|e| Error::BufferTooSmall(Some(e)))?;
+                self.send_at_command(command, |_| {})?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Disable eDRX and its notifications
+    pub fn disable_edrx(&mut self) -> Result<(), Error> {
+        log::debug!("Disabling eDRX");
+        self.send_at_command("AT+CEDRXS=3", |_| {})
+    }
+
+    /// Read the eDRX values the network provided, using `AT+CEDRXRDP`
+    pub fn edrx_status(&mut self) -> Result<EdrxStatus, Error> {
+        let mut status = None;
+
+        self.send_at_command("AT+CEDRXRDP", |line| {
+            if status.is_none() && line.starts_with("+CEDRXRDP:") {
+                status = Some(EdrxStatus::parse(line, b"+CEDRXRDP:"));
+            }
+        })?;
+
+        let status = status.ok_or(Error::NoAtResponse)??;
+        log::debug!("eDRX status: {:?}", status);
+
+        Ok(status)
+    }
+}
@@ -1,7 +1,11 @@
 #[derive(Debug, Clone)]
 pub enum Error {
+    #[cfg(feature = "nrfxlib")]
     NrfModem(nrfxlib::Error),
-    NrfSys(i32),
+    /// A call of the modem library failed with this errno
+    NrfSys(Errno),
+    /// The modem responded to an AT command with an error
+    AtError(AtError),
     AddressNotFound,
     HostnameTooLong,
     HostnameNotAscii,
@@ -19,13 +23,322 @@ pub enum Error {
     SimFailure,
     /// A buffer was too small. The number indicates how big the buffer has to be (if that can be determined).
     BufferTooSmall(Option<usize>),
+    /// The operation is not supported
+    NotSupported,
+    /// The address has no hostname, because the DNS server has no PTR record for it
+    NoPtrRecord,
+    /// The DNS server responded with this error code
+    DnsServerError(u8),
+    /// The response of the DNS server could not be parsed
+    InvalidDnsResponse,
 }
 
+/// What kind of error an [Error] is, for code that doesn't know about the modem,
+/// like protocol implementations on top of embedded-nal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ErrorKind {
+    /// The peer closed, reset or aborted the connection
+    ConnectionReset,
+    ConnectionRefused,
+    /// The socket is not connected, or already closed
+    NotConnected,
+    /// The operation can't be done right now, try again later
+    WouldBlock,
+    /// There is no network: LTE is not registered, or the network or host is unreachable
+    NetworkDown,
+    TimedOut,
+    /// The hostname or address could not be resolved
+    AddressNotFound,
+    AddressInUse,
+    /// An argument or the configuration is not valid
+    InvalidInput,
+    /// The modem or a server responded with something that could not be parsed
+    InvalidData,
+    /// The modem does not allow the operation, e.g. in its current state
+    PermissionDenied,
+    Unsupported,
+    /// A buffer or a resource of the modem ran out
+    OutOfMemory,
+    Other,
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            #[cfg(feature = "nrfxlib")]
+            Self::NrfModem(nrfxlib::Error::Nordic(_, _, errno)) => Errno::from_code(*errno).kind(),
+            #[cfg(feature = "nrfxlib")]
+            Self::NrfModem(_) => ErrorKind::Other,
+            Self::NrfSys(errno) => errno.kind(),
+            Self::AtError(_) => ErrorKind::Other,
+            Self::AddressNotFound | Self::NoPtrRecord => ErrorKind::AddressNotFound,
+            Self::HostnameTooLong
+            | Self::HostnameNotAscii
+            | Self::SocketAlreadyOpen
+            | Self::InvalidConfiguration
+            | Self::InvalidBandConfiguration => ErrorKind::InvalidInput,
+            Self::SocketClosed => ErrorKind::NotConnected,
+            Self::Fmt(_) => ErrorKind::Other,
+            Self::AtParsing(_)
+            | Self::NoAtResponse
+            | Self::UnexpectedAtResponse
+            | Self::InvalidDnsResponse => ErrorKind::InvalidData,
+            Self::NotAllowedInActiveState => ErrorKind::PermissionDenied,
+            Self::LteRegistrationDenied | Self::SimFailure => ErrorKind::NetworkDown,
+            Self::BufferTooSmall(_) => ErrorKind::OutOfMemory,
+            Self::NotSupported => ErrorKind::Unsupported,
+            Self::DnsServerError(_) => ErrorKind::Other,
+        }
+    }
+
+    /// Returns `true` if the operation may succeed when it's tried again, possibly on a new connection.
+    ///
+    /// Errors that come from the configuration or from how the modem is used are not transient.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            #[cfg(feature = "nrfxlib")]
+            Self::NrfModem(nrfxlib::Error::Nordic(_, _, errno)) => {
+                Errno::from_code(*errno).is_transient()
+            }
+            Self::NrfSys(errno) => errno.is_transient(),
+            // SERVFAIL
+            Self::DnsServerError(2) => true,
+            _ => false,
+        }
+    }
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            #[cfg(feature = "nrfxlib")]
+            Self::NrfModem(e) => write!(f, "modem library error: {:?}", e),
+            Self::NrfSys(errno) => write!(f, "modem library error {}: {}", errno.code(), errno),
+            Self::AtError(e) => write!(f, "AT command failed with {}", e),
+            Self::AddressNotFound => f.write_str("address not found"),
+            Self::HostnameTooLong => f.write_str("hostname too long"),
+            Self::HostnameNotAscii => f.write_str("hostname is not ASCII"),
+            Self::SocketAlreadyOpen => f.write_str("socket already open"),
+            Self::SocketClosed => f.write_str("socket closed"),
+            Self::Fmt(e) => write!(f, "formatting failed: {}", e),
+            Self::AtParsing(e) => write!(f, "could not parse the AT response: {:?}", e),
+            Self::NoAtResponse => f.write_str("no response to the AT command"),
+            Self::UnexpectedAtResponse => f.write_str("unexpected response to the AT command"),
+            Self::InvalidConfiguration => f.write_str("invalid configuration"),
+            Self::NotAllowedInActiveState => f.write_str("not allowed while the modem is active"),
+            Self::InvalidBandConfiguration => {
+                f.write_str("band configuration not valid for the system mode")
+            }
+            Self::LteRegistrationDenied => f.write_str("LTE registration denied"),
+            Self::SimFailure => f.write_str("SIM failure"),
+            Self::BufferTooSmall(Some(size)) => {
+                write!(f, "buffer too small, {} bytes are needed", size)
+            }
+            Self::BufferTooSmall(None) => f.write_str("buffer too small"),
+            Self::NotSupported => f.write_str("not supported"),
+            Self::NoPtrRecord => f.write_str("no PTR record for the address"),
+            Self::DnsServerError(code) => write!(f, "DNS server error {}", code),
+            Self::InvalidDnsResponse => f.write_str("invalid DNS response"),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{}", defmt::Display2Format(self))
+    }
+}
+
+/// The error responses the modem can give to an AT command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AtError {
+    /// Plain `ERROR` response
+    Error,
+    /// `+CME ERROR: xx` response
+    CmeError(CmeError),
+    /// `+CMS ERROR: xx` response
+    CmsError(CmsError),
+}
+
+impl core::fmt::Display for AtError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Error => f.write_str("ERROR"),
+            Self::CmeError(e) => write!(f, "+CME ERROR: {} ({})", e.code(), e),
+            Self::CmsError(e) => write!(f, "+CMS ERROR: {} ({})", e.code(), e),
+        }
+    }
+}
+
+/// Defines an enum of error codes with a description of each, and `Other` for the codes that are not in it
+macro_rules! error_codes {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident = $code:literal => $description:literal,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)*
+            /// A code that is not known to this crate
+            Other(i32),
+        }
+
+        impl $name {
+            pub fn from_code(code: i32) -> Self {
+                match code {
+                    $($code => Self::$variant,)*
+                    code => Self::Other(code),
+                }
+            }
+
+            /// The number of the code
+            pub fn code(&self) -> i32 {
+                match self {
+                    $(Self::$variant => $code,)*
+                    Self::Other(code) => *code,
+                }
+            }
+        }
+
+        impl core::fmt::Display for $name {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                match self {
+                    $(Self::$variant => f.write_str($description),)*
+                    Self::Other(_) => f.write_str("unknown error"),
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    /// The `+CME ERROR` codes of 3GPP TS 27.007 section 9.2, and the extensions of the nRF91 modem.
+    ///
+    /// What the extensions of the modem mean depends a bit on the command, see its documentation.
+    pub enum CmeError {
+        PhoneFailure = 0 => "phone failure",
+        NoConnectionToPhone = 1 => "no connection to phone",
+        PhoneAdaptorLinkReserved = 2 => "phone-adaptor link reserved",
+        OperationNotAllowed = 3 => "operation not allowed",
+        OperationNotSupported = 4 => "operation not supported",
+        PhSimPinRequired = 5 => "PH-SIM PIN required",
+        PhFsimPinRequired = 6 => "PH-FSIM PIN required",
+        PhFsimPukRequired = 7 => "PH-FSIM PUK required",
+        SimNotInserted = 10 => "SIM not inserted",
+        SimPinRequired = 11 => "SIM PIN required",
+        SimPukRequired = 12 => "SIM PUK required",
+        SimFailure = 13 => "SIM failure",
+        SimBusy = 14 => "SIM busy",
+        SimWrong = 15 => "SIM wrong",
+        IncorrectPassword = 16 => "incorrect password",
+        SimPin2Required = 17 => "SIM PIN2 required",
+        SimPuk2Required = 18 => "SIM PUK2 required",
+        MemoryFull = 20 => "memory full",
+        InvalidIndex = 21 => "invalid index",
+        NotFound = 22 => "not found",
+        MemoryFailure = 23 => "memory failure",
+        TextStringTooLong = 24 => "text string too long",
+        InvalidCharactersInTextString = 25 => "invalid characters in text string",
+        DialStringTooLong = 26 => "dial string too long",
+        InvalidCharactersInDialString = 27 => "invalid characters in dial string",
+        NoNetworkService = 30 => "no network service",
+        NetworkTimeout = 31 => "network timeout",
+        NetworkNotAllowed = 32 => "network not allowed, emergency calls only",
+        NetworkPersonalizationPinRequired = 40 => "network personalization PIN required",
+        NetworkPersonalizationPukRequired = 41 => "network personalization PUK required",
+        IncorrectParameters = 50 => "incorrect parameters",
+        CommandDisabled = 51 => "command implemented but currently disabled",
+        CommandAborted = 52 => "command aborted by user",
+        NotAttachedByFunctionality = 53 => "not attached to network due to MT functionality restrictions",
+        MtRestrictedToEmergencyCalls = 54 => "modem not allowed, MT restricted to emergency calls only",
+        NotAllowedByFunctionality = 55 => "operation not allowed because of MT functionality restrictions",
+        FixedDialNumberOnly = 56 => "fixed dial number only allowed",
+        TemporarilyOutOfService = 57 => "temporarily out of service due to other MT usage",
+        LanguageNotSupported = 58 => "language or alphabet not supported",
+        UnexpectedDataValue = 59 => "unexpected data value",
+        SystemFailure = 60 => "system failure",
+        DataMissing = 61 => "data missing",
+        CallBarred = 62 => "call barred",
+        MessageWaitingIndicationFailed = 63 => "message waiting indication subscription failure",
+        Unknown = 100 => "unknown",
+        IllegalMs = 103 => "illegal MS",
+        IllegalMe = 106 => "illegal ME",
+        GprsServicesNotAllowed = 107 => "GPRS services not allowed",
+        PlmnNotAllowed = 111 => "PLMN not allowed",
+        LocationAreaNotAllowed = 112 => "location area not allowed",
+        RoamingNotAllowed = 113 => "roaming not allowed in this location area",
+        ServiceOptionNotSupported = 132 => "service option not supported",
+        ServiceOptionNotSubscribed = 133 => "requested service option not subscribed",
+        ServiceOptionOutOfOrder = 134 => "service option temporarily out of order",
+        UnspecifiedGprsError = 148 => "unspecified GPRS error",
+        PdpAuthenticationFailure = 149 => "PDP authentication failure",
+        InvalidMobileClass = 150 => "invalid mobile class",
+        /// An extension of the nRF91 modem
+        ItemNotFound = 513 => "not found",
+        /// An extension of the nRF91 modem
+        NotAllowed = 514 => "not allowed",
+        /// An extension of the nRF91 modem
+        StorageFull = 515 => "memory full",
+        /// An extension of the nRF91 modem, e.g. changing the system mode or credentials while LTE is active
+        NotAllowedInActiveState = 518 => "not allowed in active state",
+        /// An extension of the nRF91 modem
+        AlreadyExists = 519 => "already exists",
+        /// An extension of the nRF91 modem
+        NotAllowedInCurrentState = 520 => "not allowed in current state",
+        /// An extension of the nRF91 modem
+        PlmnSearchInterrupted = 521 => "PLMN search interrupted",
+        /// An extension of the nRF91 modem
+        InvalidBandConfiguration = 522 => "band configuration not valid for the system mode",
+    }
+}
+
+error_codes! {
+    /// The `+CMS ERROR` codes of 3GPP TS 27.005 section 3.2.5, for SMS commands
+    pub enum CmsError {
+        MeFailure = 300 => "ME failure",
+        SmsServiceReserved = 301 => "SMS service of ME reserved",
+        OperationNotAllowed = 302 => "operation not allowed",
+        OperationNotSupported = 303 => "operation not supported",
+        InvalidPduModeParameter = 304 => "invalid PDU mode parameter",
+        InvalidTextModeParameter = 305 => "invalid text mode parameter",
+        SimNotInserted = 310 => "SIM not inserted",
+        SimPinRequired = 311 => "SIM PIN required",
+        PhSimPinRequired = 312 => "PH-SIM PIN required",
+        SimFailure = 313 => "SIM failure",
+        SimBusy = 314 => "SIM busy",
+        SimWrong = 315 => "SIM wrong",
+        SimPukRequired = 316 => "SIM PUK required",
+        SimPin2Required = 317 => "SIM PIN2 required",
+        SimPuk2Required = 318 => "SIM PUK2 required",
+        MemoryFailure = 320 => "memory failure",
+        InvalidMemoryIndex = 321 => "invalid memory index",
+        MemoryFull = 322 => "memory full",
+        SmscAddressUnknown = 330 => "SMSC address unknown",
+        NoNetworkService = 331 => "no network service",
+        NetworkTimeout = 332 => "network timeout",
+        NoCnmaAcknowledgementExpected = 340 => "no +CNMA acknowledgement expected",
+        UnknownError = 500 => "unknown error",
+    }
+}
+
+#[cfg(feature = "nrfxlib")]
 impl From<nrfxlib::Error> for Error {
     fn from(e: nrfxlib::Error) -> Self {
         Self::NrfModem(e)
     }
 }
+impl From<AtError> for Error {
+    fn from(e: AtError) -> Self {
+        Self::AtError(e)
+    }
+}
 impl From<core::fmt::Error> for Error {
     fn from(e: core::fmt::Error) -> Self {
         Self::Fmt(e)
@@ -36,3 +349,170 @@ impl From<at_commands::parser::ParseError> for Error {
         Self::AtParsing(e)
     }
 }
+
+#[cfg(feature = "async")]
+impl embedded_io_async::Error for Error {
+    fn kind(&self) -> embedded_io_async::ErrorKind {
+        use embedded_io_async::ErrorKind as IoErrorKind;
+
+        match self.kind() {
+            ErrorKind::ConnectionReset => IoErrorKind::ConnectionReset,
+            ErrorKind::ConnectionRefused => IoErrorKind::ConnectionRefused,
+            ErrorKind::NotConnected => IoErrorKind::NotConnected,
+            ErrorKind::TimedOut => IoErrorKind::TimedOut,
+            ErrorKind::AddressNotFound => IoErrorKind::NotFound,
+            ErrorKind::AddressInUse => IoErrorKind::AddrInUse,
+            ErrorKind::InvalidInput => IoErrorKind::InvalidInput,
+            ErrorKind::InvalidData => IoErrorKind::InvalidData,
+            ErrorKind::PermissionDenied => IoErrorKind::PermissionDenied,
+            ErrorKind::Unsupported => IoErrorKind::Unsupported,
+            ErrorKind::OutOfMemory => IoErrorKind::OutOfMemory,
+            ErrorKind::WouldBlock | ErrorKind::NetworkDown | ErrorKind::Other => IoErrorKind::Other,
+        }
+    }
+}
+
+error_codes! {
+    /// The errno values of the modem library
+    pub enum Errno {
+        /// `EPERM`
+        OperationNotPermitted = 1 => "operation not permitted",
+        /// `ENOENT`
+        NotFound = 2 => "no such file or directory",
+        /// `EIO`
+        Io = 5 => "input/output error",
+        /// `ENOEXEC`
+        ExecFormat = 8 => "exec format error",
+        /// `EBADF`
+        BadFileDescriptor = 9 => "bad file descriptor",
+        /// `ENOMEM`
+        OutOfMemory = 12 => "cannot allocate memory",
+        /// `EACCES`
+        PermissionDenied = 13 => "permission denied",
+        /// `EFAULT`
+        BadAddress = 14 => "bad address",
+        /// `EINVAL`
+        InvalidArgument = 22 => "invalid argument",
+        /// `EMFILE`
+        TooManySockets = 24 => "too many open sockets",
+        /// `ENOSPC`
+        NoSpace = 28 => "no space left on device",
+        /// `EAGAIN`
+        WouldBlock = 35 => "resource temporarily unavailable",
+        /// `EDOM`
+        Domain = 37 => "domain error",
+        /// `EMSGSIZE`
+        MessageTooLong = 40 => "message too long",
+        /// `EPROTOTYPE`
+        WrongProtocolType = 41 => "protocol wrong type for socket",
+        /// `ENOPROTOOPT`
+        ProtocolOptionNotAvailable = 42 => "protocol not available",
+        /// `EPROTONOSUPPORT`
+        ProtocolNotSupported = 43 => "protocol not supported",
+        /// `ESOCKTNOSUPPORT`
+        SocketTypeNotSupported = 44 => "socket type not supported",
+        /// `EOPNOTSUPP`
+        NotSupported = 45 => "operation not supported",
+        /// `EAFNOSUPPORT`
+        AddressFamilyNotSupported = 47 => "address family not supported by protocol",
+        /// `EADDRINUSE`
+        AddressInUse = 48 => "address already in use",
+        /// `ENETDOWN`
+        NetworkDown = 50 => "network is down",
+        /// `ENETUNREACH`
+        NetworkUnreachable = 51 => "network is unreachable",
+        /// `ENETRESET`
+        NetworkReset = 52 => "connection aborted by network",
+        /// `ECONNABORTED`
+        ConnectionAborted = 53 => "software caused connection abort",
+        /// `ECONNRESET`
+        ConnectionReset = 54 => "connection reset by peer",
+        /// `EISCONN`
+        AlreadyConnected = 56 => "transport endpoint is already connected",
+        /// `ENOTCONN`
+        NotConnected = 57 => "transport endpoint is not connected",
+        /// `ETIMEDOUT`
+        TimedOut = 60 => "connection timed out",
+        /// `ENOBUFS`
+        NoBufferSpace = 105 => "no buffer space available",
+        /// `ECONNREFUSED`
+        ConnectionRefused = 111 => "connection refused",
+        /// `EHOSTDOWN`
+        HostDown = 112 => "host is down",
+        /// `EHOSTUNREACH`
+        HostUnreachable = 113 => "no route to host",
+        /// `EALREADY`
+        Already = 114 => "operation already in progress",
+        /// `EINPROGRESS`
+        InProgress = 115 => "operation in progress",
+        /// `ECANCELED`
+        Canceled = 125 => "operation canceled",
+        /// `ENOKEY`
+        NoKey = 126 => "required key not available",
+        /// `EKEYEXPIRED`
+        KeyExpired = 127 => "key has expired",
+        /// `EKEYREVOKED`
+        KeyRevoked = 128 => "key has been revoked",
+        /// `EKEYREJECTED`
+        KeyRejected = 129 => "key was rejected by service",
+    }
+}
+
+impl Errno {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::WouldBlock | Self::Already | Self::InProgress => ErrorKind::WouldBlock,
+            Self::ConnectionReset | Self::ConnectionAborted | Self::NetworkReset => {
+                ErrorKind::ConnectionReset
+            }
+            Self::ConnectionRefused => ErrorKind::ConnectionRefused,
+            Self::NotConnected => ErrorKind::NotConnected,
+            Self::NetworkDown
+            | Self::NetworkUnreachable
+            | Self::HostDown
+            | Self::HostUnreachable => ErrorKind::NetworkDown,
+            Self::TimedOut => ErrorKind::TimedOut,
+            Self::AddressInUse => ErrorKind::AddressInUse,
+            Self::InvalidArgument
+            | Self::BadAddress
+            | Self::BadFileDescriptor
+            | Self::MessageTooLong => ErrorKind::InvalidInput,
+            Self::OperationNotPermitted | Self::PermissionDenied => ErrorKind::PermissionDenied,
+            Self::NotSupported
+            | Self::ProtocolNotSupported
+            | Self::SocketTypeNotSupported
+            | Self::AddressFamilyNotSupported
+            | Self::ProtocolOptionNotAvailable
+            | Self::WrongProtocolType => ErrorKind::Unsupported,
+            Self::OutOfMemory | Self::NoBufferSpace | Self::TooManySockets | Self::NoSpace => {
+                ErrorKind::OutOfMemory
+            }
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// Returns `true` if the operation may succeed when it's tried again, possibly on a new connection.
+    ///
+    /// These are the errors of the network, the peer and the resources of the modem that run out for a while.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::OutOfMemory
+                | Self::WouldBlock
+                | Self::NetworkDown
+                | Self::NetworkUnreachable
+                | Self::NetworkReset
+                | Self::ConnectionAborted
+                | Self::ConnectionReset
+                | Self::NotConnected
+                | Self::TimedOut
+                | Self::NoBufferSpace
+                | Self::ConnectionRefused
+                | Self::HostDown
+                | Self::HostUnreachable
+                | Self::Already
+                | Self::InProgress
+                | Self::Canceled
+        )
+    }
+}
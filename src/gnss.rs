@@ -1,14 +1,16 @@
-use crate::{error::Error, log, to_nb_result, Modem, SocketState};
+use crate::{
+    backend::{ModemBackend, SocketHandle, SocketKind},
+    error::Error,
+    log, to_nb_result, Modem, SocketState,
+};
 use embedded_nal::nb;
 
-pub use nrfxlib::gnss::{DeleteMask, GnssData, NmeaMask};
-
-impl Modem {
+impl<B: ModemBackend> Modem<B> {
     pub fn gnss_socket(&mut self) -> Result<GnssSocket, Error> {
         log::debug!("Creating GNSS socket");
 
         Ok(GnssSocket {
-            inner: nrfxlib::gnss::GnssSocket::new()?,
+            inner: self.backend.socket(SocketKind::Gnss)?,
             state: SocketState::Closed,
         })
     }
@@ -28,11 +30,7 @@ impl Modem {
         new_state.active_gnss_sockets += 1;
         self.change_state(new_state)?;
 
-        socket.inner.set_fix_interval(options.fix_interval)?;
-        socket.inner.set_fix_retry(options.fix_retry)?;
-        socket.inner.set_nmea_mask(options.nmea_mask)?;
-
-        socket.inner.start(options.delete_mask)?;
+        self.backend.gnss_start(socket.inner, &options)?;
         socket.state = SocketState::Connected;
 
         log::debug!("Connected GNSS socket");
@@ -47,7 +45,7 @@ impl Modem {
             return nb::Result::Err(nb::Error::Other(Error::SocketClosed));
         }
 
-        let fix = to_nb_result(socket.inner.get_fix())?;
+        let fix = to_nb_result(self.backend.gnss_receive(socket.inner))?;
 
         match fix {
             Some(fix) => Ok(fix),
@@ -59,18 +57,19 @@ impl Modem {
         log::debug!("Closing GNSS socket");
 
         socket.state = SocketState::Closed;
+        let close_result = self.backend.close(socket.inner);
         drop(socket);
 
         let mut new_state = self.state.clone();
         new_state.active_gnss_sockets -= 1;
         self.change_state(new_state)?;
 
-        Ok(())
+        close_result
     }
 }
 
 pub struct GnssSocket {
-    inner: nrfxlib::gnss::GnssSocket,
+    inner: SocketHandle,
     state: SocketState,
 }
 
@@ -100,3 +99,214 @@ impl Default for GnssOptions {
         }
     }
 }
+
+/// Represents a position or NMEA string from the GNSS subsystem
+#[derive(Clone)]
+pub enum GnssData {
+    /// An NMEA formatted string, beginning with '$'.
+    Nmea {
+        /// A non-null terminated buffer of ASCII bytes
+        buffer: [u8; 83],
+        /// The number of valid bytes in `buffer`
+        length: usize,
+    },
+    /// Position, time and satellite information
+    Position(GnssPosition),
+    /// The assistance data the GNSS subsystem is asking for
+    Agps(AgpsRequest),
+}
+
+impl GnssData {
+    /// Returns true if this fix is valid (i.e. is a position frame, AND has the valid flag set).
+    pub fn is_valid(&self) -> bool {
+        match self {
+            GnssData::Nmea { .. } => false,
+            GnssData::Position(p) => p.is_valid(),
+            GnssData::Agps(_) => false,
+        }
+    }
+}
+
+impl core::fmt::Debug for GnssData {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            GnssData::Nmea { buffer, length } => f
+                .debug_struct("GnssData")
+                .field(
+                    "nmea",
+                    &core::str::from_utf8(&buffer[..*length]).unwrap_or("<invalid>"),
+                )
+                .finish(),
+            GnssData::Position(p) => f.debug_struct("GnssData").field("position", p).finish(),
+            GnssData::Agps(a) => f.debug_struct("GnssData").field("agps", a).finish(),
+        }
+    }
+}
+
+/// A position fix of the GNSS subsystem
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GnssPosition {
+    /// Latitude in degrees
+    pub latitude: f64,
+    /// Longitude in degrees
+    pub longitude: f64,
+    /// Altitude above the WGS-84 ellipsoid in meters
+    pub altitude: f32,
+    /// Accuracy (2D 1-sigma) in meters
+    pub accuracy: f32,
+    /// Horizontal speed in meters per second
+    pub speed: f32,
+    /// Heading of the user movement in degrees
+    pub heading: f32,
+    /// The UTC date and time of the fix
+    pub datetime: GnssDateTime,
+    /// Position dilution of precision
+    pub pdop: f32,
+    /// Horizontal dilution of precision
+    pub hdop: f32,
+    /// Vertical dilution of precision
+    pub vdop: f32,
+    /// Time dilution of precision
+    pub tdop: f32,
+    /// Bit mask of the PVT flags. Bit 0 indicates a valid fix.
+    pub flags: u8,
+    /// The satellites that are being tracked
+    pub sv: [GnssSatellite; 12],
+}
+
+impl GnssPosition {
+    /// Returns true if the fix valid flag is set
+    pub fn is_valid(&self) -> bool {
+        self.flags & 1 != 0
+    }
+}
+
+/// The UTC date and time of a GNSS fix
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GnssDateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub seconds: u8,
+    pub ms: u16,
+}
+
+/// Tracking information about a single satellite
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GnssSatellite {
+    /// The id of the satellite. 0 means that this entry is not in use.
+    pub sv: u16,
+    /// The signal type
+    pub signal: u8,
+    /// Carrier to noise ratio in 0.1 dB-Hz
+    pub cn0: u16,
+    /// Elevation in degrees
+    pub elevation: i16,
+    /// Azimuth in degrees
+    pub azimuth: i16,
+    /// Bit mask of the satellite flags
+    pub flags: u8,
+}
+
+/// The assistance data the GNSS subsystem is asking for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AgpsRequest {
+    /// Bit mask of the satellites that need ephemerides
+    pub sv_mask_ephe: u32,
+    /// Bit mask of the satellites that need almanacs
+    pub sv_mask_alm: u32,
+    /// Bit mask of the other requested data
+    pub data_flags: u32,
+}
+
+/// Specifies which NMEA fields you want from the GNSS sub-system.
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct NmeaMask(u16);
+
+impl NmeaMask {
+    /// Create a new NmeaMask, which selects no NMEA fields.
+    pub fn new() -> Self {
+        NmeaMask(0)
+    }
+
+    /// Enable a particular NMEA field type in this mask.
+    pub fn set(self, field: NmeaField) -> Self {
+        NmeaMask(self.0 | field as u16)
+    }
+
+    /// Disable a particular NMEA field type in this mask.
+    pub fn clear(self, field: NmeaField) -> Self {
+        NmeaMask(self.0 & !(field as u16))
+    }
+
+    /// Convert to an integer, for the socket to consume.
+    pub fn as_u16(self) -> u16 {
+        self.0
+    }
+}
+
+/// The specific fields you can enable or disable in an [NmeaMask].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u16)]
+pub enum NmeaField {
+    /// Enables Global Positioning System Fix Data.
+    GpsFixData = 1 << 0,
+    /// Enables Geographic Position Latitude/Longitude and time.
+    LatLongTime = 1 << 1,
+    /// Enables DOP and active satellites.
+    DopAndActiveSatellites = 1 << 2,
+    /// Enables Satellites in view.
+    SatellitesInView = 1 << 3,
+    /// Enables Recommended minimum specific GPS/Transit data.
+    RecommendedMinimumSpecificFixData = 1 << 4,
+}
+
+/// Specifies which non-volatile fields you want to delete before starting the GNSS.
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DeleteMask(u32);
+
+impl DeleteMask {
+    /// Create a new DeleteMask, which selects nothing to be deleted.
+    pub fn new() -> Self {
+        DeleteMask(0)
+    }
+
+    /// Mark a particular field as requiring deletion.
+    pub fn set(self, field: DeleteField) -> Self {
+        DeleteMask(self.0 | field as u32)
+    }
+
+    /// Unmark a particular field as requiring deletion.
+    pub fn clear(self, field: DeleteField) -> Self {
+        DeleteMask(self.0 & !(field as u32))
+    }
+
+    /// Convert to an integer, for the socket to consume.
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+/// The specific fields you can enable or disable in a [DeleteMask].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u32)]
+pub enum DeleteField {
+    /// Bit 0 denotes ephemerides data.
+    Ephemerides = 1 << 0,
+    /// Bit 1 denotes almanac data (excluding leap second and ionospheric correction parameters).
+    Almanac = 1 << 1,
+    /// Bit 2 denotes ionospheric correction parameters data.
+    IonosphericCorrection = 1 << 2,
+    /// Bit 3 denotes last good fix (the last position) data.
+    LastGoodFix = 1 << 3,
+    /// Bit 4 denotes GPS time-of-week (TOW) data.
+    TimeOfWeek = 1 << 4,
+    /// Bit 5 denotes GPS week number data.
+    WeekNumber = 1 << 5,
+    /// Bit 6 denotes leap second (UTC parameters) data.
+    LeapSecond = 1 << 6,
+    /// Bit 7 denotes local clock (TCXO) frequency offset data.
+    LocalClockFrequencyOffset = 1 << 7,
+}
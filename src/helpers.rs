@@ -1,37 +1,11 @@
 use crate::{
     at::AtSocket,
+    backend::ModemBackend,
     gnss::{GnssOptions, GnssSocket},
     lte::LteSocket,
     Modem,
 };
 use embedded_nal::{nb, SocketAddr, TcpClientStack, UdpClientStack};
-use nrfxlib::Pollable;
-
-/// Perform a non-blocking write on the socket.
-pub fn send(socket: &impl Pollable, buf: &[u8]) -> Result<Option<usize>, nrfxlib::Error> {
-    let length = buf.len();
-    let ptr = buf.as_ptr();
-    let result = unsafe {
-        nrfxlib_sys::nrf_send(
-            socket.get_fd(),
-            ptr as *const _,
-            length as u32,
-            nrfxlib_sys::NRF_MSG_DONTWAIT as i32,
-        )
-    };
-    if result == -1 && nrfxlib::get_last_error() == nrfxlib_sys::NRF_EAGAIN as i32 {
-        // This is EAGAIN
-        Ok(None)
-    } else if result < 0 {
-        Err(nrfxlib::Error::Nordic(
-            "send",
-            result as i32,
-            nrfxlib::get_last_error(),
-        ))
-    } else {
-        Ok(Some(result as usize))
-    }
-}
 
 /// Creates a new socket, lets it connect, hands it over to the given function, closes the socket and then returns the function result.
 /// This makes sure that closing the socket is not forgotten.
@@ -82,9 +56,10 @@ where
 
 /// Creates a new socket, lets it connect, hands it over to the given function, closes the socket and then returns the function result.
 /// This makes sure that closing the socket is not forgotten.
-pub fn deferred_at_socket<F, R, E>(net: &mut Modem, function: F) -> Result<R, E>
+pub fn deferred_at_socket<B, F, R, E>(net: &mut Modem<B>, function: F) -> Result<R, E>
 where
-    F: FnOnce(&mut Modem, &mut AtSocket) -> Result<R, E>,
+    B: ModemBackend,
+    F: FnOnce(&mut Modem<B>, &mut AtSocket) -> Result<R, E>,
     E: From<crate::error::Error>,
 {
     let mut socket = net.at_socket()?;
@@ -101,9 +76,10 @@ where
 
 /// Creates a new socket, lets it connect, hands it over to the given function, closes the socket and then returns the function result.
 /// This makes sure that closing the socket is not forgotten.
-pub fn deferred_lte_socket<F, R, E>(net: &mut Modem, function: F) -> Result<R, E>
+pub fn deferred_lte_socket<B, F, R, E>(net: &mut Modem<B>, function: F) -> Result<R, E>
 where
-    F: FnOnce(&mut Modem, &mut LteSocket) -> Result<R, E>,
+    B: ModemBackend,
+    F: FnOnce(&mut Modem<B>, &mut LteSocket) -> Result<R, E>,
     E: From<crate::error::Error>,
 {
     let mut socket = net.lte_socket()?;
@@ -119,13 +95,14 @@ where
 
 /// Creates a new socket, lets it connect, hands it over to the given function, closes the socket and then returns the function result.
 /// This makes sure that closing the socket is not forgotten.
-pub fn deferred_gnss_socket<F, R, E>(
-    net: &mut Modem,
+pub fn deferred_gnss_socket<B, F, R, E>(
+    net: &mut Modem<B>,
     options: GnssOptions,
     function: F,
 ) -> Result<R, E>
 where
-    F: FnOnce(&mut Modem, &mut GnssSocket) -> Result<R, E>,
+    B: ModemBackend,
+    F: FnOnce(&mut Modem<B>, &mut GnssSocket) -> Result<R, E>,
     E: From<crate::error::Error>,
 {
     let mut socket = net.gnss_socket()?;
@@ -1,42 +1,111 @@
 #![doc = include_str!("../README.md")]
 #![no_std]
 
+#[cfg(feature = "sim")]
+extern crate std;
+
+use backend::ModemBackend;
+#[cfg(feature = "nrfxlib")]
+use backend::NrfxlibBackend;
+use command::{ReadRegistration, SetSystemMode};
 use embedded_nal::nb;
 use error::Error;
+use power::LtePowerConfig;
+use registration::RegistrationState;
 
+#[cfg(feature = "async")]
+pub mod async_nal;
 pub mod at;
+pub mod backend;
+pub mod command;
+pub mod credentials;
 pub mod dns;
+pub mod dtls;
+pub mod edrx;
 pub mod error;
 pub mod gnss;
 pub mod helpers;
 pub mod log;
 pub mod lte;
+pub mod pdn;
+pub mod power;
+pub mod psm;
+pub mod registration;
+pub mod shared;
+#[cfg(feature = "sim")]
+pub mod sim;
 pub mod tcp;
+pub mod tls;
 pub mod udp;
+pub mod urc;
 
 pub use embedded_nal;
-pub use nrfxlib::{application_irq_handler, ipc_irq_handler, trace_irq_handler};
+#[cfg(feature = "async")]
+pub use embedded_nal_async;
+#[cfg(all(feature = "nrfxlib", not(feature = "async")))]
+pub use nrfxlib::ipc_irq_handler;
+#[cfg(feature = "nrfxlib")]
+pub use nrfxlib::{application_irq_handler, trace_irq_handler};
+
+/// Call this from the IPC interrupt handler.
+///
+/// Besides running the interrupt handler of nrfxlib, this wakes the async tasks that wait for the modem.
+#[cfg(all(feature = "nrfxlib", feature = "async"))]
+pub fn ipc_irq_handler() {
+    nrfxlib::ipc_irq_handler();
+    backend::wake_modem_tasks();
+}
 
-pub type GpsPowerCallback = fn(bool, &mut Modem) -> Result<(), Error>;
+pub type GpsPowerCallback<
+    #[cfg(feature = "nrfxlib")] B = NrfxlibBackend,
+    #[cfg(not(feature = "nrfxlib"))] B,
+> = fn(bool, &mut Modem<B>) -> Result<(), Error>;
 
-pub struct Modem {
+pub struct Modem<
+    #[cfg(feature = "nrfxlib")] B: ModemBackend = NrfxlibBackend,
+    #[cfg(not(feature = "nrfxlib"))] B: ModemBackend,
+> {
+    backend: B,
     state: ModemState,
-    gps_power_callback: GpsPowerCallback,
+    gps_power_callback: GpsPowerCallback<B>,
+    lte_power_config: LtePowerConfig,
+    system_mode: SystemMode,
+    dns: dns::DnsState,
 }
 
-impl Modem {
+#[cfg(feature = "nrfxlib")]
+impl Modem<NrfxlibBackend> {
     pub fn new(
         gps_power_callback: Option<GpsPowerCallback>,
         mode: SystemMode,
+        lte_power_config: LtePowerConfig,
     ) -> Result<Self, Error> {
-        nrfxlib::init()?;
-        nrfxlib::modem::off()?;
+        Self::with_backend(
+            NrfxlibBackend::new(),
+            gps_power_callback,
+            mode,
+            lte_power_config,
+        )
+    }
+}
 
-        // nrfxlib::modem::set_system_mode(nrfxlib::modem::SystemMode::LteMAndGnss)?;
+impl<B: ModemBackend> Modem<B> {
+    /// Create a modem that runs on top of the given backend
+    pub fn with_backend(
+        mut backend: B,
+        gps_power_callback: Option<GpsPowerCallback<B>>,
+        mode: SystemMode,
+        lte_power_config: LtePowerConfig,
+    ) -> Result<Self, Error> {
+        backend.init()?;
 
         let mut modem = Self {
+            backend,
             state: ModemState::default(),
             gps_power_callback: gps_power_callback.unwrap_or(|_, _| Ok(())),
+            lte_power_config,
+            system_mode: mode,
+            dns: Default::default(),
         };
 
         modem.set_system_mode(mode)?;
@@ -44,6 +113,11 @@ impl Modem {
         Ok(modem)
     }
 
+    /// Get access to the backend the modem runs on
+    pub fn backend(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
     pub fn debug(&self) -> impl core::fmt::Debug {
         self.state.clone()
     }
@@ -53,55 +127,34 @@ impl Modem {
             return Err(Error::InvalidConfiguration);
         }
 
-        let mut at = self.at_socket()?;
-
-        let execute_result = (|| {
-            self.at_connect(&mut at)?;
-            let mut buffer = [0; 32];
-            let data = mode.create_at_command(&mut buffer)?;
-            self.at_send_raw(&mut at, data)?;
-            Ok(())
-        })();
+        self.execute(&SetSystemMode(mode))?;
+        self.system_mode = mode;
 
-        self.at_close(at)?;
-
-        match execute_result {
-            Err(Error::NrfModem(nrfxlib::Error::AtError(nrfxlib::AtError::CmeError(518)))) => {
-                Err(Error::NotAllowedInActiveState)
-            }
-            Err(Error::NrfModem(nrfxlib::Error::AtError(nrfxlib::AtError::CmeError(522)))) => {
-                Err(Error::InvalidBandConfiguration)
-            }
-            result => result,
-        }
+        Ok(())
     }
 
     fn change_state(&mut self, new_state: ModemState) -> Result<(), Error> {
         log::debug!("New state: {:?}", new_state);
 
         // Check what the LTE state should be
-        match (self.state.active_lte_sockets, new_state.active_lte_sockets) {
+        match (self.state.lte_users(), new_state.lte_users()) {
             // Staying turned off
             (0, 0) => {}
             // Turning on
             (0, _) => {
                 log::debug!("Turning on modem lte");
-                // Set Ultra low power mode.
-                nrfxlib::at::send_at_command("AT%XDATAPRFL=0", |_| {})?;
-                // Set UICC low power mode
-                nrfxlib::at::send_at_command("AT+CEPPI=1", |_| {})?;
-                // Set Power Saving Mode (PSM)
-                nrfxlib::at::send_at_command("AT+CPSMS=1", |_| {})?;
+                // Set the data profile, UICC power saving and PSM
+                self.apply_lte_power_config()?;
                 // Activate LTE without changing GNSS, this also activates UICC
-                nrfxlib::at::send_at_command("AT+CFUN=21", |_| {})?;
+                self.send_at_command("AT+CFUN=21", |_| {})?;
             }
             // Turning off
             (_, 0) => {
                 log::debug!("Turning off modem lte");
                 // Deactivate LTE without changing GNSS
-                nrfxlib::at::send_at_command("AT+CFUN=20", |_| {})?;
+                self.send_at_command("AT+CFUN=20", |_| {})?;
                 // Deactivate UICC (Universal Integrated Circuit Card)
-                nrfxlib::at::send_at_command("AT+CFUN=40", |_| {})?;
+                self.send_at_command("AT+CFUN=40", |_| {})?;
             }
             // Staying turned on
             (_, _) => {}
@@ -119,14 +172,14 @@ impl Modem {
                 log::debug!("Turning on modem gnss");
                 (self.gps_power_callback)(true, self)?;
                 // Activate GNSS without changing LTE
-                nrfxlib::at::send_at_command("AT+CFUN=31", |_| {})?;
+                self.send_at_command("AT+CFUN=31", |_| {})?;
             }
             // Turning off
             (_, 0) => {
                 log::debug!("Turning off modem gnss");
                 // Deactivate GNSS without changing LTE
                 (self.gps_power_callback)(false, self)?;
-                nrfxlib::at::send_at_command("AT+CFUN=30", |_| {})?;
+                self.send_at_command("AT+CFUN=30", |_| {})?;
             }
             // Staying turned on
             (_, _) => {}
@@ -139,30 +192,22 @@ impl Modem {
     fn wait_for_lte(&mut self) -> nb::Result<(), Error> {
         log::trace!("Waiting for LTE");
 
-        let mut values = None;
-        to_nb_result(nrfxlib::at::send_at_command("AT+CEREG?", |val| {
-            values = Some(
-                at_commands::parser::CommandParser::parse(val.as_bytes())
-                    .expect_identifier(b"+CEREG:")
-                    .expect_int_parameter()
-                    .expect_int_parameter()
-                    .finish(),
-            );
-        }))?;
-
-        if let Some(values) = values {
-            let (_, stat) = to_nb_result(values)?;
-            log::trace!("LTE status: {stat}");
-            match stat {
-                1 | 5 => Ok(()),
-                0 | 2 | 4 => Err(nb::Error::WouldBlock),
-                3 => to_nb_result(Err(Error::LteRegistrationDenied)),
-                90 => to_nb_result(Err(Error::SimFailure)),
-                _ => to_nb_result(Err(Error::UnexpectedAtResponse)),
-            }
-        } else {
-            to_nb_result(Err(Error::NoAtResponse))
-        }
+        let status = to_nb_result(self.execute(&ReadRegistration))?;
+        lte_wait_result(status.state)
+    }
+}
+
+/// Whether waiting for LTE is done with the registration state, or has to go on
+fn lte_wait_result(state: RegistrationState) -> nb::Result<(), Error> {
+    log::trace!("LTE status: {:?}", state);
+
+    match state {
+        RegistrationState::RegisteredHome | RegistrationState::RegisteredRoaming => Ok(()),
+        RegistrationState::NotRegistered
+        | RegistrationState::Searching
+        | RegistrationState::Unknown => Err(nb::Error::WouldBlock),
+        RegistrationState::Denied => to_nb_result(Err(Error::LteRegistrationDenied)),
+        RegistrationState::UiccFailure => to_nb_result(Err(Error::SimFailure)),
     }
 }
 
@@ -174,13 +219,26 @@ fn to_nb_result<T, E1, E2: From<E1>>(r: Result<T, E1>) -> nb::Result<T, E2> {
 struct ModemState {
     active_lte_sockets: u32,
     active_gnss_sockets: u32,
+    /// The PDN connections activated with [Modem::activate_pdn], one bit per cid
+    active_pdns: u32,
+}
+
+impl ModemState {
+    /// The sockets and PDN connections that need LTE to be on
+    fn lte_users(&self) -> u32 {
+        self.active_lte_sockets + self.active_pdns.count_ones()
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 enum SocketState {
     Closed,
     WaitingForLte,
+    /// Bound to a local port, but not connected
+    Bound,
     Connected,
+    /// A TCP socket that accepts incoming connections
+    Listening,
 }
 
 impl SocketState {
@@ -197,6 +255,20 @@ impl SocketState {
     fn is_closed(&self) -> bool {
         matches!(self, Self::Closed)
     }
+
+    /// Returns `true` if the socket state is [`Bound`].
+    ///
+    /// [`Bound`]: SocketState::Bound
+    fn is_bound(&self) -> bool {
+        matches!(self, Self::Bound)
+    }
+
+    /// Returns `true` if the socket state is [`Listening`].
+    ///
+    /// [`Listening`]: SocketState::Listening
+    fn is_listening(&self) -> bool {
+        matches!(self, Self::Listening)
+    }
 }
 
 /// Identifies which radios in the nRF9160 should be active
@@ -243,15 +315,4 @@ impl SystemMode {
             }
         }
     }
-
-    fn create_at_command<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a [u8], Error> {
-        at_commands::builder::CommandBuilder::create_set(buffer, true)
-            .named("%XSYSTEMMODE")
-            .with_int_parameter(self.lte_support as u8)
-            .with_int_parameter(self.nbiot_support as u8)
-            .with_int_parameter(self.gnss_support as u8)
-            .with_int_parameter(self.preference as u8)
-            .finish()
-            .map_err(|e| Error::BufferTooSmall(Some(e)))
-    }
 }
@@ -3,10 +3,14 @@ use core::{
     str::FromStr,
 };
 
-use crate::{at::AtSocket, error::Error, log, to_nb_result, Modem, SocketState};
+use crate::{
+    at::AtSocket, backend::ModemBackend, command::ReadClock, error::Error, log, to_nb_result,
+    Modem, SocketState,
+};
+use at_commands::parser::CommandParser;
 use embedded_nal::nb;
 
-impl Modem {
+impl<B: ModemBackend> Modem<B> {
     /// Get an AT socket, but where the LTE is also active at the same time
     pub fn lte_socket(&mut self) -> Result<LteSocket, Error> {
         log::debug!("Creating LTE socket");
@@ -46,14 +50,7 @@ impl Modem {
     }
 
     pub fn lte_read_clock(&mut self, socket: &mut LteSocket) -> Result<ClockTime, Error> {
-        self.at_send(&mut socket.inner, "AT+CCLK?")?;
-
-        let mut result = Err(Error::NoAtResponse);
-        self.at_poll_response(&mut socket.inner, |s| {
-            result = s.parse();
-        })?;
-
-        result
+        self.execute_on(&mut socket.inner, &ReadClock)
     }
 
     pub fn lte_close(&mut self, mut socket: LteSocket) -> Result<(), Error> {
@@ -93,6 +90,7 @@ impl DerefMut for LteSocket {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ClockTime {
     pub year: u16,
     pub month: u8,
@@ -105,41 +103,47 @@ pub struct ClockTime {
 impl FromStr for ClockTime {
     type Err = Error;
 
+    /// Parse the response to `AT+CCLK?`, e.g. `+CCLK: "18/12/06,22:10:00+08"`
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() != 29 {
-            return Err(Error::UnexpectedAtResponse);
+        let (time,) = CommandParser::parse(s.trim().as_bytes())
+            .expect_identifier(b"+CCLK:")
+            .expect_string_parameter()
+            .finish()?;
+
+        // The time zone after the seconds is left out
+        let (date, time) = time.split_once(',').ok_or(Error::UnexpectedAtResponse)?;
+        let time = time
+            .split(['+', '-'])
+            .next()
+            .ok_or(Error::UnexpectedAtResponse)?;
+
+        let mut date = date.split('/').map(|value| value.parse::<u8>());
+        let mut time = time.split(':').map(|value| value.parse::<u8>());
+
+        match (
+            date.next(),
+            date.next(),
+            date.next(),
+            time.next(),
+            time.next(),
+            time.next(),
+        ) {
+            (
+                Some(Ok(year)),
+                Some(Ok(month)),
+                Some(Ok(day)),
+                Some(Ok(hour)),
+                Some(Ok(minute)),
+                Some(Ok(sec)),
+            ) => Ok(ClockTime {
+                year: 2000 + year as u16,
+                month,
+                day,
+                hour,
+                minute,
+                sec,
+            }),
+            _ => Err(Error::UnexpectedAtResponse),
         }
-
-        // Typical response: "+CCLK: \"18/12/06,22:10:00+08\""
-        let time_str = &s[8..25];
-
-        let year: i32 = time_str[0..2]
-            .parse()
-            .map_err(|_| Error::UnexpectedAtResponse)?;
-        let month: u32 = time_str[3..5]
-            .parse()
-            .map_err(|_| Error::UnexpectedAtResponse)?;
-        let day: u32 = time_str[6..8]
-            .parse()
-            .map_err(|_| Error::UnexpectedAtResponse)?;
-
-        let hour: u32 = time_str[9..11]
-            .parse()
-            .map_err(|_| Error::UnexpectedAtResponse)?;
-        let minute: u32 = time_str[12..14]
-            .parse()
-            .map_err(|_| Error::UnexpectedAtResponse)?;
-        let sec: u32 = time_str[15..17]
-            .parse()
-            .map_err(|_| Error::UnexpectedAtResponse)?;
-
-        Ok(ClockTime {
-            year: (year + 2000) as _,
-            month: month as _,
-            day: day as _,
-            hour: hour as _,
-            minute: minute as _,
-            sec: sec as _,
-        })
     }
 }
@@ -0,0 +1,443 @@
+//! Packet Data Network (PDN) connections
+//!
+//! A PDN connection is defined by a PDP context, which has a context id (cid), an APN and an address family.
+//! Context 0 is the default context that is set up with the attach to the network.
+//! Other contexts can be defined for private APNs and are activated when they are needed.
+//!
+//! ```ignore
+//! modem.define_pdp_context(&PdpContext {
+//!     cid: 1,
+//!     family: PdnFamily::Ipv4v6,
+//!     apn: "company.apn",
+//! })?;
+//! modem.set_pdn_authentication(1, PdnAuthentication::Chap { username: "user", password: "secret" })?;
+//! // This turns LTE on, and it stays on until the PDN connection is deactivated again
+//! nb::block!(modem.activate_pdn(1))?;
+//!
+//! // Management traffic goes over the private APN, everything else over the default PDN
+//! let mut socket = TcpClientStack::socket(&mut modem)?;
+//! modem.bind_to_pdn(&socket, "company.apn")?;
+//! nb::block!(TcpClientStack::connect(&mut modem, &mut socket, management_server))?;
+//! ```
+
+use crate::{
+    backend::{ModemBackend, SocketHandle},
+    dtls::DtlsSocket,
+    error::Error,
+    log,
+    tcp::TcpSocket,
+    tls::TlsSocket,
+    to_nb_result,
+    udp::UdpSocket,
+    Modem,
+};
+use at_commands::{builder::CommandBuilder, parser::CommandParser};
+use embedded_nal::nb;
+
+/// The bit of the cid in the active PDN connections of the modem state
+fn pdn_bit(cid: u8) -> Result<u32, Error> {
+    1u32.checked_shl(u32::from(cid))
+        .ok_or(Error::InvalidConfiguration)
+}
+
+/// The address family of a PDN connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdnFamily {
+    Ipv4,
+    Ipv6,
+    /// Both IPv4 and IPv6, if the network allows it
+    Ipv4v6,
+    NonIp,
+}
+
+impl PdnFamily {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ipv4 => "IP",
+            Self::Ipv6 => "IPV6",
+            Self::Ipv4v6 => "IPV4V6",
+            Self::NonIp => "Non-IP",
+        }
+    }
+
+    fn parse(family: &str) -> Result<Self, Error> {
+        match family {
+            "IP" => Ok(Self::Ipv4),
+            "IPV6" => Ok(Self::Ipv6),
+            "IPV4V6" => Ok(Self::Ipv4v6),
+            "Non-IP" => Ok(Self::NonIp),
+            _ => Err(Error::UnexpectedAtResponse),
+        }
+    }
+}
+
+/// A PDP context, which defines a PDN connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PdpContext<'a> {
+    /// The context id. 0 is the default context.
+    pub cid: u8,
+    pub family: PdnFamily,
+    /// The access point name
+    pub apn: &'a str,
+}
+
+impl<'a> PdpContext<'a> {
+    /// Parse a line like `+CGDCONT: 1,"IPV4V6","company.apn","10.0.0.2",0,0`
+    fn parse(line: &'a str) -> Result<Self, Error> {
+        let (cid, family, apn) = CommandParser::parse(line.trim().as_bytes())
+            .expect_identifier(b"+CGDCONT:")
+            .expect_int_parameter()
+            .expect_string_parameter()
+            .expect_optional_string_parameter()
+            .finish()?;
+
+        Ok(Self {
+            cid: u8::try_from(cid).map_err(|_| Error::UnexpectedAtResponse)?,
+            family: PdnFamily::parse(family)?,
+            apn: apn.unwrap_or_default(),
+        })
+    }
+}
+
+/// The authentication of a PDN connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdnAuthentication<'a> {
+    None,
+    Pap {
+        username: &'a str,
+        password: &'a str,
+    },
+    Chap {
+        username: &'a str,
+        password: &'a str,
+    },
+}
+
+/// Why the network activated a different family than was asked for, as reported by [PdnEvent::Activated]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdnActivationReason {
+    Ipv4Only = 0,
+    Ipv6Only = 1,
+    /// Only one family per PDN connection is allowed
+    SingleAddressBearersOnly = 2,
+}
+
+/// A `+CGEV` notification about the PDN connections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdnEvent {
+    /// The PDN connection of the context has been activated
+    Activated {
+        cid: u8,
+        reason: Option<PdnActivationReason>,
+    },
+    /// The PDN connection of the context has been deactivated, by the network or by the modem
+    Deactivated { cid: u8, by_network: bool },
+    /// The IPv6 link of the context is up
+    Ipv6LinkUp { cid: u8 },
+    /// The modem has been detached from the network, by the network or by the modem
+    Detached { by_network: bool },
+}
+
+impl PdnEvent {
+    /// Parse a `+CGEV` notification, e.g. `+CGEV: ME PDN ACT 1` or `+CGEV: NW PDN DEACT 1`.
+    ///
+    /// The notifications must be turned on with [Modem::enable_pdn_events].
+    pub fn parse_notification(line: &str) -> Result<Self, Error> {
+        let event = line
+            .trim()
+            .strip_prefix("+CGEV:")
+            .ok_or(Error::UnexpectedAtResponse)?
+            .trim();
+
+        let parse_cid = |cid: &str| cid.trim().parse().map_err(|_| Error::UnexpectedAtResponse);
+
+        if let Some(parameters) = event.strip_prefix("ME PDN ACT ") {
+            let (cid, reason) = match parameters.split_once(',') {
+                Some((cid, reason)) => (cid, Some(reason)),
+                None => (parameters, None),
+            };
+            let reason = match reason.map(str::trim) {
+                None => None,
+                Some("0") => Some(PdnActivationReason::Ipv4Only),
+                Some("1") => Some(PdnActivationReason::Ipv6Only),
+                Some("2") => Some(PdnActivationReason::SingleAddressBearersOnly),
+                Some(_) => return Err(Error::UnexpectedAtResponse),
+            };
+
+            Ok(Self::Activated {
+                cid: parse_cid(cid)?,
+                reason,
+            })
+        } else if let Some(cid) = event.strip_prefix("ME PDN DEACT ") {
+            Ok(Self::Deactivated {
+                cid: parse_cid(cid)?,
+                by_network: false,
+            })
+        } else if let Some(cid) = event.strip_prefix("NW PDN DEACT ") {
+            Ok(Self::Deactivated {
+                cid: parse_cid(cid)?,
+                by_network: true,
+            })
+        } else if let Some(cid) = event.strip_prefix("IPV6 ") {
+            Ok(Self::Ipv6LinkUp {
+                cid: parse_cid(cid)?,
+            })
+        } else if event == "ME DETACH" {
+            Ok(Self::Detached { by_network: false })
+        } else if event == "NW DETACH" {
+            Ok(Self::Detached { by_network: true })
+        } else {
+            Err(Error::UnexpectedAtResponse)
+        }
+    }
+}
+
+/// A socket that can be bound to a PDN with [Modem::bind_to_pdn]
+pub trait PdnSocket: sealed::Sealed {}
+
+mod sealed {
+    use crate::backend::SocketHandle;
+
+    pub trait Sealed {
+        /// The handle of the socket, or `None` if it has been connected or bound already
+        fn unused_handle(&self) -> Option<SocketHandle>;
+    }
+}
+
+impl PdnSocket for TcpSocket {}
+impl sealed::Sealed for TcpSocket {
+    fn unused_handle(&self) -> Option<SocketHandle> {
+        self.is_unused().then(|| self.handle())
+    }
+}
+
+impl PdnSocket for UdpSocket {}
+impl sealed::Sealed for UdpSocket {
+    fn unused_handle(&self) -> Option<SocketHandle> {
+        self.is_unused().then(|| self.handle())
+    }
+}
+
+impl PdnSocket for TlsSocket {}
+impl sealed::Sealed for TlsSocket {
+    fn unused_handle(&self) -> Option<SocketHandle> {
+        self.inner().unused_handle()
+    }
+}
+
+impl PdnSocket for DtlsSocket {}
+impl sealed::Sealed for DtlsSocket {
+    fn unused_handle(&self) -> Option<SocketHandle> {
+        self.inner().unused_handle()
+    }
+}
+
+/// Values are quoted in the AT commands, so they can't contain quotes themselves
+fn check_quotable(value: &str) -> Result<(), Error> {
+    if value.contains('"') {
+        return Err(Error::InvalidConfiguration);
+    }
+
+    Ok(())
+}
+
+impl<B: ModemBackend> Modem<B> {
+    /// Define a PDP context with `AT+CGDCONT`, replacing the one with the same cid
+    pub fn define_pdp_context(&mut self, context: &PdpContext) -> Result<(), Error> {
+        log::debug!("Defining PDP context {:?}", context);
+        check_quotable(context.apn)?;
+
+        let mut buffer = [0; 128];
+        let command = CommandBuilder::create_set(&mut buffer, true)
+            .named("+CGDCONT")
+            .with_int_parameter(context.cid)
+            .with_string_parameter(context.family.as_str())
+            .with_string_parameter(context.apn)
+            .finish()
+            .map_err(|e| Error::BufferTooSmall(Some(e)))?;
+
+        self.send_at_command(command, |_| {})
+    }
+
+    /// Remove the PDP context with the cid. The default context can't be removed.
+    pub fn delete_pdp_context(&mut self, cid: u8) -> Result<(), Error> {
+        log::debug!("Deleting PDP context {}", cid);
+
+        let mut buffer = [0; 32];
+        let command = CommandBuilder::create_set(&mut buffer, true)
+            .named("+CGDCONT")
+            .with_int_parameter(cid)
+            .finish()
+            .map_err(|e| Error::BufferTooSmall(Some(e)))?;
+
+        self.send_at_command(command, |_| {})
+    }
+
+    /// Call the callback for every PDP context that is defined
+    pub fn pdp_contexts<F>(&mut self, mut callback: F) -> Result<(), Error>
+    where
+        F: FnMut(PdpContext),
+    {
+        let mut result = Ok(());
+
+        self.send_at_command("AT+CGDCONT?", |line| {
+            if result.is_ok() && line.starts_with("+CGDCONT:") {
+                result = PdpContext::parse(line).map(&mut callback);
+            }
+        })?;
+
+        result
+    }
+
+    /// Set the authentication of the PDN connection of the context with `AT+CGAUTH`
+    pub fn set_pdn_authentication(
+        &mut self,
+        cid: u8,
+        authentication: PdnAuthentication,
+    ) -> Result<(), Error> {
+        log::debug!("Setting the authentication of PDP context {}", cid);
+
+        let mut buffer = [0; 160];
+        let builder = CommandBuilder::create_set(&mut buffer, true)
+            .named("+CGAUTH")
+            .with_int_parameter(cid);
+        let builder = match authentication {
+            PdnAuthentication::None => builder.with_int_parameter(0),
+            PdnAuthentication::Pap { username, password } => {
+                check_quotable(username)?;
+                check_quotable(password)?;
+                builder
+                    .with_int_parameter(1)
+                    .with_string_parameter(username)
+                    .with_string_parameter(password)
+            }
+            PdnAuthentication::Chap { username, password } => {
+                check_quotable(username)?;
+                check_quotable(password)?;
+                builder
+                    .with_int_parameter(2)
+                    .with_string_parameter(username)
+                    .with_string_parameter(password)
+            }
+        };
+        let command = builder
+            .finish()
+            .map_err(|e| Error::BufferTooSmall(Some(e)))?;
+
+        self.send_at_command(command, |_| {})
+    }
+
+    /// Activate the PDN connection of the context with `AT+CGACT`.
+    ///
+    /// LTE is turned on if needed and this returns `WouldBlock` until the modem is registered.
+    /// The PDN connection keeps LTE on until it is deactivated with [Self::deactivate_pdn],
+    /// also when it has been deactivated by the network.
+    pub fn activate_pdn(&mut self, cid: u8) -> nb::Result<(), Error> {
+        log::debug!("Activating PDN {}", cid);
+
+        let bit = pdn_bit(cid)?;
+        if self.state.active_pdns & bit == 0 {
+            let mut new_state = self.state.clone();
+            new_state.active_pdns |= bit;
+            to_nb_result(self.change_state(new_state))?;
+        }
+
+        let result = match self.wait_for_lte() {
+            Err(nb::Error::WouldBlock) => return Err(nb::Error::WouldBlock),
+            Err(nb::Error::Other(e)) => Err(e),
+            Ok(()) => self.set_pdn_active(cid, true),
+        };
+
+        if let Err(e) = result {
+            self.release_pdn(bit)?;
+            return Err(nb::Error::Other(e));
+        }
+
+        Ok(())
+    }
+
+    /// Deactivate the PDN connection of the context with `AT+CGACT`.
+    ///
+    /// LTE is turned off if nothing else uses it anymore.
+    pub fn deactivate_pdn(&mut self, cid: u8) -> Result<(), Error> {
+        log::debug!("Deactivating PDN {}", cid);
+
+        let bit = pdn_bit(cid)?;
+        self.set_pdn_active(cid, false)?;
+        self.release_pdn(bit)
+    }
+
+    /// Stop counting the PDN connection as a user of LTE
+    fn release_pdn(&mut self, bit: u32) -> Result<(), Error> {
+        if self.state.active_pdns & bit == 0 {
+            return Ok(());
+        }
+
+        let mut new_state = self.state.clone();
+        new_state.active_pdns &= !bit;
+        self.change_state(new_state)
+    }
+
+    fn set_pdn_active(&mut self, cid: u8, active: bool) -> Result<(), Error> {
+        let mut buffer = [0; 32];
+        let command = CommandBuilder::create_set(&mut buffer, true)
+            .named("+CGACT")
+            .with_int_parameter(active as u8)
+            .with_int_parameter(cid)
+            .finish()
+            .map_err(|e| Error::BufferTooSmall(Some(e)))?;
+
+        self.send_at_command(command, |_| {})
+    }
+
+    /// Call the callback with the cid of every PDP context and whether its PDN connection is active
+    pub fn pdn_states<F>(&mut self, mut callback: F) -> Result<(), Error>
+    where
+        F: FnMut(u8, bool),
+    {
+        let mut result = Ok(());
+
+        self.send_at_command("AT+CGACT?", |line| {
+            if result.is_ok() && line.starts_with("+CGACT:") {
+                result = CommandParser::parse(line.trim().as_bytes())
+                    .expect_identifier(b"+CGACT:")
+                    .expect_int_parameter()
+                    .expect_int_parameter()
+                    .finish()
+                    .map_err(Error::from)
+                    .and_then(|(cid, state)| {
+                        let cid = u8::try_from(cid).map_err(|_| Error::UnexpectedAtResponse)?;
+                        callback(cid, state == 1);
+                        Ok(())
+                    });
+            }
+        })?;
+
+        result
+    }
+
+    /// Let the socket use the PDN connection of the APN instead of the default one,
+    /// e.g. the APN of a [PdpContext] that has been defined with [Modem::define_pdp_context].
+    ///
+    /// This must be done before the socket is connected or bound, and the PDN must be active by then.
+    pub fn bind_to_pdn<S: PdnSocket>(&mut self, socket: &S, apn: &str) -> Result<(), Error> {
+        log::debug!("Binding socket to PDN {}", apn);
+
+        if apn.is_empty() {
+            return Err(Error::InvalidConfiguration);
+        }
+
+        let handle = socket.unused_handle().ok_or(Error::SocketAlreadyOpen)?;
+        self.backend.bind_to_pdn(handle, apn)
+    }
+
+    /// Turn on the `+CGEV` notifications, which can be parsed with [PdnEvent::parse_notification]
+    pub fn enable_pdn_events(&mut self) -> Result<(), Error> {
+        self.send_at_command("AT+CGEREP=1", |_| {})
+    }
+
+    /// Turn off the `+CGEV` notifications
+    pub fn disable_pdn_events(&mut self) -> Result<(), Error> {
+        self.send_at_command("AT+CGEREP=0", |_| {})
+    }
+}
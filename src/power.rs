@@ -0,0 +1,101 @@
+//! Configuration of the power saving features the modem uses when LTE is turned on
+
+use crate::{backend::ModemBackend, error::Error, log, psm::PsmConfig, Modem};
+use at_commands::builder::CommandBuilder;
+
+/// The power settings that are applied every time LTE is turned on
+///
+/// The default is the most power efficient configuration: the ultra low power data profile,
+/// UICC power saving and PSM with the timers the modem chooses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LtePowerConfig {
+    /// The data profile that tells the modem what to optimize for
+    pub data_profile: DataProfile,
+    /// Let the UICC (the SIM) go to a low power state when it's not used
+    pub uicc_power_saving: bool,
+    /// Request Power Saving Mode (PSM) from the network. `None` disables PSM.
+    pub psm: Option<PsmConfig>,
+    /// AT commands that are sent after the other settings and before LTE is activated
+    pub extra_commands: &'static [&'static str],
+}
+
+impl Default for LtePowerConfig {
+    fn default() -> Self {
+        Self {
+            data_profile: DataProfile::UltraLowPower,
+            uicc_power_saving: true,
+            psm: Some(PsmConfig::default()),
+            extra_commands: &[],
+        }
+    }
+}
+
+/// The data profile of the modem (`AT%XDATAPRFL`)
+///
+/// Based on: <https://infocenter.nordicsemi.com/topic/ref_at_commands/REF/at_commands/mob_termination_ctrl_status/xdataprfl.html>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataProfile {
+    UltraLowPower = 0,
+    LowPower = 1,
+    Normal = 2,
+    Performance = 3,
+    HighPerformance = 4,
+}
+
+impl<B: ModemBackend> Modem<B> {
+    /// The power settings that are applied when LTE is turned on
+    pub fn lte_power_config(&self) -> &LtePowerConfig {
+        &self.lte_power_config
+    }
+
+    /// Change the power settings that are applied when LTE is turned on.
+    ///
+    /// If LTE is on right now, the settings are applied immediately.
+    pub fn set_lte_power_config(&mut self, config: LtePowerConfig) -> Result<(), Error> {
+        self.lte_power_config = config;
+
+        if self.state.lte_users() > 0 {
+            self.apply_lte_power_config()?;
+        }
+
+        Ok(())
+    }
+
+    /// Send the AT commands of the power config to the modem
+    pub(crate) fn apply_lte_power_config(&mut self) -> Result<(), Error> {
+        let config = self.lte_power_config;
+        log::debug!("Applying LTE power config: {:?}", config);
+
+        let mut buffer = [0; 64];
+
+        let command = CommandBuilder::create_set(&mut buffer, true)
+            .named("%XDATAPRFL")
+            .with_int_parameter(config.data_profile as u8)
+            .finish()
+            .map_err(|e| Error::BufferTooSmall(Some(e)))?;
+        self.send_at_command(command, |_| {})?;
+
+        let command = CommandBuilder::create_set(&mut buffer, true)
+            .named("+CEPPI")
+            .with_int_parameter(config.uicc_power_saving as u8)
+            .finish()
+            .map_err(|e| Error::BufferTooSmall(Some(e)))?;
+        self.send_at_command(command, |_| {})?;
+
+        let command = match config.psm {
+            None => CommandBuilder::create_set(&mut buffer, true)
+                .named("+CPSMS")
+                .with_int_parameter(0)
+                .finish()
+                .map_err(|e| Error::BufferTooSmall(Some(e)))?,
+            Some(psm) => psm.create_at_command(&mut buffer)?,
+        };
+        self.send_at_command(command, |_| {})?;
+
+        for command in config.extra_commands {
+            self.send_at_command(*command, |_| {})?;
+        }
+
+        Ok(())
+    }
+}
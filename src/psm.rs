@@ -0,0 +1,213 @@
+//! Power Saving Mode (PSM) timers
+//!
+//! The timers are exchanged with the network as the GPRS timer bit strings of 3GPP TS 24.008.
+//! This module converts between those and [Duration]s.
+
+use crate::{backend::ModemBackend, error::Error, log, Modem};
+use at_commands::{builder::CommandBuilder, parser::CommandParser};
+use core::time::Duration;
+
+/// The PSM timers that are requested from the network
+///
+/// A timer that is `None` is left for the modem to choose.
+/// Because the network only supports a limited set of values,
+/// the requested timers are rounded up to the nearest value that can be encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PsmConfig {
+    /// How often the device reports to the network while in PSM (T3412 extended)
+    pub periodic_tau: Option<Duration>,
+    /// How long the device stays reachable after going idle before it enters PSM (T3324)
+    pub active_time: Option<Duration>,
+}
+
+/// The PSM timers the network granted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PsmStatus {
+    /// How often the device has to report to the network. `None` if the network didn't give a value.
+    pub periodic_tau: Option<Duration>,
+    /// How long the device stays reachable before it enters PSM. `None` if PSM is not granted.
+    pub active_time: Option<Duration>,
+}
+
+impl PsmStatus {
+    /// Returns `true` if the network granted PSM
+    pub fn is_enabled(&self) -> bool {
+        self.active_time.is_some()
+    }
+}
+
+/// The units of the GPRS timer 3 (T3412 extended), by the value of bits 6 to 8
+pub(crate) const TIMER_3_UNITS: [(u8, u64); 7] = [
+    (0b011, 2),
+    (0b100, 30),
+    (0b101, 60),
+    (0b001, 60 * 60),
+    (0b000, 10 * 60),
+    (0b010, 10 * 60 * 60),
+    (0b110, 320 * 60 * 60),
+];
+
+/// The units of the GPRS timer 2 (T3324), and of the legacy T3412, by the value of bits 6 to 8
+pub(crate) const TIMER_2_UNITS: [(u8, u64); 3] = [(0b000, 2), (0b001, 60), (0b010, 6 * 60)];
+
+/// The unit bits that indicate the timer is deactivated
+const TIMER_DEACTIVATED: u8 = 0b111;
+
+/// Encode the duration as a GPRS timer bit string with the smallest unit that fits
+fn encode_timer<'a>(
+    duration: Duration,
+    units: &[(u8, u64)],
+    buffer: &'a mut [u8; 8],
+) -> Result<&'a str, Error> {
+    let seconds = duration.as_secs() + (duration.subsec_nanos() > 0) as u64;
+
+    // Pick the unit that gets closest to the requested duration
+    let (unit, value) = units
+        .iter()
+        .map(|(unit, unit_seconds)| (*unit, seconds.div_ceil(*unit_seconds), *unit_seconds))
+        .filter(|(_, value, _)| *value <= 0b11111)
+        .min_by_key(|(_, value, unit_seconds)| value * unit_seconds)
+        .map(|(unit, value, _)| (unit, value))
+        .ok_or(Error::InvalidConfiguration)?;
+
+    let byte = (unit << 5) | value as u8;
+    for (i, bit) in buffer.iter_mut().enumerate() {
+        *bit = if byte & (0x80 >> i) != 0 { b'1' } else { b'0' };
+    }
+
+    Ok(core::str::from_utf8(buffer).unwrap())
+}
+
+/// Decode a GPRS timer bit string. Returns `None` if the timer is deactivated or absent.
+pub(crate) fn decode_timer(
+    bits: Option<&str>,
+    units: &[(u8, u64)],
+) -> Result<Option<Duration>, Error> {
+    let bits = match bits {
+        None | Some("") => return Ok(None),
+        Some(bits) => bits,
+    };
+
+    if bits.len() != 8 {
+        return Err(Error::UnexpectedAtResponse);
+    }
+    let byte = u8::from_str_radix(bits, 2).map_err(|_| Error::UnexpectedAtResponse)?;
+
+    let unit = byte >> 5;
+    let value = (byte & 0b11111) as u64;
+
+    if unit == TIMER_DEACTIVATED {
+        return Ok(None);
+    }
+
+    units
+        .iter()
+        .find(|(u, _)| *u == unit)
+        .map(|(_, unit_seconds)| Some(Duration::from_secs(value * unit_seconds)))
+        .ok_or(Error::UnexpectedAtResponse)
+}
+
+impl PsmConfig {
+    /// Create the `AT+CPSMS` command that requests these timers
+    pub(crate) fn create_at_command<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a [u8], Error> {
+        let mut tau_buffer = [0; 8];
+        let mut active_time_buffer = [0; 8];
+
+        let periodic_tau = self
+            .periodic_tau
+            .map(|tau| encode_timer(tau, &TIMER_3_UNITS, &mut tau_buffer))
+            .transpose()?;
+        let active_time = self
+            .active_time
+            .map(|time| encode_timer(time, &TIMER_2_UNITS, &mut active_time_buffer))
+            .transpose()?;
+
+        let builder = CommandBuilder::create_set(buffer, true)
+            .named("+CPSMS")
+            .with_int_parameter(1);
+
+        let builder = if periodic_tau.is_some() || active_time.is_some() {
+            builder
+                .with_empty_parameter()
+                .with_empty_parameter()
+                .with_optional_string_parameter(periodic_tau)
+                .with_optional_string_parameter(active_time)
+        } else {
+            builder
+        };
+
+        builder.finish().map_err(|e| Error::BufferTooSmall(Some(e)))
+    }
+}
+
+impl<B: ModemBackend> Modem<B> {
+    /// Enable PSM and request the given timers from the network.
+    ///
+    /// The timers are also requested every time LTE is turned on.
+    /// Use [psm_status](Self::psm_status) to see what the network granted.
+    pub fn set_psm(&mut self, config: PsmConfig) -> Result<(), Error> {
+        log::debug!("Setting PSM: {:?}", config);
+
+        let mut buffer = [0; 64];
+        let command = config.create_at_command(&mut buffer)?;
+        self.send_at_command(command, |_| {})?;
+
+        self.lte_power_config.psm = Some(config);
+
+        Ok(())
+    }
+
+    /// Get the PSM timers the network granted, using `AT%XMONITOR`.
+    ///
+    /// When the modem is not registered to a network, nothing is granted.
+    pub fn psm_status(&mut self) -> Result<PsmStatus, Error> {
+        let mut status = None;
+
+        self.send_at_command("AT%XMONITOR", |line| {
+            if status.is_some() || !line.starts_with("%XMONITOR:") {
+                return;
+            }
+
+            let result = CommandParser::parse(line.as_bytes())
+                .expect_identifier(b"%XMONITOR:")
+                .expect_int_parameter()
+                .expect_optional_string_parameter()
+                .expect_optional_string_parameter()
+                .expect_optional_string_parameter()
+                .expect_optional_string_parameter()
+                .expect_optional_int_parameter()
+                .expect_optional_int_parameter()
+                .expect_optional_string_parameter()
+                .expect_optional_int_parameter()
+                .expect_optional_int_parameter()
+                .expect_optional_int_parameter()
+                .expect_optional_int_parameter()
+                .expect_optional_string_parameter()
+                .expect_optional_string_parameter()
+                .expect_optional_string_parameter()
+                .expect_optional_string_parameter()
+                .finish()
+                .map_err(Error::from)
+                .and_then(
+                    |(_, _, _, _, _, _, _, _, _, _, _, _, _, active_time, tau_ext, tau)| {
+                        let periodic_tau = match decode_timer(tau_ext, &TIMER_3_UNITS)? {
+                            Some(tau) => Some(tau),
+                            None => decode_timer(tau, &TIMER_2_UNITS)?,
+                        };
+
+                        Ok(PsmStatus {
+                            periodic_tau,
+                            active_time: decode_timer(active_time, &TIMER_2_UNITS)?,
+                        })
+                    },
+                );
+
+            status = Some(result);
+        })?;
+
+        let status = status.ok_or(Error::NoAtResponse)??;
+        log::debug!("PSM status: {:?}", status);
+
+        Ok(status)
+    }
+}
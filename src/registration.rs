@@ -0,0 +1,199 @@
+//! The network registration status, from `+CEREG`
+//!
+//! With `+CEREG` mode 5, the modem reports the cell, the access technology, why the network rejected
+//! the registration and the PSM timers of the network, besides whether it's registered.
+
+use crate::{
+    backend::ModemBackend,
+    command::{ReadRegistration, ReadRegistrationMode, SetRegistrationMode},
+    error::Error,
+    log,
+    psm::{decode_timer, TIMER_2_UNITS, TIMER_3_UNITS},
+    Modem,
+};
+use at_commands::parser::CommandParser;
+use core::time::Duration;
+
+/// Whether the modem is registered to the network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationState {
+    /// Not registered, and not searching for a network to register to
+    NotRegistered = 0,
+    /// Registered to the home network
+    RegisteredHome = 1,
+    /// Not registered, but searching for a network to register to
+    Searching = 2,
+    /// The network denied the registration
+    Denied = 3,
+    Unknown = 4,
+    /// Registered to a roaming network
+    RegisteredRoaming = 5,
+    /// Not registered, because the UICC failed
+    UiccFailure = 90,
+}
+
+impl RegistrationState {
+    /// Returns `true` if the modem is registered to a home or roaming network
+    pub fn is_registered(&self) -> bool {
+        matches!(self, Self::RegisteredHome | Self::RegisteredRoaming)
+    }
+
+    fn from_int(value: i32) -> Result<Self, Error> {
+        match value {
+            0 => Ok(Self::NotRegistered),
+            1 => Ok(Self::RegisteredHome),
+            2 => Ok(Self::Searching),
+            3 => Ok(Self::Denied),
+            4 => Ok(Self::Unknown),
+            5 => Ok(Self::RegisteredRoaming),
+            90 => Ok(Self::UiccFailure),
+            _ => Err(Error::UnexpectedAtResponse),
+        }
+    }
+}
+
+/// The access technology of the cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessTechnology {
+    LteM = 7,
+    NbIot = 9,
+}
+
+impl AccessTechnology {
+    fn from_int(value: i32) -> Result<Self, Error> {
+        match value {
+            7 => Ok(Self::LteM),
+            9 => Ok(Self::NbIot),
+            _ => Err(Error::UnexpectedAtResponse),
+        }
+    }
+}
+
+/// Why the network rejected the registration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectCause {
+    /// An EMM cause of 3GPP TS 24.301 annex A
+    Emm(u16),
+    /// A cause that is specific to the modem
+    ManufacturerSpecific(u16),
+}
+
+/// The registration status of the modem, as reported by `+CEREG` mode 5
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegistrationStatus {
+    pub state: RegistrationState,
+    /// The tracking area code of the cell
+    pub tracking_area_code: Option<u16>,
+    /// The E-UTRAN cell id
+    pub cell_id: Option<u32>,
+    pub access_technology: Option<AccessTechnology>,
+    /// Why the network rejected the last registration, if it did
+    pub reject_cause: Option<RejectCause>,
+    /// The active time the network granted. `None` if PSM is not granted.
+    pub active_time: Option<Duration>,
+    /// The periodic TAU the network granted
+    pub periodic_tau: Option<Duration>,
+}
+
+impl RegistrationStatus {
+    /// Parse a `+CEREG` notification of mode 5, e.g. `+CEREG: 1,"0140","00011B07",7,,,"11100000","11100000"`.
+    ///
+    /// This also parses the notifications of lower modes, which leave out the values they don't report.
+    pub fn parse_notification(line: &str) -> Result<Self, Error> {
+        let parameters = line
+            .trim()
+            .strip_prefix("+CEREG:")
+            .ok_or(Error::UnexpectedAtResponse)?;
+
+        Self::parse_parameters(parameters)
+    }
+
+    /// Parse the response to `AT+CEREG?`, which starts with the mode
+    pub(crate) fn parse_read_response(line: &str) -> Result<Self, Error> {
+        let (_mode, parameters) = line
+            .trim()
+            .strip_prefix("+CEREG:")
+            .and_then(|parameters| parameters.split_once(','))
+            .ok_or(Error::UnexpectedAtResponse)?;
+
+        Self::parse_parameters(parameters)
+    }
+
+    /// Parse `<stat>[,[<tac>],[<ci>],[<AcT>][,<cause_type>],[<reject_cause>][,[<Active-Time>],[<Periodic-TAU>]]]]`
+    fn parse_parameters(parameters: &str) -> Result<Self, Error> {
+        let (
+            state,
+            tracking_area_code,
+            cell_id,
+            access_technology,
+            cause_type,
+            reject_cause,
+            active_time,
+            periodic_tau,
+        ) = CommandParser::parse(parameters.trim().as_bytes())
+            .expect_int_parameter()
+            .expect_optional_string_parameter()
+            .expect_optional_string_parameter()
+            .expect_optional_int_parameter()
+            .expect_optional_int_parameter()
+            .expect_optional_int_parameter()
+            .expect_optional_string_parameter()
+            .expect_optional_string_parameter()
+            .finish()?;
+
+        let reject_cause = match (cause_type, reject_cause) {
+            (_, None) => None,
+            (Some(1), Some(cause)) => Some(RejectCause::ManufacturerSpecific(cause_value(cause)?)),
+            (_, Some(cause)) => Some(RejectCause::Emm(cause_value(cause)?)),
+        };
+
+        Ok(Self {
+            state: RegistrationState::from_int(state)?,
+            tracking_area_code: non_empty(tracking_area_code)
+                .map(|tac| u16::from_str_radix(tac, 16))
+                .transpose()
+                .map_err(|_| Error::UnexpectedAtResponse)?,
+            cell_id: non_empty(cell_id)
+                .map(|ci| u32::from_str_radix(ci, 16))
+                .transpose()
+                .map_err(|_| Error::UnexpectedAtResponse)?,
+            access_technology: access_technology
+                .map(AccessTechnology::from_int)
+                .transpose()?,
+            reject_cause,
+            active_time: decode_timer(active_time, &TIMER_2_UNITS)?,
+            periodic_tau: decode_timer(periodic_tau, &TIMER_3_UNITS)?,
+        })
+    }
+}
+
+fn cause_value(cause: i32) -> Result<u16, Error> {
+    u16::try_from(cause).map_err(|_| Error::UnexpectedAtResponse)
+}
+
+/// Treat an empty parameter like an absent one
+fn non_empty(value: Option<&str>) -> Option<&str> {
+    value.filter(|value| !value.is_empty())
+}
+
+impl<B: ModemBackend> Modem<B> {
+    /// Read the registration status with `AT+CEREG?` in mode 5.
+    ///
+    /// If the `+CEREG` notifications are in another mode, they're set to mode 5 for the read and set back afterwards.
+    pub fn registration_status(&mut self) -> Result<RegistrationStatus, Error> {
+        let mode = self.execute(&ReadRegistrationMode)?;
+
+        let status = if mode == 5 {
+            self.execute(&ReadRegistration)?
+        } else {
+            self.execute(&SetRegistrationMode(5))?;
+            let status = self.execute(&ReadRegistration);
+            self.execute(&SetRegistrationMode(mode))?;
+            status?
+        };
+
+        log::debug!("Registration status: {:?}", status);
+
+        Ok(status)
+    }
+}
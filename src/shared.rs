@@ -0,0 +1,181 @@
+//! Sockets that close themselves when they are dropped.
+//!
+//! The sockets of the [Modem] must be closed explicitly, because closing needs the modem.
+//! A [SharedModem] can be borrowed by its sockets, so an [OwnedSocket] can close itself when it is dropped,
+//! turning off the radios when they are not needed anymore.
+//!
+//! ```ignore
+//! let modem = SharedModem::new(modem);
+//!
+//! let mut socket = modem.tcp_socket()?;
+//! socket.with(|modem, socket| nb::block!(modem.connect(socket, remote)))?;
+//! socket.with(|modem, socket| nb::block!(modem.send(socket, b"hello")))?;
+//! // The socket is closed here, even when one of the calls above returned early
+//! ```
+
+use crate::{
+    at::AtSocket,
+    backend::ModemBackend,
+    dtls::{DtlsOptions, DtlsSocket},
+    error::Error,
+    gnss::GnssSocket,
+    log,
+    lte::LteSocket,
+    tcp::TcpSocket,
+    tls::{TlsOptions, TlsSocket},
+    udp::UdpSocket,
+    Modem,
+};
+use core::cell::RefCell;
+use embedded_nal::{TcpClientStack, UdpClientStack};
+
+/// A [Modem] that can be shared with the sockets that are created with it
+pub struct SharedModem<B: ModemBackend> {
+    modem: RefCell<Modem<B>>,
+}
+
+impl<B: ModemBackend> SharedModem<B> {
+    pub fn new(modem: Modem<B>) -> Self {
+        Self {
+            modem: RefCell::new(modem),
+        }
+    }
+
+    /// Get the modem back
+    pub fn into_inner(self) -> Modem<B> {
+        self.modem.into_inner()
+    }
+
+    /// Use the modem directly.
+    ///
+    /// Sockets of this modem must not be dropped in the function, because they need the modem to close.
+    pub fn with_modem<R>(&self, f: impl FnOnce(&mut Modem<B>) -> R) -> R {
+        f(&mut self.modem.borrow_mut())
+    }
+
+    /// Create a TCP socket that closes itself when it's dropped
+    pub fn tcp_socket(&self) -> Result<OwnedSocket<'_, B, TcpSocket>, Error> {
+        self.own(TcpClientStack::socket)
+    }
+
+    /// Create a TLS socket that closes itself when it's dropped
+    pub fn tls_socket(&self, options: &TlsOptions) -> Result<OwnedSocket<'_, B, TlsSocket>, Error> {
+        self.own(|modem| modem.tls_socket(options))
+    }
+
+    /// Create a UDP socket that closes itself when it's dropped
+    pub fn udp_socket(&self) -> Result<OwnedSocket<'_, B, UdpSocket>, Error> {
+        self.own(UdpClientStack::socket)
+    }
+
+    /// Create a DTLS socket that closes itself when it's dropped
+    pub fn dtls_socket(
+        &self,
+        options: &DtlsOptions,
+    ) -> Result<OwnedSocket<'_, B, DtlsSocket>, Error> {
+        self.own(|modem| modem.dtls_socket(options))
+    }
+
+    /// Create an AT socket that closes itself when it's dropped
+    pub fn at_socket(&self) -> Result<OwnedSocket<'_, B, AtSocket>, Error> {
+        self.own(Modem::at_socket)
+    }
+
+    /// Create an LTE socket that closes itself when it's dropped
+    pub fn lte_socket(&self) -> Result<OwnedSocket<'_, B, LteSocket>, Error> {
+        self.own(Modem::lte_socket)
+    }
+
+    /// Create a GNSS socket that closes itself when it's dropped
+    pub fn gnss_socket(&self) -> Result<OwnedSocket<'_, B, GnssSocket>, Error> {
+        self.own(Modem::gnss_socket)
+    }
+
+    fn own<S: ModemSocket<B>>(
+        &self,
+        create: impl FnOnce(&mut Modem<B>) -> Result<S, Error>,
+    ) -> Result<OwnedSocket<'_, B, S>, Error> {
+        Ok(OwnedSocket {
+            modem: self,
+            socket: Some(self.with_modem(create)?),
+        })
+    }
+}
+
+/// A socket that can be closed by the [Modem]
+pub trait ModemSocket<B: ModemBackend>: Sized {
+    fn close(self, modem: &mut Modem<B>) -> Result<(), Error>;
+}
+
+impl<B: ModemBackend> ModemSocket<B> for TcpSocket {
+    fn close(self, modem: &mut Modem<B>) -> Result<(), Error> {
+        TcpClientStack::close(modem, self)
+    }
+}
+
+impl<B: ModemBackend> ModemSocket<B> for TlsSocket {
+    fn close(self, modem: &mut Modem<B>) -> Result<(), Error> {
+        TcpClientStack::close(modem, self.into_inner())
+    }
+}
+
+impl<B: ModemBackend> ModemSocket<B> for UdpSocket {
+    fn close(self, modem: &mut Modem<B>) -> Result<(), Error> {
+        UdpClientStack::close(modem, self)
+    }
+}
+
+impl<B: ModemBackend> ModemSocket<B> for DtlsSocket {
+    fn close(self, modem: &mut Modem<B>) -> Result<(), Error> {
+        UdpClientStack::close(modem, self.into_inner())
+    }
+}
+
+impl<B: ModemBackend> ModemSocket<B> for AtSocket {
+    fn close(self, modem: &mut Modem<B>) -> Result<(), Error> {
+        modem.at_close(self)
+    }
+}
+
+impl<B: ModemBackend> ModemSocket<B> for LteSocket {
+    fn close(self, modem: &mut Modem<B>) -> Result<(), Error> {
+        modem.lte_close(self)
+    }
+}
+
+impl<B: ModemBackend> ModemSocket<B> for GnssSocket {
+    fn close(self, modem: &mut Modem<B>) -> Result<(), Error> {
+        modem.gnss_close(self)
+    }
+}
+
+/// A socket that borrows its [SharedModem] and closes itself when it's dropped
+pub struct OwnedSocket<'a, B: ModemBackend, S: ModemSocket<B>> {
+    modem: &'a SharedModem<B>,
+    // Only `None` while it's being closed
+    socket: Option<S>,
+}
+
+impl<B: ModemBackend, S: ModemSocket<B>> OwnedSocket<'_, B, S> {
+    /// Use the socket together with the modem
+    pub fn with<R>(&mut self, f: impl FnOnce(&mut Modem<B>, &mut S) -> R) -> R {
+        let socket = self.socket.as_mut().unwrap();
+        self.modem.with_modem(|modem| f(modem, socket))
+    }
+
+    /// Close the socket. Unlike dropping it, this reports errors.
+    pub fn close(mut self) -> Result<(), Error> {
+        let socket = self.socket.take().unwrap();
+        self.modem.with_modem(|modem| socket.close(modem))
+    }
+}
+
+impl<B: ModemBackend, S: ModemSocket<B>> Drop for OwnedSocket<'_, B, S> {
+    fn drop(&mut self) {
+        if let Some(socket) = self.socket.take() {
+            if let Err(_e) = self.modem.with_modem(|modem| socket.close(modem)) {
+                log::error!("Could not close socket: {:?}", _e);
+            }
+        }
+    }
+}
@@ -0,0 +1,644 @@
+//! A simulated modem backend for running the [Modem](crate::Modem) on the host.
+//!
+//! The [SimulatedModem] answers AT commands from a script and records every command it receives,
+//! so tests can check what the modem was asked to do.
+//!
+//! TCP and UDP sockets are backed by real `std::net` sockets, so they can talk to a server on localhost.
+//! Remote addresses can be redirected to such a local server with [SimulatedModem::redirect].
+//! Bound sockets use localhost, on the port they were bound to.
+//! TLS and DTLS sockets are simulated as plain TCP and UDP sockets.
+//! Their options are recorded, see [SimulatedModem::tls_configurations] and [SimulatedModem::dtls_configurations].
+//!
+//! ```
+//! use nrf_modem_nal::{
+//!     embedded_nal::nb, sim::SimulatedModem, ConnectionPreference, Modem, SystemMode,
+//! };
+//!
+//! let mut sim = SimulatedModem::new();
+//! sim.script("AT+CEREG?", "+CEREG: 0,2\r\nOK").times(3);
+//! sim.script("AT+CEREG?", "+CEREG: 0,1\r\nOK");
+//!
+//! let mut modem = Modem::with_backend(
+//!     sim,
+//!     None,
+//!     SystemMode {
+//!         lte_support: true,
+//!         nbiot_support: false,
+//!         gnss_support: true,
+//!         preference: ConnectionPreference::None,
+//!     },
+//!     Default::default(),
+//! )
+//! .unwrap();
+//!
+//! let mut lte = modem.lte_socket().unwrap();
+//! nb::block!(modem.lte_connect(&mut lte)).unwrap();
+//! modem.lte_close(lte).unwrap();
+//!
+//! assert!(modem
+//!     .backend()
+//!     .transcript()
+//!     .iter()
+//!     .any(|command| command == "AT+CFUN=21"));
+//! ```
+
+use crate::{
+    backend::{ModemBackend, SocketHandle, SocketKind},
+    dtls::DtlsOptions,
+    error::{Errno, Error},
+    gnss::{GnssData, GnssOptions},
+    tls::{PeerVerification, TlsOptions},
+};
+use embedded_nal::{AddrType, IpAddr, SocketAddr};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{ErrorKind, Read, Write},
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// A modem that answers AT commands from a script
+#[derive(Debug, Default)]
+pub struct SimulatedModem {
+    script: Vec<ScriptedResponse>,
+    transcript: Vec<String>,
+    sockets: BTreeMap<i32, SimulatedSocket>,
+    next_handle: i32,
+    hosts: Vec<(String, IpAddr)>,
+    host_errors: Vec<(String, Error)>,
+    redirects: Vec<(SocketAddr, std::net::SocketAddr)>,
+    gnss_data: VecDeque<GnssData>,
+    tls_configurations: Vec<TlsConfiguration>,
+    dtls_configurations: Vec<DtlsConfiguration>,
+    pdn_bindings: Vec<String>,
+}
+
+/// The [TlsOptions] a simulated TLS socket was configured with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfiguration {
+    pub sec_tags: Vec<u32>,
+    pub peer_verification: PeerVerification,
+    pub hostname: Option<String>,
+}
+
+impl TlsConfiguration {
+    fn new(options: &TlsOptions) -> Self {
+        Self {
+            sec_tags: options.sec_tags.to_vec(),
+            peer_verification: options.peer_verification,
+            hostname: options.hostname.map(ToString::to_string),
+        }
+    }
+}
+
+/// The [DtlsOptions] a simulated DTLS socket was configured with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DtlsConfiguration {
+    pub security: TlsConfiguration,
+}
+
+/// A response to an AT command in the script of the [SimulatedModem]
+#[derive(Debug)]
+pub struct ScriptedResponse {
+    command: String,
+    response: String,
+    remaining: Option<usize>,
+    notification: Option<String>,
+}
+
+impl ScriptedResponse {
+    /// Only give this response the given amount of times.
+    /// After that, the next matching entry of the script is used.
+    pub fn times(&mut self, amount: usize) -> &mut Self {
+        self.remaining = Some(amount);
+        self
+    }
+
+    /// Send a notification to all open AT sockets after the response, e.g. `+CEREG: 1` after `AT+CEREG=1`
+    pub fn notify(&mut self, notification: &str) -> &mut Self {
+        self.notification = Some(notification.to_string());
+        self
+    }
+}
+
+#[derive(Debug)]
+struct SimulatedSocket {
+    kind: SocketKind,
+    received: VecDeque<Vec<u8>>,
+    connection: Option<Connection>,
+    local_port: Option<u16>,
+    /// A non-blocking connect has been started
+    connecting: bool,
+}
+
+/// The host socket that backs a simulated TCP or UDP socket
+#[derive(Debug)]
+enum Connection {
+    Tcp(std::net::TcpStream),
+    Udp(std::net::UdpSocket),
+    Listener(std::net::TcpListener),
+}
+
+impl SimulatedModem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a response to the script.
+    ///
+    /// Every AT command that starts with `command` is answered with `response`.
+    /// The response should contain the final result code, e.g. `OK` or `+CME ERROR: 518`.
+    /// When multiple entries match, the earliest one that has not been used up is used.
+    /// Commands that don't match anything are answered with `OK`.
+    pub fn script(&mut self, command: &str, response: &str) -> &mut ScriptedResponse {
+        self.script.push(ScriptedResponse {
+            command: command.to_string(),
+            response: response.to_string(),
+            remaining: None,
+            notification: None,
+        });
+        self.script.last_mut().unwrap()
+    }
+
+    /// All AT commands that have been received, in order and without the line ending
+    pub fn transcript(&self) -> &[String] {
+        &self.transcript
+    }
+
+    /// Take the transcript so far, leaving an empty one behind
+    pub fn take_transcript(&mut self) -> Vec<String> {
+        core::mem::take(&mut self.transcript)
+    }
+
+    /// Let the hostname resolve to the given address
+    pub fn add_host(&mut self, hostname: &str, address: IpAddr) {
+        self.hosts.push((hostname.to_string(), address));
+    }
+
+    /// Let the next lookup of the hostname fail with the error, e.g. a transient [Errno]
+    pub fn add_host_error(&mut self, hostname: &str, error: Error) {
+        self.host_errors.push((hostname.to_string(), error));
+    }
+
+    /// Let sockets that connect to `remote` connect to `local` instead.
+    ///
+    /// This is useful to connect to a test server on localhost while the code under test
+    /// uses the address of the real server.
+    pub fn redirect(&mut self, remote: SocketAddr, local: std::net::SocketAddr) {
+        self.redirects.push((remote, local));
+    }
+
+    /// Queue data that will be received on the GNSS socket
+    pub fn push_gnss_data(&mut self, data: GnssData) {
+        self.gnss_data.push_back(data);
+    }
+
+    /// Send a notification to all open AT sockets, e.g. `+CSCON: 1`
+    pub fn push_notification(&mut self, notification: &str) {
+        let mut data = notification.as_bytes().to_vec();
+        data.extend_from_slice(b"\r\n");
+
+        for socket in self.sockets.values_mut() {
+            if socket.kind == SocketKind::At {
+                socket.received.push_back(data.clone());
+            }
+        }
+    }
+
+    /// The options of all TLS sockets that have been configured, in order
+    pub fn tls_configurations(&self) -> &[TlsConfiguration] {
+        &self.tls_configurations
+    }
+
+    /// The options of all DTLS sockets that have been configured, in order
+    pub fn dtls_configurations(&self) -> &[DtlsConfiguration] {
+        &self.dtls_configurations
+    }
+
+    /// The APNs of the PDNs sockets have been bound to, in order
+    pub fn pdn_bindings(&self) -> &[String] {
+        &self.pdn_bindings
+    }
+
+    /// The amount of sockets that have been created, but not yet closed
+    pub fn open_sockets(&self) -> usize {
+        self.sockets.len()
+    }
+
+    /// The response to the command, and the notification that follows it
+    fn respond(&mut self, command: &str) -> (String, Option<String>) {
+        let entry = self.script.iter_mut().find(|entry| {
+            entry.remaining != Some(0) && command.starts_with(entry.command.as_str())
+        });
+
+        match entry {
+            Some(entry) => {
+                if let Some(remaining) = entry.remaining.as_mut() {
+                    *remaining -= 1;
+                }
+                (entry.response.clone(), entry.notification.clone())
+            }
+            None => ("OK".to_string(), None),
+        }
+    }
+
+    fn get_socket(&mut self, socket: SocketHandle) -> Result<&mut SimulatedSocket, Error> {
+        self.sockets
+            .get_mut(&socket.0)
+            .ok_or(Error::NrfSys(Errno::BadFileDescriptor))
+    }
+
+    /// The address the modem reports for an address of the host, undoing the redirects
+    fn modem_address(&self, host: std::net::SocketAddr) -> SocketAddr {
+        self.redirects
+            .iter()
+            .find(|(_, to)| *to == host)
+            .map(|(from, _)| *from)
+            .unwrap_or_else(|| nal_address(host))
+    }
+
+    fn host_address(&self, remote: SocketAddr) -> std::net::SocketAddr {
+        self.redirects
+            .iter()
+            .find(|(from, _)| *from == remote)
+            .map(|(_, to)| *to)
+            .unwrap_or_else(|| match remote {
+                SocketAddr::V4(remote) => std::net::SocketAddr::new(
+                    std::net::Ipv4Addr::from(remote.ip().octets()).into(),
+                    remote.port(),
+                ),
+                SocketAddr::V6(remote) => std::net::SocketAddr::new(
+                    std::net::Ipv6Addr::from(remote.ip().octets()).into(),
+                    remote.port(),
+                ),
+            })
+    }
+}
+
+/// Convert an address of the host to the type of [embedded_nal]
+fn nal_address(address: std::net::SocketAddr) -> SocketAddr {
+    match address {
+        std::net::SocketAddr::V4(address) => SocketAddr::new(
+            embedded_nal::Ipv4Addr::from(address.ip().octets()).into(),
+            address.port(),
+        ),
+        std::net::SocketAddr::V6(address) => SocketAddr::new(
+            embedded_nal::Ipv6Addr::from(address.ip().octets()).into(),
+            address.port(),
+        ),
+    }
+}
+
+/// Convert an error of a host socket into the error the modem would give
+fn io_error(error: std::io::Error) -> Error {
+    let errno = match error.kind() {
+        ErrorKind::ConnectionRefused => Errno::ConnectionRefused,
+        ErrorKind::ConnectionReset => Errno::ConnectionReset,
+        ErrorKind::ConnectionAborted => Errno::ConnectionAborted,
+        ErrorKind::NotConnected => Errno::NotConnected,
+        ErrorKind::AddrInUse => Errno::AddressInUse,
+        ErrorKind::TimedOut => Errno::TimedOut,
+        _ => Errno::Io,
+    };
+
+    Error::NrfSys(errno)
+}
+
+/// Turn the result of a non-blocking operation on a host socket into the backend result
+fn nonblocking(result: std::io::Result<usize>) -> Result<Option<usize>, Error> {
+    match result {
+        Ok(length) => Ok(Some(length)),
+        Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+        Err(e) => Err(io_error(e)),
+    }
+}
+
+impl ModemBackend for SimulatedModem {
+    fn init(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn socket(&mut self, kind: SocketKind) -> Result<SocketHandle, Error> {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+
+        self.sockets.insert(
+            handle,
+            SimulatedSocket {
+                kind,
+                received: VecDeque::new(),
+                connection: None,
+                local_port: None,
+                connecting: false,
+            },
+        );
+
+        Ok(SocketHandle(handle))
+    }
+
+    fn connect_nonblocking(
+        &mut self,
+        socket: SocketHandle,
+        remote: SocketAddr,
+    ) -> Result<Option<()>, Error> {
+        // The first call only starts connecting, like the modem does
+        let simulated = self.get_socket(socket)?;
+        if !simulated.connecting {
+            simulated.connecting = true;
+            return Ok(None);
+        }
+
+        simulated.connecting = false;
+        self.connect(socket, remote).map(Some)
+    }
+
+    fn bind(&mut self, socket: SocketHandle, local_port: u16) -> Result<(), Error> {
+        let socket = self.get_socket(socket)?;
+
+        match socket.kind {
+            SocketKind::Tcp => {}
+            SocketKind::Udp => {
+                let udp = std::net::UdpSocket::bind((std::net::Ipv4Addr::LOCALHOST, local_port))
+                    .map_err(io_error)?;
+                udp.set_nonblocking(true).map_err(io_error)?;
+                socket.connection = Some(Connection::Udp(udp));
+            }
+            _ => return Err(Error::NrfSys(Errno::NotSupported)),
+        }
+
+        socket.local_port = Some(local_port);
+
+        Ok(())
+    }
+
+    fn listen(&mut self, socket: SocketHandle, _backlog: usize) -> Result<(), Error> {
+        let socket = self.get_socket(socket)?;
+
+        let (SocketKind::Tcp, Some(local_port)) = (socket.kind, socket.local_port) else {
+            return Err(Error::NrfSys(Errno::NotSupported));
+        };
+
+        let listener = std::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, local_port))
+            .map_err(io_error)?;
+        listener.set_nonblocking(true).map_err(io_error)?;
+        socket.connection = Some(Connection::Listener(listener));
+
+        Ok(())
+    }
+
+    fn accept(
+        &mut self,
+        socket: SocketHandle,
+    ) -> Result<Option<(SocketHandle, SocketAddr)>, Error> {
+        let Some(Connection::Listener(listener)) = self.get_socket(socket)?.connection.as_ref()
+        else {
+            return Err(Error::NrfSys(Errno::NotSupported));
+        };
+
+        let (stream, remote) = match listener.accept() {
+            Ok(connection) => connection,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
+            Err(e) => return Err(io_error(e)),
+        };
+        stream.set_nonblocking(true).map_err(io_error)?;
+
+        let handle = self.socket(SocketKind::Tcp)?;
+        self.get_socket(handle)?.connection = Some(Connection::Tcp(stream));
+
+        Ok(Some((handle, nal_address(remote))))
+    }
+
+    fn connect(&mut self, socket: SocketHandle, remote: SocketAddr) -> Result<(), Error> {
+        // The sockets of the modem library are IPv4 only
+        if remote.is_ipv6() {
+            return Err(Error::NrfSys(Errno::AddressFamilyNotSupported));
+        }
+
+        let address = self.host_address(remote);
+        let socket = self.get_socket(socket)?;
+
+        let connection = match socket.kind {
+            SocketKind::Tcp | SocketKind::Tls => {
+                let stream = std::net::TcpStream::connect(address).map_err(io_error)?;
+                stream.set_nonblocking(true).map_err(io_error)?;
+                Connection::Tcp(stream)
+            }
+            SocketKind::Udp | SocketKind::Dtls if socket.connection.is_some() => {
+                // Bound before, so the socket keeps its port
+                let Some(Connection::Udp(udp)) = socket.connection.take() else {
+                    return Err(Error::NrfSys(Errno::NotSupported));
+                };
+                udp.connect(address).map_err(io_error)?;
+                Connection::Udp(udp)
+            }
+            SocketKind::Udp | SocketKind::Dtls => {
+                let local: std::net::SocketAddr = if address.is_ipv4() {
+                    (std::net::Ipv4Addr::UNSPECIFIED, 0).into()
+                } else {
+                    (std::net::Ipv6Addr::UNSPECIFIED, 0).into()
+                };
+                let udp = std::net::UdpSocket::bind(local).map_err(io_error)?;
+                udp.connect(address).map_err(io_error)?;
+                udp.set_nonblocking(true).map_err(io_error)?;
+                Connection::Udp(udp)
+            }
+            _ => return Err(Error::NrfSys(Errno::NotSupported)),
+        };
+
+        socket.connection = Some(connection);
+
+        Ok(())
+    }
+
+    fn send(&mut self, handle: SocketHandle, buffer: &[u8]) -> Result<Option<usize>, Error> {
+        let socket = self.get_socket(handle)?;
+
+        match socket.connection.as_mut() {
+            Some(Connection::Tcp(stream)) => nonblocking(stream.write(buffer)),
+            Some(Connection::Udp(udp)) => nonblocking(udp.send(buffer)),
+            Some(Connection::Listener(_)) => Err(Error::NrfSys(Errno::NotConnected)),
+            None if socket.kind == SocketKind::At => self.write(handle, buffer).map(Some),
+            None => Err(Error::NrfSys(Errno::NotConnected)),
+        }
+    }
+
+    fn send_to(
+        &mut self,
+        socket: SocketHandle,
+        remote: SocketAddr,
+        buffer: &[u8],
+    ) -> Result<Option<usize>, Error> {
+        // The sockets of the modem library are IPv4 only
+        if remote.is_ipv6() {
+            return Err(Error::NrfSys(Errno::AddressFamilyNotSupported));
+        }
+
+        let address = self.host_address(remote);
+
+        // Like the modem, a UDP socket that isn't bound yet is bound to any port
+        let simulated = self.get_socket(socket)?;
+        if simulated.kind == SocketKind::Udp && simulated.connection.is_none() {
+            self.bind(socket, 0)?;
+        }
+
+        match self.get_socket(socket)?.connection.as_mut() {
+            Some(Connection::Udp(udp)) => nonblocking(udp.send_to(buffer, address)),
+            _ => Err(Error::NrfSys(Errno::NotSupported)),
+        }
+    }
+
+    fn write(&mut self, socket: SocketHandle, buffer: &[u8]) -> Result<usize, Error> {
+        if self.get_socket(socket)?.kind != SocketKind::At {
+            return Err(Error::NrfSys(Errno::NotSupported));
+        }
+
+        let command = String::from_utf8_lossy(buffer)
+            .trim_end_matches(['\r', '\n', '\0'])
+            .to_string();
+        let (response, notification) = self.respond(&command);
+        self.transcript.push(command);
+
+        let mut response = response.into_bytes();
+        response.extend_from_slice(b"\r\n");
+        self.get_socket(socket)?.received.push_back(response);
+
+        if let Some(notification) = notification {
+            self.push_notification(&notification);
+        }
+
+        Ok(buffer.len())
+    }
+
+    fn receive(&mut self, socket: SocketHandle, buffer: &mut [u8]) -> Result<Option<usize>, Error> {
+        let socket = self.get_socket(socket)?;
+
+        match socket.connection.as_mut() {
+            Some(Connection::Tcp(stream)) => return nonblocking(stream.read(buffer)),
+            Some(Connection::Udp(udp)) => return nonblocking(udp.recv(buffer)),
+            Some(Connection::Listener(_)) => return Err(Error::NrfSys(Errno::NotConnected)),
+            None => {}
+        }
+
+        match socket.received.pop_front() {
+            Some(data) => {
+                // Like a datagram, everything that doesn't fit is lost
+                let length = data.len().min(buffer.len());
+                buffer[..length].copy_from_slice(&data[..length]);
+                Ok(Some(length))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn receive_from(
+        &mut self,
+        socket: SocketHandle,
+        buffer: &mut [u8],
+    ) -> Result<Option<(usize, Option<SocketAddr>)>, Error> {
+        let result = match self.get_socket(socket)?.connection.as_mut() {
+            Some(Connection::Udp(udp)) => udp.recv_from(buffer),
+            _ => return Err(Error::NrfSys(Errno::NotSupported)),
+        };
+
+        match result {
+            Ok((length, sender)) => Ok(Some((length, Some(self.modem_address(sender))))),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    fn close(&mut self, socket: SocketHandle) -> Result<(), Error> {
+        self.sockets
+            .remove(&socket.0)
+            .map(|_| ())
+            .ok_or(Error::NrfSys(Errno::BadFileDescriptor))
+    }
+
+    fn get_addr_info<F>(
+        &mut self,
+        hostname: &str,
+        addr_type: AddrType,
+        mut callback: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(IpAddr),
+    {
+        if let Some(index) = self
+            .host_errors
+            .iter()
+            .position(|(host, _)| host == hostname)
+        {
+            return Err(self.host_errors.remove(index).1);
+        }
+
+        let mut found = false;
+        self.hosts
+            .iter()
+            .filter(|(host, _)| host == hostname)
+            .filter(|(_, address)| match addr_type {
+                AddrType::IPv4 => address.is_ipv4(),
+                AddrType::IPv6 => address.is_ipv6(),
+                AddrType::Either => true,
+            })
+            .for_each(|(_, address)| {
+                found = true;
+                callback(*address)
+            });
+
+        // Like the modem, which answers a name without addresses with an errno
+        if found {
+            Ok(())
+        } else {
+            Err(Error::NrfSys(Errno::InvalidArgument))
+        }
+    }
+
+    fn bind_to_pdn(&mut self, socket: SocketHandle, apn: &str) -> Result<(), Error> {
+        match self.get_socket(socket)?.kind {
+            SocketKind::Tcp | SocketKind::Udp | SocketKind::Tls | SocketKind::Dtls => {}
+            _ => return Err(Error::NrfSys(Errno::NotSupported)),
+        }
+
+        self.pdn_bindings.push(apn.to_string());
+
+        Ok(())
+    }
+
+    fn tls_configure(&mut self, socket: SocketHandle, options: &TlsOptions) -> Result<(), Error> {
+        if self.get_socket(socket)?.kind != SocketKind::Tls {
+            return Err(Error::NrfSys(Errno::NotSupported));
+        }
+
+        self.tls_configurations.push(TlsConfiguration::new(options));
+
+        Ok(())
+    }
+
+    fn dtls_configure(&mut self, socket: SocketHandle, options: &DtlsOptions) -> Result<(), Error> {
+        if self.get_socket(socket)?.kind != SocketKind::Dtls {
+            return Err(Error::NrfSys(Errno::NotSupported));
+        }
+
+        self.dtls_configurations.push(DtlsConfiguration {
+            security: TlsConfiguration::new(&options.security),
+        });
+
+        Ok(())
+    }
+
+    fn gnss_start(&mut self, socket: SocketHandle, _options: &GnssOptions) -> Result<(), Error> {
+        self.get_socket(socket)?;
+        Ok(())
+    }
+
+    fn gnss_receive(&mut self, socket: SocketHandle) -> Result<Option<GnssData>, Error> {
+        self.get_socket(socket)?;
+        Ok(self.gnss_data.pop_front())
+    }
+
+    /// There is no interrupt in the simulator, so the task is woken right away to try again
+    #[cfg(feature = "async")]
+    fn register_waker(&mut self, _slot: &crate::backend::WakerSlot, waker: &core::task::Waker) {
+        waker.wake_by_ref();
+    }
+}
@@ -1,44 +1,82 @@
-use crate::{error::Error, log, to_nb_result, Modem, SocketState};
-use core::fmt::Write;
+use crate::{
+    backend::{ModemBackend, SocketHandle, SocketKind},
+    error::Error,
+    log, to_nb_result, Modem, SocketState,
+};
 use embedded_nal::nb::{self};
 
-impl embedded_nal::TcpClientStack for Modem {
-    type TcpSocket = TcpSocket;
-    type Error = Error;
-
-    fn socket(&mut self) -> Result<Self::TcpSocket, Self::Error> {
-        log::debug!("Creating TCP socket");
-
+impl<B: ModemBackend> Modem<B> {
+    /// Create a stream socket of the given kind, which is then used like a TCP socket
+    pub(crate) fn stream_socket(&mut self, kind: SocketKind) -> Result<TcpSocket, Error> {
         Ok(TcpSocket {
-            inner: nrfxlib::tcp::TcpSocket::new()?,
+            inner: self.backend.socket(kind)?,
             state: SocketState::Closed,
         })
     }
 
-    fn connect(
+    /// Connect a TCP socket like [TcpClientStack::connect](embedded_nal::TcpClientStack::connect),
+    /// but return `WouldBlock` instead of blocking while the connection is made.
+    ///
+    /// This doesn't wait for the registration, so the modem must already be registered.
+    #[cfg(feature = "async")]
+    pub(crate) fn tcp_connect_nonblocking(
         &mut self,
-        socket: &mut Self::TcpSocket,
+        socket: &mut TcpSocket,
         remote: embedded_nal::SocketAddr,
-    ) -> nb::Result<(), Self::Error> {
-        log::trace!("Connecting TCP socket to {}", remote);
+    ) -> nb::Result<(), Error> {
+        log::trace!("Connecting TCP socket to {} without blocking", remote);
+
+        to_nb_result(self.tcp_turn_on_lte(socket))?;
+
+        match self.backend.connect_nonblocking(socket.inner, remote) {
+            Ok(Some(())) => {}
+            Ok(None) => return Err(nb::Error::WouldBlock),
+            Err(e) => return Err(nb::Error::Other(e)),
+        }
+        socket.state = SocketState::Connected;
+
+        log::debug!("Connected TCP socket");
+
+        Ok(())
+    }
 
+    /// Let the socket count as an LTE socket, so LTE is turned on
+    pub(crate) fn tcp_turn_on_lte(&mut self, socket: &mut TcpSocket) -> Result<(), Error> {
         if socket.state.is_connected() {
-            return nb::Result::Err(nb::Error::Other(Error::SocketAlreadyOpen));
+            return Err(Error::SocketAlreadyOpen);
         }
 
         if socket.state.is_closed() {
             let mut new_state = self.state.clone();
             new_state.active_lte_sockets += 1;
-            to_nb_result(self.change_state(new_state))?;
+            self.change_state(new_state)?;
             socket.state = SocketState::WaitingForLte;
         }
 
-        self.wait_for_lte()?;
+        Ok(())
+    }
+}
+
+impl<B: ModemBackend> embedded_nal::TcpClientStack for Modem<B> {
+    type TcpSocket = TcpSocket;
+    type Error = Error;
+
+    fn socket(&mut self) -> Result<Self::TcpSocket, Self::Error> {
+        log::debug!("Creating TCP socket");
+        self.stream_socket(SocketKind::Tcp)
+    }
+
+    fn connect(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        remote: embedded_nal::SocketAddr,
+    ) -> nb::Result<(), Self::Error> {
+        log::trace!("Connecting TCP socket to {}", remote);
 
-        let mut ip_string = heapless::String::<64>::new();
-        to_nb_result(write!(ip_string, "{}", remote.ip()))?;
+        to_nb_result(self.tcp_turn_on_lte(socket))?;
+        self.wait_for_lte()?;
 
-        to_nb_result(socket.inner.connect(&ip_string, remote.port()))?;
+        to_nb_result(self.backend.connect(socket.inner, remote))?;
         socket.state = SocketState::Connected;
 
         log::debug!("Connected TCP socket");
@@ -61,13 +99,13 @@ impl embedded_nal::TcpClientStack for Modem {
             return nb::Result::Err(nb::Error::Other(Error::SocketClosed));
         }
 
-        match crate::helpers::send(&socket.inner, buffer) {
+        match self.backend.send(socket.inner, buffer) {
             Ok(Some(amount)) => {
                 log::debug!("Sent {amount} bytes to TCP socket");
                 nb::Result::Ok(amount)
             }
             Ok(None) => nb::Result::Err(nb::Error::WouldBlock),
-            Err(e) => nb::Result::Err(nb::Error::Other(e.into())),
+            Err(e) => nb::Result::Err(nb::Error::Other(e)),
         }
     }
 
@@ -82,13 +120,13 @@ impl embedded_nal::TcpClientStack for Modem {
             return nb::Result::Err(nb::Error::Other(Error::SocketClosed));
         }
 
-        match socket.inner.recv(buffer) {
+        match self.backend.receive(socket.inner, buffer) {
             Ok(Some(amount)) => {
                 log::debug!("Received {amount} bytes from TCP socket");
                 nb::Result::Ok(amount)
             }
             Ok(None) => nb::Result::Err(nb::Error::WouldBlock),
-            Err(e) => nb::Result::Err(nb::Error::Other(e.into())),
+            Err(e) => nb::Result::Err(nb::Error::Other(e)),
         }
     }
 
@@ -98,6 +136,7 @@ impl embedded_nal::TcpClientStack for Modem {
         let socket_state = socket.state;
 
         socket.state = SocketState::Closed;
+        let close_result = self.backend.close(socket.inner);
         drop(socket);
 
         if !socket_state.is_closed() {
@@ -106,15 +145,107 @@ impl embedded_nal::TcpClientStack for Modem {
             self.change_state(new_state)?;
         }
 
+        close_result
+    }
+}
+
+/// The amount of incoming connections that can wait to be accepted
+const LISTEN_BACKLOG: usize = 1;
+
+impl<B: ModemBackend> embedded_nal::TcpFullStack for Modem<B> {
+    /// Bind the socket to the local port. This turns on LTE and blocks until the modem is registered.
+    fn bind(&mut self, socket: &mut Self::TcpSocket, local_port: u16) -> Result<(), Self::Error> {
+        log::trace!("Binding TCP socket to port {}", local_port);
+
+        // A bind that failed while waiting for LTE can be tried again
+        if socket.state.is_bound() || socket.state.is_listening() {
+            return Err(Error::SocketAlreadyOpen);
+        }
+
+        self.tcp_turn_on_lte(socket)?;
+
+        nb::block!(self.wait_for_lte())?;
+
+        self.backend.bind(socket.inner, local_port)?;
+        socket.state = SocketState::Bound;
+
+        log::debug!("Bound TCP socket to port {}", local_port);
+
+        Ok(())
+    }
+
+    fn listen(&mut self, socket: &mut Self::TcpSocket) -> Result<(), Self::Error> {
+        log::trace!("Listening on TCP socket");
+
+        match socket.state {
+            SocketState::Bound => {}
+            // Not bound, or the bind has failed
+            SocketState::Closed | SocketState::WaitingForLte => return Err(Error::SocketClosed),
+            SocketState::Connected | SocketState::Listening => {
+                return Err(Error::SocketAlreadyOpen)
+            }
+        }
+
+        self.backend.listen(socket.inner, LISTEN_BACKLOG)?;
+        socket.state = SocketState::Listening;
+
+        log::debug!("Listening on TCP socket");
+
         Ok(())
     }
+
+    /// Accept an incoming connection. The new socket keeps LTE turned on until it's closed.
+    fn accept(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+    ) -> nb::Result<(Self::TcpSocket, embedded_nal::SocketAddr), Self::Error> {
+        log::trace!("Accepting on TCP socket");
+
+        if !socket.state.is_listening() {
+            return nb::Result::Err(nb::Error::Other(Error::SocketClosed));
+        }
+
+        let (inner, remote) = match to_nb_result(self.backend.accept(socket.inner))? {
+            Some(connection) => connection,
+            None => return nb::Result::Err(nb::Error::WouldBlock),
+        };
+
+        // LTE is already on for the listening socket, so this only counts the new socket
+        let mut new_state = self.state.clone();
+        new_state.active_lte_sockets += 1;
+        if let Err(e) = self.change_state(new_state) {
+            to_nb_result(self.backend.close(inner))?;
+            return nb::Result::Err(nb::Error::Other(e));
+        }
+
+        log::debug!("Accepted TCP connection from {}", remote);
+
+        Ok((
+            TcpSocket {
+                inner,
+                state: SocketState::Connected,
+            },
+            remote,
+        ))
+    }
 }
 
 pub struct TcpSocket {
-    inner: nrfxlib::tcp::TcpSocket,
+    inner: SocketHandle,
     state: SocketState,
 }
 
+impl TcpSocket {
+    pub(crate) fn handle(&self) -> SocketHandle {
+        self.inner
+    }
+
+    /// Returns `true` if the socket hasn't been connected or bound yet
+    pub(crate) fn is_unused(&self) -> bool {
+        self.state.is_closed()
+    }
+}
+
 impl Drop for TcpSocket {
     #[track_caller]
     fn drop(&mut self) {
@@ -0,0 +1,145 @@
+//! TLS client sockets
+//!
+//! The TLS session is handled by the modem. The credentials it uses are stored in the modem
+//! under security tags, which are given to the socket with the [TlsOptions].
+//!
+//! ```ignore
+//! let options = TlsOptions {
+//!     sec_tags: &[42],
+//!     peer_verification: PeerVerification::Required,
+//!     hostname: Some("example.com"),
+//! };
+//!
+//! let mut stack = modem.tls(options);
+//! let mut socket = stack.socket()?;
+//! nb::block!(stack.connect(&mut socket, remote))?;
+//! ```
+
+use crate::{
+    backend::{ModemBackend, SocketKind},
+    error::Error,
+    log,
+    tcp::TcpSocket,
+    Modem,
+};
+use embedded_nal::{nb, TcpClientStack};
+
+/// How the certificate of the server is verified
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PeerVerification {
+    /// The certificate is not verified
+    None = 0,
+    /// The certificate is verified if the server sends one
+    Optional = 1,
+    /// The connection fails if the certificate can't be verified
+    #[default]
+    Required = 2,
+}
+
+/// The settings of a TLS socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TlsOptions<'a> {
+    /// The security tags of the credentials that may be used. The modem supports up to 7 tags.
+    pub sec_tags: &'a [u32],
+    /// How the certificate of the server is verified
+    pub peer_verification: PeerVerification,
+    /// The hostname the certificate of the server is checked against. It's also sent with SNI.
+    pub hostname: Option<&'a str>,
+}
+
+impl<B: ModemBackend> Modem<B> {
+    /// Use the modem as a [TcpClientStack] that creates TLS sockets with the given options
+    pub fn tls<'o>(&mut self, options: TlsOptions<'o>) -> TlsStack<'_, 'o, B> {
+        TlsStack {
+            modem: self,
+            options,
+        }
+    }
+
+    /// Create a TLS socket.
+    ///
+    /// The options are applied right away, so they can't be changed after the socket has been created.
+    pub fn tls_socket(&mut self, options: &TlsOptions) -> Result<TlsSocket, Error> {
+        log::debug!("Creating TLS socket");
+
+        let socket = self.stream_socket(SocketKind::Tls)?;
+
+        match self.backend.tls_configure(socket.handle(), options) {
+            Ok(()) => Ok(TlsSocket { inner: socket }),
+            Err(e) => {
+                TcpClientStack::close(self, socket)?;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// A [TcpClientStack] that creates TLS sockets, see [Modem::tls]
+pub struct TlsStack<'m, 'o, B: ModemBackend> {
+    modem: &'m mut Modem<B>,
+    options: TlsOptions<'o>,
+}
+
+impl<B: ModemBackend> TlsStack<'_, '_, B> {
+    /// Use the modem directly
+    pub fn modem(&mut self) -> &mut Modem<B> {
+        self.modem
+    }
+}
+
+impl<B: ModemBackend> TcpClientStack for TlsStack<'_, '_, B> {
+    type TcpSocket = TlsSocket;
+    type Error = Error;
+
+    fn socket(&mut self) -> Result<Self::TcpSocket, Self::Error> {
+        self.modem.tls_socket(&self.options)
+    }
+
+    /// Connect to the server and do the TLS handshake. This turns on LTE if it isn't yet.
+    fn connect(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        remote: embedded_nal::SocketAddr,
+    ) -> nb::Result<(), Self::Error> {
+        self.modem.connect(&mut socket.inner, remote)
+    }
+
+    fn is_connected(&mut self, socket: &Self::TcpSocket) -> Result<bool, Self::Error> {
+        self.modem.is_connected(&socket.inner)
+    }
+
+    fn send(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        buffer: &[u8],
+    ) -> nb::Result<usize, Self::Error> {
+        self.modem.send(&mut socket.inner, buffer)
+    }
+
+    fn receive(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        buffer: &mut [u8],
+    ) -> nb::Result<usize, Self::Error> {
+        self.modem.receive(&mut socket.inner, buffer)
+    }
+
+    fn close(&mut self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+        self.modem.close(socket.inner)
+    }
+}
+
+/// A TCP socket with TLS. It must be closed like a [TcpSocket].
+pub struct TlsSocket {
+    inner: TcpSocket,
+}
+
+impl TlsSocket {
+    pub(crate) fn inner(&self) -> &TcpSocket {
+        &self.inner
+    }
+
+    pub(crate) fn into_inner(self) -> TcpSocket {
+        self.inner
+    }
+}
@@ -1,22 +1,20 @@
-use crate::{error::Error, log, Modem, SocketState};
-use core::fmt::Write;
+use crate::{
+    backend::{ModemBackend, SocketHandle, SocketKind},
+    error::{Errno, Error},
+    log, Modem, SocketState,
+};
 use embedded_nal::{
     nb::{self},
     SocketAddr,
 };
 
-impl embedded_nal::UdpClientStack for Modem {
+impl<B: ModemBackend> embedded_nal::UdpClientStack for Modem<B> {
     type UdpSocket = UdpSocket;
     type Error = Error;
 
     fn socket(&mut self) -> Result<Self::UdpSocket, Self::Error> {
         log::debug!("Creating UDP socket");
-
-        Ok(UdpSocket {
-            inner: nrfxlib::udp::UdpSocket::new()?,
-            state: SocketState::Closed,
-            remote_address: None,
-        })
+        self.datagram_socket(SocketKind::Udp)
     }
 
     fn connect(
@@ -30,19 +28,11 @@ impl embedded_nal::UdpClientStack for Modem {
             return Err(Error::SocketAlreadyOpen);
         }
 
-        if socket.state.is_closed() {
-            let mut new_state = self.state.clone();
-            new_state.active_lte_sockets += 1;
-            self.change_state(new_state)?;
-            socket.state = SocketState::WaitingForLte;
-        }
+        self.udp_turn_on_lte(socket)?;
 
         nb::block!(self.wait_for_lte())?;
 
-        let mut ip_string = heapless::String::<64>::new();
-        write!(ip_string, "{}", remote.ip())?;
-
-        socket.inner.connect(&ip_string, remote.port())?;
+        self.backend.connect(socket.inner, remote)?;
         socket.state = SocketState::Connected;
         socket.remote_address = Some(remote);
 
@@ -54,17 +44,22 @@ impl embedded_nal::UdpClientStack for Modem {
     fn send(&mut self, socket: &mut Self::UdpSocket, buffer: &[u8]) -> nb::Result<(), Self::Error> {
         log::trace!("Sending to UDP socket");
 
-        if !socket.state.is_connected() {
-            return nb::Result::Err(nb::Error::Other(Error::SocketClosed));
+        match socket.state {
+            SocketState::Connected => {}
+            // There's no address to send to
+            SocketState::Bound => {
+                return nb::Result::Err(nb::Error::Other(Error::NrfSys(Errno::NotConnected)))
+            }
+            _ => return nb::Result::Err(nb::Error::Other(Error::SocketClosed)),
         }
 
-        match crate::helpers::send(&socket.inner, buffer) {
+        match self.backend.send(socket.inner, buffer) {
             Ok(Some(_)) => {
                 log::debug!("Sent {} bytes from UDP socket", buffer.len());
                 nb::Result::Ok(())
             }
             Ok(None) => nb::Result::Err(nb::Error::WouldBlock),
-            Err(e) => nb::Result::Err(nb::Error::Other(e.into())),
+            Err(e) => nb::Result::Err(nb::Error::Other(e)),
         }
     }
 
@@ -75,17 +70,22 @@ impl embedded_nal::UdpClientStack for Modem {
     ) -> nb::Result<(usize, embedded_nal::SocketAddr), Self::Error> {
         log::trace!("Receiving from UDP socket");
 
-        if !socket.state.is_connected() {
+        if !socket.state.is_connected() && !socket.state.is_bound() {
             return nb::Result::Err(nb::Error::Other(Error::SocketClosed));
         }
 
-        match socket.inner.recv(buffer) {
-            Ok(Some(amount)) => {
+        match self.backend.receive_from(socket.inner, buffer) {
+            Ok(Some((amount, sender))) => {
                 log::debug!("Received {amount} bytes from UDP socket");
-                nb::Result::Ok((amount, socket.remote_address.unwrap()))
+
+                // If the modem doesn't tell, it can only have come from the connected address
+                let sender = sender
+                    .or(socket.remote_address)
+                    .ok_or(nb::Error::Other(Error::AddressNotFound))?;
+                nb::Result::Ok((amount, sender))
             }
             Ok(None) => nb::Result::Err(nb::Error::WouldBlock),
-            Err(e) => nb::Result::Err(nb::Error::Other(e.into())),
+            Err(e) => nb::Result::Err(nb::Error::Other(e)),
         }
     }
 
@@ -95,6 +95,7 @@ impl embedded_nal::UdpClientStack for Modem {
         let socket_state = socket.state;
 
         socket.state = SocketState::Closed;
+        let close_result = self.backend.close(socket.inner);
         drop(socket);
 
         if !socket_state.is_closed() {
@@ -103,16 +104,94 @@ impl embedded_nal::UdpClientStack for Modem {
             self.change_state(new_state)?;
         }
 
+        close_result
+    }
+}
+
+impl<B: ModemBackend> embedded_nal::UdpFullStack for Modem<B> {
+    /// Bind the socket to the local port. This turns on LTE and blocks until the modem is registered.
+    fn bind(&mut self, socket: &mut Self::UdpSocket, local_port: u16) -> Result<(), Self::Error> {
+        log::trace!("Binding UDP socket to port {}", local_port);
+
+        if socket.state.is_connected() || socket.state.is_bound() {
+            return Err(Error::SocketAlreadyOpen);
+        }
+
+        self.udp_turn_on_lte(socket)?;
+
+        nb::block!(self.wait_for_lte())?;
+
+        self.backend.bind(socket.inner, local_port)?;
+        socket.state = SocketState::Bound;
+
+        log::debug!("Bound UDP socket to port {}", local_port);
+
+        Ok(())
+    }
+
+    fn send_to(
+        &mut self,
+        socket: &mut Self::UdpSocket,
+        remote: SocketAddr,
+        buffer: &[u8],
+    ) -> nb::Result<(), Self::Error> {
+        log::trace!("Sending to {} from UDP socket", remote);
+
+        if !socket.state.is_connected() && !socket.state.is_bound() {
+            return nb::Result::Err(nb::Error::Other(Error::SocketClosed));
+        }
+
+        match self.backend.send_to(socket.inner, remote, buffer) {
+            Ok(Some(_)) => {
+                log::debug!("Sent {} bytes to {} from UDP socket", buffer.len(), remote);
+                nb::Result::Ok(())
+            }
+            Ok(None) => nb::Result::Err(nb::Error::WouldBlock),
+            Err(e) => nb::Result::Err(nb::Error::Other(e)),
+        }
+    }
+}
+
+impl<B: ModemBackend> Modem<B> {
+    /// Create a datagram socket of the given kind, which is then used like a UDP socket
+    pub(crate) fn datagram_socket(&mut self, kind: SocketKind) -> Result<UdpSocket, Error> {
+        Ok(UdpSocket {
+            inner: self.backend.socket(kind)?,
+            state: SocketState::Closed,
+            remote_address: None,
+        })
+    }
+
+    /// Let the socket count as an LTE socket, so LTE is turned on
+    pub(crate) fn udp_turn_on_lte(&mut self, socket: &mut UdpSocket) -> Result<(), Error> {
+        if socket.state.is_closed() {
+            let mut new_state = self.state.clone();
+            new_state.active_lte_sockets += 1;
+            self.change_state(new_state)?;
+            socket.state = SocketState::WaitingForLte;
+        }
+
         Ok(())
     }
 }
 
 pub struct UdpSocket {
-    inner: nrfxlib::udp::UdpSocket,
+    inner: SocketHandle,
     state: SocketState,
     remote_address: Option<SocketAddr>,
 }
 
+impl UdpSocket {
+    pub(crate) fn handle(&self) -> SocketHandle {
+        self.inner
+    }
+
+    /// Returns `true` if the socket hasn't been connected or bound yet
+    pub(crate) fn is_unused(&self) -> bool {
+        self.state.is_closed()
+    }
+}
+
 impl Drop for UdpSocket {
     #[track_caller]
     fn drop(&mut self) {
@@ -0,0 +1,572 @@
+//! Unsolicited result codes (URCs)
+//!
+//! The modem sends notifications on the AT sockets, in between the responses to the commands.
+//! A [UrcDispatcher] owns an AT socket, tells the notifications apart from the responses and parses the known ones
+//! into a [Urc]. Every notification goes to the handlers that subscribed to its kind, or into a queue
+//! that can be polled when there are none.
+//!
+//! The notifications have to be turned on with their AT commands, e.g. `AT+CEREG=5`, `AT+CSCON=1`, `AT%XTIME=1`,
+//! `AT+CGEREP=1` or `AT%MDMEV=1`, which can be sent with [Modem::urc_send_command].
+
+use crate::{
+    at::{final_result, AtSocket},
+    backend::ModemBackend,
+    command::MAX_RESPONSE_SIZE,
+    edrx::EdrxStatus,
+    error::Error,
+    log,
+    lte::ClockTime,
+    pdn::PdnEvent,
+    registration::RegistrationStatus,
+    Modem,
+};
+use at_commands::parser::CommandParser;
+use core::str::FromStr;
+use embedded_nal::nb;
+
+/// The maximum amount of handlers a [UrcDispatcher] can have
+pub const URC_HANDLERS: usize = 4;
+/// The maximum amount of neighbor cells of a [CellMeasurement]. The other ones are left out.
+pub const NCELLMEAS_NEIGHBORS: usize = 8;
+
+/// A notification of the modem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Urc {
+    /// `+CEREG`
+    Registration(RegistrationStatus),
+    /// `+CSCON`, whether the RRC connection is in connected mode
+    SignalingConnection(bool),
+    /// `%XTIME`
+    NetworkTime(NetworkTime),
+    /// `+CGEV`
+    Pdn(PdnEvent),
+    /// `%MDMEV`
+    ModemEvent(ModemEvent),
+    /// `%NCELLMEAS`
+    CellMeasurement(CellMeasurement),
+    /// `+CEDRXP`
+    Edrx(EdrxStatus),
+}
+
+/// A function that handles the notifications of a [UrcKind]
+pub type UrcHandler = fn(&Urc);
+
+/// The kinds of [Urc], to subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UrcKind {
+    Registration,
+    SignalingConnection,
+    NetworkTime,
+    Pdn,
+    ModemEvent,
+    CellMeasurement,
+    Edrx,
+}
+
+impl Urc {
+    /// Parse a notification.
+    ///
+    /// Returns `None` if the line is not a notification this crate knows.
+    pub fn parse(line: &str) -> Option<Result<Self, Error>> {
+        let line = line.trim();
+        let (prefix, _) = line.split_once(':')?;
+
+        Some(match prefix {
+            "+CEREG" => RegistrationStatus::parse_notification(line).map(Self::Registration),
+            "+CSCON" => CommandParser::parse(line.as_bytes())
+                .expect_identifier(b"+CSCON:")
+                .expect_int_parameter()
+                .finish()
+                .map_err(Error::from)
+                .map(|(mode,)| Self::SignalingConnection(mode == 1)),
+            "%XTIME" => NetworkTime::parse_notification(line).map(Self::NetworkTime),
+            "+CGEV" => PdnEvent::parse_notification(line).map(Self::Pdn),
+            "%MDMEV" => ModemEvent::parse_notification(line).map(Self::ModemEvent),
+            "%NCELLMEAS" => CellMeasurement::parse_notification(line).map(Self::CellMeasurement),
+            "+CEDRXP" => EdrxStatus::parse_notification(line).map(Self::Edrx),
+            _ => return None,
+        })
+    }
+
+    pub fn kind(&self) -> UrcKind {
+        match self {
+            Self::Registration(_) => UrcKind::Registration,
+            Self::SignalingConnection(_) => UrcKind::SignalingConnection,
+            Self::NetworkTime(_) => UrcKind::NetworkTime,
+            Self::Pdn(_) => UrcKind::Pdn,
+            Self::ModemEvent(_) => UrcKind::ModemEvent,
+            Self::CellMeasurement(_) => UrcKind::CellMeasurement,
+            Self::Edrx(_) => UrcKind::Edrx,
+        }
+    }
+}
+
+/// The time of the network, as reported by `%XTIME`. The network may leave out any of the values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkTime {
+    /// The offset of the local time from UTC, in quarters of an hour
+    pub time_zone: Option<i8>,
+    pub universal_time: Option<ClockTime>,
+    /// The daylight saving time adjustment, in hours
+    pub daylight_saving_time: Option<u8>,
+}
+
+impl NetworkTime {
+    /// Parse a `%XTIME` notification, e.g. `%XTIME: "80","81109251821408","00"`.
+    ///
+    /// The values are semi-octets, as in 3GPP TS 24.008.
+    pub fn parse_notification(line: &str) -> Result<Self, Error> {
+        let parameters = line
+            .trim()
+            .strip_prefix("%XTIME:")
+            .ok_or(Error::UnexpectedAtResponse)?;
+
+        // The network can leave out values, which the modem reports as empty parameters
+        let mut fields = parameters
+            .split(',')
+            .map(|field| field.trim().trim_matches('"'));
+        let (time_zone, universal_time, daylight_saving_time) =
+            (fields.next(), fields.next(), fields.next());
+
+        let universal_time = non_empty(universal_time)
+            .map(|time| {
+                let octet = |index: usize| time.get(index * 2..index * 2 + 2).map(semi_octets);
+
+                match (octet(0), octet(1), octet(2), octet(3), octet(4), octet(5)) {
+                    (Some(year), Some(month), Some(day), Some(hour), Some(minute), Some(sec)) => {
+                        Ok(ClockTime {
+                            year: 2000 + year? as u16,
+                            month: month?,
+                            day: day?,
+                            hour: hour?,
+                            minute: minute?,
+                            sec: sec?,
+                        })
+                    }
+                    _ => Err(Error::UnexpectedAtResponse),
+                }
+            })
+            .transpose()?;
+
+        Ok(Self {
+            time_zone: non_empty(time_zone).map(time_zone_value).transpose()?,
+            universal_time,
+            daylight_saving_time: non_empty(daylight_saving_time)
+                .map(semi_octets)
+                .transpose()?,
+        })
+    }
+}
+
+/// Decode an octet of two swapped decimal digits, e.g. `"81"` is 18
+fn semi_octets(octet: &str) -> Result<u8, Error> {
+    match octet.as_bytes() {
+        [low, high] if low.is_ascii_digit() && high.is_ascii_digit() => {
+            Ok((high - b'0') * 10 + (low - b'0'))
+        }
+        _ => Err(Error::UnexpectedAtResponse),
+    }
+}
+
+/// Decode the time zone octet, whose first digit also has the sign bit
+fn time_zone_value(octet: &str) -> Result<i8, Error> {
+    let value = u8::from_str_radix(octet, 16).map_err(|_| Error::UnexpectedAtResponse)?;
+    let (tens, units) = (value & 0x07, value >> 4);
+    if units > 9 {
+        return Err(Error::UnexpectedAtResponse);
+    }
+
+    let quarters = (tens * 10 + units) as i8;
+    Ok(if value & 0x08 != 0 {
+        -quarters
+    } else {
+        quarters
+    })
+}
+
+/// An event of the modem, as reported by `%MDMEV`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModemEvent {
+    /// The modem is too hot and turned off the radio
+    Overheated,
+    /// The battery voltage is too low and the modem turned off the radio
+    BatteryLow,
+    /// The light search for a network is done, without finding one to register to
+    LightSearchDone,
+    /// The search for a network is done, without finding one to register to
+    SearchDone,
+    /// The modem has reset too often in a short time
+    ResetLoop,
+    /// The modem has no IMEI
+    NoImei,
+    /// The coverage enhancement level changed
+    CeLevel(u8),
+}
+
+impl ModemEvent {
+    /// Parse a `%MDMEV` notification, e.g. `%MDMEV: ME OVERHEATED`
+    pub fn parse_notification(line: &str) -> Result<Self, Error> {
+        let event = line
+            .trim()
+            .strip_prefix("%MDMEV:")
+            .ok_or(Error::UnexpectedAtResponse)?
+            .trim();
+
+        match event {
+            "ME OVERHEATED" => Ok(Self::Overheated),
+            "ME BATTERY LOW" => Ok(Self::BatteryLow),
+            "SEARCH STATUS 1" => Ok(Self::LightSearchDone),
+            "SEARCH STATUS 2" => Ok(Self::SearchDone),
+            "RESET LOOP" => Ok(Self::ResetLoop),
+            "NO IMEI" => Ok(Self::NoImei),
+            event => event
+                .strip_prefix("PRACH CE-LEVEL ")
+                .and_then(|level| level.parse().ok())
+                .map(Self::CeLevel)
+                .ok_or(Error::UnexpectedAtResponse),
+        }
+    }
+}
+
+/// Whether a cell measurement succeeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellMeasurementStatus {
+    Success = 0,
+    Failed = 1,
+    /// The measurement was stopped with `AT%NCELLMEASSTOP` or by the network
+    Interrupted = 2,
+}
+
+/// The measured serving cell
+///
+/// The RSRP and RSRQ are the indices of 3GPP TS 36.133. The RSRP in dBm is `rsrp - 140`,
+/// the RSRQ in dB is `rsrq * 0.5 - 19.5`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServingCell {
+    /// The E-UTRAN cell id
+    pub cell_id: u32,
+    /// The mobile country code
+    pub mcc: u16,
+    /// The mobile network code
+    pub mnc: u16,
+    pub tracking_area_code: u16,
+    /// The timing advance, 65535 if it's not valid
+    pub timing_advance: u16,
+    pub earfcn: u32,
+    pub physical_cell_id: u16,
+    pub rsrp: i16,
+    pub rsrq: i16,
+    /// The uptime of the modem when the cell was measured, in milliseconds
+    pub measurement_time: u64,
+}
+
+/// A measured neighbor cell, with the RSRP and RSRQ indices of [ServingCell]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NeighborCell {
+    pub earfcn: u32,
+    pub physical_cell_id: u16,
+    pub rsrp: i16,
+    pub rsrq: i16,
+    /// The measurement time of this cell relative to the serving cell, in milliseconds
+    pub time_difference: i32,
+}
+
+/// The result of `AT%NCELLMEAS`, as reported by `%NCELLMEAS`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CellMeasurement {
+    pub status: CellMeasurementStatus,
+    /// The serving cell, if the measurement succeeded
+    pub serving_cell: Option<ServingCell>,
+    /// At most [NCELLMEAS_NEIGHBORS] neighbor cells
+    pub neighbor_cells: heapless::Vec<NeighborCell, NCELLMEAS_NEIGHBORS>,
+    /// The uptime of the modem when the timing advance was measured, in milliseconds
+    pub timing_advance_measurement_time: Option<u64>,
+}
+
+impl CellMeasurement {
+    /// Parse a `%NCELLMEAS` notification, e.g. `%NCELLMEAS: 0,"00011B07","26295","00B7",10512,9034,2,49,-16,107745,0`
+    pub fn parse_notification(line: &str) -> Result<Self, Error> {
+        let parameters = line
+            .trim()
+            .strip_prefix("%NCELLMEAS:")
+            .ok_or(Error::UnexpectedAtResponse)?;
+
+        let mut fields = parameters
+            .split(',')
+            .map(|field| field.trim().trim_matches('"'));
+
+        let status = match field::<u8>(&mut fields)? {
+            0 => CellMeasurementStatus::Success,
+            1 => CellMeasurementStatus::Failed,
+            2 => CellMeasurementStatus::Interrupted,
+            _ => return Err(Error::UnexpectedAtResponse),
+        };
+
+        let mut measurement = Self {
+            status,
+            serving_cell: None,
+            neighbor_cells: heapless::Vec::new(),
+            timing_advance_measurement_time: None,
+        };
+
+        let cell_id = match fields.next() {
+            Some(cell_id) => {
+                u32::from_str_radix(cell_id, 16).map_err(|_| Error::UnexpectedAtResponse)?
+            }
+            None => return Ok(measurement),
+        };
+
+        let plmn = fields.next().ok_or(Error::UnexpectedAtResponse)?;
+        let (mcc, mnc) = match (plmn.get(..3), plmn.get(3..)) {
+            (Some(mcc), Some(mnc)) if !mnc.is_empty() => (mcc, mnc),
+            _ => return Err(Error::UnexpectedAtResponse),
+        };
+
+        measurement.serving_cell = Some(ServingCell {
+            cell_id,
+            mcc: mcc.parse().map_err(|_| Error::UnexpectedAtResponse)?,
+            mnc: mnc.parse().map_err(|_| Error::UnexpectedAtResponse)?,
+            tracking_area_code: fields
+                .next()
+                .and_then(|tac| u16::from_str_radix(tac, 16).ok())
+                .ok_or(Error::UnexpectedAtResponse)?,
+            timing_advance: field(&mut fields)?,
+            earfcn: field(&mut fields)?,
+            physical_cell_id: field(&mut fields)?,
+            rsrp: field(&mut fields)?,
+            rsrq: field(&mut fields)?,
+            measurement_time: field(&mut fields)?,
+        });
+
+        // The neighbor cells have 5 values each, and the timing advance measurement time comes last
+        let remaining = fields.clone().count();
+        if remaining % 5 == 1 {
+            measurement.timing_advance_measurement_time =
+                Some(field(&mut fields.clone().skip(remaining - 1))?);
+        }
+
+        // The neighbors that don't fit are left out
+        for _ in 0..(remaining / 5).min(NCELLMEAS_NEIGHBORS) {
+            let neighbor = NeighborCell {
+                earfcn: field(&mut fields)?,
+                physical_cell_id: field(&mut fields)?,
+                rsrp: field(&mut fields)?,
+                rsrq: field(&mut fields)?,
+                time_difference: field(&mut fields)?,
+            };
+            // The capacity is checked by the range above
+            measurement.neighbor_cells.push(neighbor).ok();
+        }
+
+        Ok(measurement)
+    }
+}
+
+fn field<'a, T: FromStr>(fields: &mut impl Iterator<Item = &'a str>) -> Result<T, Error> {
+    fields
+        .next()
+        .and_then(|field| field.parse().ok())
+        .ok_or(Error::UnexpectedAtResponse)
+}
+
+/// Treat an empty parameter like an absent one
+fn non_empty(value: Option<&str>) -> Option<&str> {
+    value.filter(|value| !value.is_empty())
+}
+
+/// An AT socket that dispatches the notifications it receives.
+///
+/// Notifications go to the handlers of their kind. Without handlers, they're put in a queue of `N` notifications.
+/// When the queue is full, the oldest notification is dropped.
+pub struct UrcDispatcher<const N: usize> {
+    socket: AtSocket,
+    handlers: heapless::Vec<(UrcKind, UrcHandler), URC_HANDLERS>,
+    queue: heapless::Deque<Urc, N>,
+    dropped: usize,
+}
+
+impl<const N: usize> UrcDispatcher<N> {
+    /// Call the handler for every notification of the kind.
+    ///
+    /// Returns [Error::BufferTooSmall] when there are already [URC_HANDLERS] handlers.
+    pub fn subscribe(&mut self, kind: UrcKind, handler: UrcHandler) -> Result<(), Error> {
+        self.handlers
+            .push((kind, handler))
+            .map_err(|_| Error::BufferTooSmall(None))
+    }
+
+    /// Remove the handlers of the kind, so its notifications are queued again
+    pub fn unsubscribe(&mut self, kind: UrcKind) {
+        self.handlers
+            .retain(|(handler_kind, _)| *handler_kind != kind);
+    }
+
+    /// Take the oldest notification out of the queue
+    pub fn pop(&mut self) -> Option<Urc> {
+        self.queue.pop_front()
+    }
+
+    /// The amount of notifications that have been dropped because the queue was full
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Dispatch the line if it's a notification. Returns `false` if it's not.
+    fn dispatch(&mut self, line: &str) -> bool {
+        let urc = match Urc::parse(line) {
+            None => return false,
+            Some(Ok(urc)) => urc,
+            Some(Err(_)) => {
+                log::warning!("Could not parse the notification: {}", line);
+                return true;
+            }
+        };
+
+        let mut handled = false;
+        for (kind, handler) in self.handlers.iter() {
+            if *kind == urc.kind() {
+                handler(&urc);
+                handled = true;
+            }
+        }
+
+        if !handled {
+            if self.queue.is_full() {
+                self.queue.pop_front();
+                self.dropped += 1;
+            }
+            // Only fails when `N` is 0, in which case it's already counted as dropped
+            self.queue.push_back(urc).ok();
+        }
+
+        true
+    }
+}
+
+impl<B: ModemBackend> Modem<B> {
+    /// Create a dispatcher on a new AT socket
+    pub fn urc_dispatcher<const N: usize>(&mut self) -> Result<UrcDispatcher<N>, Error> {
+        let mut socket = self.at_socket()?;
+        if let Err(e) = self.at_connect(&mut socket) {
+            self.at_close(socket)?;
+            return Err(e);
+        }
+
+        Ok(UrcDispatcher {
+            socket,
+            handlers: heapless::Vec::new(),
+            queue: heapless::Deque::new(),
+            dropped: 0,
+        })
+    }
+
+    /// Dispatch all notifications that have been received, without blocking
+    pub fn urc_poll<const N: usize>(
+        &mut self,
+        dispatcher: &mut UrcDispatcher<N>,
+    ) -> Result<(), Error> {
+        loop {
+            let mut buffer = [0u8; MAX_RESPONSE_SIZE];
+            let length = match self.at_receive(&mut dispatcher.socket, &mut buffer) {
+                Ok(length) => length,
+                Err(nb::Error::WouldBlock) => return Ok(()),
+                Err(nb::Error::Other(e)) => return Err(e),
+            };
+
+            for line in lines(&buffer[..length])? {
+                if !dispatcher.dispatch(line) {
+                    log::warning!("Ignoring a line that is not a notification: {}", line);
+                }
+            }
+        }
+    }
+
+    /// Send an AT command on the socket of the dispatcher and call the callback with every line of the response.
+    ///
+    /// Notifications that come in between are dispatched. Lines that start with the name of the command,
+    /// like `+CEREG:` for `AT+CEREG?`, are always part of the response.
+    /// This blocks until the modem has responded with `OK` or an error.
+    pub fn urc_send_command<const N: usize, F>(
+        &mut self,
+        dispatcher: &mut UrcDispatcher<N>,
+        command: &str,
+        mut callback: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&str),
+    {
+        self.at_send(&mut dispatcher.socket, command)?;
+
+        let name = command_name(command);
+        let mut result = None;
+
+        while result.is_none() {
+            let mut buffer = [0u8; MAX_RESPONSE_SIZE];
+            let length = match self.at_receive(&mut dispatcher.socket, &mut buffer) {
+                Ok(length) => length,
+                Err(nb::Error::WouldBlock) => continue,
+                Err(nb::Error::Other(e)) => return Err(e),
+            };
+
+            for line in lines(&buffer[..length])? {
+                if result.is_none() {
+                    if let Some(final_result) = final_result(line) {
+                        result = Some(final_result);
+                        continue;
+                    }
+
+                    if is_response_to(name, line) {
+                        callback(line);
+                        continue;
+                    }
+                }
+
+                // Notifications can also come right after the final result
+                if !dispatcher.dispatch(line) {
+                    if result.is_none() {
+                        callback(line);
+                    } else {
+                        log::warning!("Ignoring a line after the response: {}", line);
+                    }
+                }
+            }
+        }
+
+        result.unwrap_or(Ok(()))
+    }
+
+    /// Close the socket of the dispatcher. The notifications that are still queued are lost.
+    pub fn urc_close<const N: usize>(&mut self, dispatcher: UrcDispatcher<N>) -> Result<(), Error> {
+        self.at_close(dispatcher.socket)
+    }
+}
+
+/// The non-empty lines of data received on an AT socket
+pub(crate) fn lines(data: &[u8]) -> Result<impl Iterator<Item = &str>, Error> {
+    let data = core::str::from_utf8(data)
+        .map_err(|_| Error::UnexpectedAtResponse)?
+        .trim_end_matches('\0');
+
+    Ok(data.lines().map(str::trim).filter(|line| !line.is_empty()))
+}
+
+/// The name of the command as used in its response, e.g. `+CEREG` for `AT+CEREG?`
+fn command_name(command: &str) -> &str {
+    let command = command.trim();
+    let command = command
+        .strip_prefix("AT")
+        .or_else(|| command.strip_prefix("at"))
+        .unwrap_or(command);
+
+    command
+        .split(['=', '?', '\r', '\n'])
+        .next()
+        .unwrap_or(command)
+}
+
+fn is_response_to(name: &str, line: &str) -> bool {
+    !name.is_empty()
+        && line
+            .strip_prefix(name)
+            .is_some_and(|rest| rest.starts_with(':'))
+}
@@ -0,0 +1,268 @@
+use embassy_futures::block_on;
+use embedded_io_async::{Read, Write};
+use embedded_nal_async::{AddrType, ConnectedUdp, Dns, TcpConnect, UdpStack, UnconnectedUdp};
+use nrf_modem_nal::{async_nal::AsyncModem, error::Error, sim::SimulatedModem};
+use std::{
+    io::{Read as _, Write as _},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, UdpSocket},
+    thread,
+};
+
+mod common;
+
+/// The address the code under test connects to, which is redirected to a local server
+fn server_address() -> SocketAddr {
+    "203.0.113.1:7".parse().unwrap()
+}
+
+fn modem(sim: SimulatedModem) -> AsyncModem<SimulatedModem> {
+    AsyncModem::new(common::modem(sim))
+}
+
+/// Start a TCP server on localhost that echoes everything back on the first connection
+fn tcp_echo_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buffer = [0; 64];
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(length) => stream.write_all(&buffer[..length]).unwrap(),
+            }
+        }
+    });
+
+    address
+}
+
+#[test]
+fn tcp_connect_waits_for_registration() {
+    let mut sim = SimulatedModem::new();
+    // The notifications are off until the wait turns them on
+    sim.script("AT+CEREG?", "+CEREG: 0,2\r\nOK").times(2);
+    sim.script("AT+CEREG?", "+CEREG: 1,2\r\nOK");
+    sim.script("AT+CEREG=1", "OK").notify("+CEREG: 1");
+    sim.redirect("203.0.113.1:7".parse().unwrap(), tcp_echo_server());
+    let modem = modem(sim);
+
+    block_on(async {
+        let mut connection = modem.connect(server_address()).await.unwrap();
+        connection.write_all(b"hello").await.unwrap();
+
+        let mut buffer = [0; 5];
+        connection.read_exact(&mut buffer).await.unwrap();
+        assert_eq!(&buffer, b"hello");
+    });
+
+    let mut modem = modem.into_inner();
+    let transcript = modem.backend().take_transcript();
+    assert!(transcript.contains(&"AT+CFUN=21".to_string()));
+    // The registration is read before waiting, the wait itself only reads the notification
+    assert_eq!(transcript.iter().filter(|c| *c == "AT+CEREG?").count(), 3);
+    assert_eq!(transcript.iter().filter(|c| *c == "AT+CEREG=1").count(), 1);
+    assert_eq!(transcript.iter().filter(|c| *c == "AT+CEREG=0").count(), 1);
+    // Dropping the connection closed the socket and turned LTE off
+    assert_eq!(
+        &transcript[transcript.len() - 2..],
+        ["AT+CFUN=20", "AT+CFUN=40"]
+    );
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn tcp_connect_keeps_registration_mode() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 5,2\r\nOK").times(2);
+    sim.script("AT+CEREG?", "+CEREG: 5,1\r\nOK");
+    sim.redirect("203.0.113.1:7".parse().unwrap(), tcp_echo_server());
+    let modem = modem(sim);
+
+    block_on(async {
+        let mut connection = modem.connect(server_address()).await.unwrap();
+        connection.write_all(b"hello").await.unwrap();
+    });
+
+    // The notifications were already on, so their mode isn't touched
+    let mut modem = modem.into_inner();
+    let transcript = modem.backend().take_transcript();
+    assert!(!transcript.iter().any(|c| c.starts_with("AT+CEREG=")));
+}
+
+#[test]
+fn tcp_connect_registration_denied() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 1,3\r\nOK");
+    let modem = modem(sim);
+
+    let result = block_on(modem.connect(server_address()));
+    assert!(matches!(result, Err(Error::LteRegistrationDenied)));
+    drop(result);
+
+    let mut modem = modem.into_inner();
+    assert_eq!(modem.backend().open_sockets(), 0);
+    assert!(modem
+        .backend()
+        .transcript()
+        .ends_with(&["AT+CFUN=20".to_string(), "AT+CFUN=40".to_string()]));
+}
+
+#[test]
+fn tcp_connect_denied_while_waiting() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 0,2\r\nOK");
+    sim.script("AT+CEREG=1", "OK").notify("+CEREG: 3");
+    let modem = modem(sim);
+
+    let result = block_on(modem.connect(server_address()));
+    assert!(matches!(result, Err(Error::LteRegistrationDenied)));
+    drop(result);
+
+    // The notifications are turned off again
+    let mut modem = modem.into_inner();
+    assert_eq!(modem.backend().open_sockets(), 0);
+    assert!(modem.backend().transcript().ends_with(&[
+        "AT+CEREG=0".to_string(),
+        "AT+CFUN=20".to_string(),
+        "AT+CFUN=40".to_string()
+    ]));
+}
+
+#[test]
+fn udp_echo() {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let server_local = server.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut buffer = [0; 64];
+        let (length, sender) = server.recv_from(&mut buffer).unwrap();
+        server.send_to(&buffer[..length], sender).unwrap();
+    });
+
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 1,1\r\nOK");
+    sim.redirect("203.0.113.1:7".parse().unwrap(), server_local);
+    let modem = modem(sim);
+
+    block_on(async {
+        let (_, mut socket) = UdpStack::connect(&&modem, server_address()).await.unwrap();
+        socket.send(b"ping").await.unwrap();
+
+        let mut buffer = [0; 16];
+        let length = socket.receive_into(&mut buffer).await.unwrap();
+        assert_eq!(&buffer[..length], b"ping");
+    });
+
+    assert_eq!(modem.into_inner().backend().open_sockets(), 0);
+}
+
+#[test]
+fn udp_bind() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 1,1\r\nOK");
+    let modem = modem(sim);
+
+    // A local port that nobody uses
+    let port = UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let local = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port);
+
+    block_on(async {
+        let (bound_address, mut socket) = (&modem).bind_single(local).await.unwrap();
+        assert_eq!(bound_address, local);
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.send_to(b"ping", ("127.0.0.1", port)).unwrap();
+
+        let mut buffer = [0; 16];
+        let (length, receiver, sender) = socket.receive_into(&mut buffer).await.unwrap();
+        assert_eq!(&buffer[..length], b"ping");
+        assert_eq!(receiver, local);
+        assert_eq!(sender, client.local_addr().unwrap());
+
+        socket.send(receiver, sender, b"pong").await.unwrap();
+        let length = client.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..length], b"pong");
+    });
+
+    assert_eq!(modem.into_inner().backend().open_sockets(), 0);
+}
+
+#[test]
+fn dns() {
+    let mut sim = SimulatedModem::new();
+    sim.add_host(
+        "example.com",
+        nrf_modem_nal::embedded_nal::Ipv4Addr::new(93, 184, 216, 34).into(),
+    );
+    let modem = modem(sim);
+
+    let address = block_on(modem.get_host_by_name("example.com", AddrType::IPv4)).unwrap();
+    assert_eq!(address, IpAddr::V4(Ipv4Addr::new(93, 184, 216, 34)));
+
+    let result = block_on(modem.get_host_by_name("unknown.example.com", AddrType::IPv4));
+    assert!(matches!(result, Err(Error::AddressNotFound)));
+}
+
+#[test]
+fn dns_all_addresses() {
+    let mut sim = SimulatedModem::new();
+    sim.add_host(
+        "example.com",
+        nrf_modem_nal::embedded_nal::Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1).into(),
+    );
+    sim.add_host(
+        "example.com",
+        nrf_modem_nal::embedded_nal::Ipv4Addr::new(93, 184, 216, 34).into(),
+    );
+    let modem = modem(sim);
+
+    // Only the addresses the sockets can connect to
+    let addresses =
+        block_on(modem.get_hosts_by_name::<4>("example.com", AddrType::Either)).unwrap();
+    assert_eq!(addresses, [IpAddr::V4(Ipv4Addr::new(93, 184, 216, 34))]);
+
+    let addresses = block_on(modem.get_hosts_by_name::<4>("example.com", AddrType::IPv6)).unwrap();
+    assert_eq!(addresses, ["2001:db8::1".parse::<IpAddr>().unwrap()]);
+}
+
+#[test]
+fn all_waiting_tasks_are_woken() {
+    use nrf_modem_nal::backend::{wake_modem_tasks, WakerSlot};
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Wake, Waker},
+    };
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let first = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let second = Arc::new(CountingWaker(AtomicUsize::new(0)));
+
+    let first_slot = WakerSlot::acquire();
+    let second_slot = WakerSlot::acquire();
+    first_slot.register(&Waker::from(first.clone()));
+    second_slot.register(&Waker::from(second.clone()));
+
+    wake_modem_tasks();
+
+    assert_eq!(first.0.load(Ordering::SeqCst), 1);
+    assert_eq!(second.0.load(Ordering::SeqCst), 1);
+
+    // A waker is woken once per registration
+    wake_modem_tasks();
+    assert_eq!(first.0.load(Ordering::SeqCst), 1);
+}
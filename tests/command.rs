@@ -0,0 +1,157 @@
+use at_commands::parser::CommandParser;
+use nrf_modem_nal::{
+    command::{
+        AtCommand, ReadClock, ReadRegistration, ReadRegistrationMode, SetRegistrationMode,
+        SetSystemMode,
+    },
+    error::{AtError, CmeError, CmsError, Error},
+    lte::ClockTime,
+    registration::RegistrationState,
+    sim::SimulatedModem,
+};
+
+mod common;
+use common::{modem, LTE_ONLY};
+
+/// A command that is defined outside of the crate
+struct ReadSignalQuality;
+
+impl AtCommand for ReadSignalQuality {
+    type Response = (i32, i32);
+
+    fn write<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b [u8], Error> {
+        buffer[..7].copy_from_slice(b"AT+CESQ");
+        Ok(&buffer[..7])
+    }
+
+    fn parse(response: &str) -> Result<Self::Response, Error> {
+        let (_, _, _, _, rsrq, rsrp) = CommandParser::parse(response.trim().as_bytes())
+            .expect_identifier(b"+CESQ:")
+            .expect_int_parameter()
+            .expect_int_parameter()
+            .expect_int_parameter()
+            .expect_int_parameter()
+            .expect_int_parameter()
+            .expect_int_parameter()
+            .finish()?;
+        Ok((rsrq, rsrp))
+    }
+}
+
+#[test]
+fn parse_captured_responses() {
+    let status = ReadRegistration::parse("+CEREG: 0,1\r\n").unwrap();
+    assert_eq!(status.state, RegistrationState::RegisteredHome);
+
+    // A notification in between doesn't hide the response
+    let status = ReadRegistration::parse("+CEREG: 2\r\n+CEREG: 1,5\r\n").unwrap();
+    assert_eq!(status.state, RegistrationState::RegisteredRoaming);
+
+    assert!(matches!(
+        ReadRegistration::parse(""),
+        Err(Error::NoAtResponse)
+    ));
+
+    assert_eq!(ReadRegistrationMode::parse("+CEREG: 5,1\r\n").unwrap(), 5);
+    assert_eq!(ReadRegistrationMode::parse("+CEREG: 0,2\r\n").unwrap(), 0);
+
+    assert_eq!(
+        ReadClock::parse("+CCLK: \"18/12/06,22:10:00+08\"\r\n").unwrap(),
+        ClockTime {
+            year: 2018,
+            month: 12,
+            day: 6,
+            hour: 22,
+            minute: 10,
+            sec: 0,
+        }
+    );
+    assert!(matches!(
+        ReadClock::parse("+CCLK: \"18/12/06\"\r\n"),
+        Err(Error::UnexpectedAtResponse)
+    ));
+
+    assert_eq!(
+        ReadSignalQuality::parse("+CESQ: 99,99,255,255,31,62\r\n").unwrap(),
+        (31, 62)
+    );
+}
+
+#[test]
+fn write_commands() {
+    let mut buffer = [0; SetSystemMode::COMMAND_SIZE];
+    let command = SetSystemMode(LTE_ONLY).write(&mut buffer).unwrap();
+    assert_eq!(command, b"AT%XSYSTEMMODE=1,0,0,0\r\n");
+
+    let mut buffer = [0; SetRegistrationMode::COMMAND_SIZE];
+    let command = SetRegistrationMode(5).write(&mut buffer).unwrap();
+    assert_eq!(command, b"AT+CEREG=5\r\n");
+
+    let mut buffer = [0; 4];
+    assert!(matches!(
+        ReadClock.write(&mut buffer),
+        Err(Error::BufferTooSmall(_))
+    ));
+}
+
+#[test]
+fn execute() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CESQ", "+CESQ: 99,99,255,255,20,45\r\nOK");
+    sim.script("AT+CCLK?", "ERROR");
+    let mut modem = modem(sim);
+
+    assert_eq!(modem.execute(&ReadSignalQuality).unwrap(), (20, 45));
+    assert!(matches!(
+        modem.execute(&ReadClock),
+        Err(Error::AtError(AtError::Error))
+    ));
+
+    assert_eq!(modem.backend().transcript(), ["AT+CESQ", "AT+CCLK?"]);
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn execute_maps_cme_errors() {
+    let mut sim = SimulatedModem::new();
+    // Creating the modem sets the system mode once
+    sim.script("AT%XSYSTEMMODE=1,0,0,0", "OK").times(1);
+    sim.script("AT%XSYSTEMMODE=1,0,0,0", "+CME ERROR: 518");
+    let mut modem = modem(sim);
+
+    assert!(matches!(
+        modem.execute(&SetSystemMode(LTE_ONLY)),
+        Err(Error::NotAllowedInActiveState)
+    ));
+}
+
+#[test]
+fn cme_and_cms_errors() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CCLK?", "+CME ERROR: 520");
+    sim.script("AT+CESQ", "+CME ERROR: 999");
+    let mut modem = modem(sim);
+
+    let error = match modem.execute(&ReadClock) {
+        Err(Error::AtError(error)) => error,
+        result => panic!("Unexpected result: {result:?}"),
+    };
+    assert_eq!(error, AtError::CmeError(CmeError::NotAllowedInCurrentState));
+    assert_eq!(
+        error.to_string(),
+        "+CME ERROR: 520 (not allowed in current state)"
+    );
+
+    assert!(matches!(
+        modem.execute(&ReadSignalQuality),
+        Err(Error::AtError(AtError::CmeError(CmeError::Other(999))))
+    ));
+
+    assert_eq!(CmeError::from_code(10), CmeError::SimNotInserted);
+    assert_eq!(CmeError::SimNotInserted.to_string(), "SIM not inserted");
+    assert_eq!(CmsError::from_code(322).code(), 322);
+    assert_eq!(
+        AtError::CmsError(CmsError::MemoryFull).to_string(),
+        "+CMS ERROR: 322 (memory full)"
+    );
+}
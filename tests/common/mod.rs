@@ -0,0 +1,20 @@
+//! The setup that is shared by the tests against the simulator
+#![allow(dead_code)]
+
+use nrf_modem_nal::{
+    power::LtePowerConfig, sim::SimulatedModem, ConnectionPreference, Modem, SystemMode,
+};
+
+pub const LTE_ONLY: SystemMode = SystemMode {
+    lte_support: true,
+    nbiot_support: false,
+    gnss_support: false,
+    preference: ConnectionPreference::None,
+};
+
+/// A modem with only LTE, without the commands of the initialization in the transcript
+pub fn modem(sim: SimulatedModem) -> Modem<SimulatedModem> {
+    let mut modem = Modem::with_backend(sim, None, LTE_ONLY, LtePowerConfig::default()).unwrap();
+    modem.backend().take_transcript();
+    modem
+}
@@ -0,0 +1,221 @@
+use nrf_modem_nal::{
+    credentials::{CredentialInfo, CredentialType},
+    embedded_nal::{nb, SocketAddr, TcpClientStack},
+    error::{AtError, CmeError, Error},
+    gnss::GnssOptions,
+    sim::SimulatedModem,
+};
+
+mod common;
+use common::modem;
+
+const CERTIFICATE: &str = "-----BEGIN CERTIFICATE-----\n\
+                           MIIBszCCAVmgAwIBAgIUW\n\
+                           -----END CERTIFICATE-----\n";
+
+const SHA256: &str = "2C43952EE9E000FF2ACC4E2ED0897C0A72AD5FA72C3D934E81741CBD54F05BD1";
+
+#[test]
+fn write_quotes_the_content() {
+    let mut modem = modem(SimulatedModem::new());
+
+    let mut buffer = [0; 256];
+    modem
+        .write_credential(
+            42,
+            CredentialType::RootCaCertificate,
+            CERTIFICATE,
+            &mut buffer,
+        )
+        .unwrap();
+
+    assert_eq!(
+        modem.backend().take_transcript(),
+        [format!("AT%CMNG=0,42,0,\"{CERTIFICATE}\"")]
+    );
+}
+
+#[test]
+fn write_errors() {
+    let mut modem = modem(SimulatedModem::new());
+
+    let mut buffer = [0; 32];
+    assert!(matches!(
+        modem.write_credential(
+            42,
+            CredentialType::ClientCertificate,
+            CERTIFICATE,
+            &mut buffer
+        ),
+        Err(Error::BufferTooSmall(_))
+    ));
+    assert!(matches!(
+        modem.write_credential(42, CredentialType::PskIdentity, "\"quoted\"", &mut buffer),
+        Err(Error::InvalidConfiguration)
+    ));
+    assert!(matches!(
+        modem.write_credential(u32::MAX, CredentialType::PresharedKey, "00", &mut buffer),
+        Err(Error::InvalidConfiguration)
+    ));
+    assert!(modem.backend().transcript().is_empty());
+}
+
+#[test]
+fn read_multiline_certificate() {
+    let mut sim = SimulatedModem::new();
+    sim.script(
+        "AT%CMNG=2,42,0",
+        &format!("%CMNG: 42,0,\"{SHA256}\",\"{CERTIFICATE}\"\r\nOK"),
+    );
+    let mut modem = modem(sim);
+
+    let mut buffer = [0; 512];
+    let content = modem
+        .read_credential(42, CredentialType::RootCaCertificate, &mut buffer)
+        .unwrap();
+
+    assert_eq!(content, CERTIFICATE);
+}
+
+#[test]
+fn read_missing_credential() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT%CMNG=2,7,1", "+CME ERROR: 513");
+    let mut modem = modem(sim);
+
+    let mut buffer = [0; 512];
+    assert!(matches!(
+        modem.read_credential(7, CredentialType::ClientCertificate, &mut buffer),
+        Err(Error::AtError(AtError::CmeError(CmeError::ItemNotFound)))
+    ));
+}
+
+#[test]
+fn read_into_small_buffer() {
+    let mut sim = SimulatedModem::new();
+    sim.script(
+        "AT%CMNG=2,42,0",
+        &format!("%CMNG: 42,0,\"{SHA256}\",\"{CERTIFICATE}\"\r\nOK"),
+    );
+    let mut modem = modem(sim);
+
+    let mut buffer = [0; 64];
+    assert!(matches!(
+        modem.read_credential(42, CredentialType::RootCaCertificate, &mut buffer),
+        Err(Error::BufferTooSmall(None))
+    ));
+}
+
+#[test]
+fn list_credentials() {
+    let mut sim = SimulatedModem::new();
+    sim.script(
+        "AT%CMNG=1",
+        &format!("%CMNG: 42,0,\"{SHA256}\"\r\n%CMNG: 16842753,4\r\nOK"),
+    );
+    let mut modem = modem(sim);
+
+    let mut buffer = [0; 256];
+    let mut credentials = Vec::new();
+    modem
+        .list_credentials(None, &mut buffer, |info| credentials.push(info))
+        .unwrap();
+
+    let sha256 = credentials[0].sha256.unwrap();
+    assert_eq!(credentials.len(), 2);
+    assert_eq!(credentials[0].sec_tag, 42);
+    assert_eq!(
+        credentials[0].credential_type,
+        CredentialType::RootCaCertificate
+    );
+    assert_eq!(sha256[..4], [0x2C, 0x43, 0x95, 0x2E]);
+    assert_eq!(sha256[31], 0xD1);
+    assert_eq!(
+        credentials[1],
+        CredentialInfo {
+            sec_tag: 16842753,
+            credential_type: CredentialType::PskIdentity,
+            sha256: None,
+        }
+    );
+    assert_eq!(modem.backend().take_transcript(), ["AT%CMNG=1"]);
+}
+
+#[test]
+fn list_credentials_of_tag() {
+    let mut modem = modem(SimulatedModem::new());
+
+    let mut buffer = [0; 256];
+    modem
+        .list_credentials(Some(42), &mut buffer, |_| panic!("There are none"))
+        .unwrap();
+
+    assert_eq!(modem.backend().take_transcript(), ["AT%CMNG=1,42"]);
+}
+
+#[test]
+fn delete_credential() {
+    let mut modem = modem(SimulatedModem::new());
+
+    modem
+        .delete_credential(42, CredentialType::ClientPrivateKey)
+        .unwrap();
+
+    assert_eq!(modem.backend().take_transcript(), ["AT%CMNG=3,42,2"]);
+}
+
+#[test]
+fn refused_while_lte_is_active() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 0,2\r\nOK");
+    let mut modem = modem(sim);
+
+    // Turns on LTE, but the modem doesn't register
+    let mut socket = TcpClientStack::socket(&mut modem).unwrap();
+    let remote: SocketAddr = "203.0.113.1:443".parse().unwrap();
+    assert!(matches!(
+        TcpClientStack::connect(&mut modem, &mut socket, remote),
+        Err(nb::Error::WouldBlock)
+    ));
+    modem.backend().take_transcript();
+
+    let mut buffer = [0; 256];
+    assert!(matches!(
+        modem.delete_credential(42, CredentialType::ClientPrivateKey),
+        Err(Error::NotAllowedInActiveState)
+    ));
+    assert!(matches!(
+        modem.list_credentials(None, &mut buffer, |_| {}),
+        Err(Error::NotAllowedInActiveState)
+    ));
+    assert!(modem.backend().transcript().is_empty());
+
+    // Allowed again when LTE is off
+    TcpClientStack::close(&mut modem, socket).unwrap();
+    modem
+        .delete_credential(42, CredentialType::ClientPrivateKey)
+        .unwrap();
+}
+
+#[test]
+fn refused_while_gnss_is_active() {
+    let mut modem = modem(SimulatedModem::new());
+
+    let mut socket = modem.gnss_socket().unwrap();
+    modem
+        .gnss_connect(&mut socket, GnssOptions::default())
+        .unwrap();
+    modem.backend().take_transcript();
+
+    assert!(matches!(
+        modem.delete_credential(42, CredentialType::ClientPrivateKey),
+        Err(Error::NotAllowedInActiveState)
+    ));
+    assert!(modem.backend().transcript().is_empty());
+
+    // Allowed again when GNSS is off
+    modem.gnss_close(socket).unwrap();
+    modem
+        .delete_credential(42, CredentialType::ClientPrivateKey)
+        .unwrap();
+}
@@ -0,0 +1,644 @@
+use nrf_modem_nal::{
+    dns::{DnsCacheConfig, DnsServers},
+    embedded_nal::{nb, AddrType, Dns, IpAddr, Ipv4Addr, Ipv6Addr},
+    error::{Errno, Error},
+    sim::SimulatedModem,
+    Modem,
+};
+use std::{
+    cell::Cell,
+    net::UdpSocket,
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+mod common;
+
+/// The DNS server the network gives, which is redirected to a local server
+const NETWORK_DNS: &str = "198.51.100.53";
+
+const IPV4_A: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+const IPV4_B: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 2);
+const IPV6: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x1);
+
+fn modem(sim: SimulatedModem) -> Modem<SimulatedModem> {
+    let mut sim = sim;
+    sim.script("AT+CEREG?", "+CEREG: 0,1\r\nOK");
+    sim.script(
+        "AT+CGCONTRDP=0",
+        &format!("+CGCONTRDP: 0,,\"internet\",\"\",\"\",\"{NETWORK_DNS}\",\"\",,,,,1028\r\nOK"),
+    );
+    common::modem(sim)
+}
+
+/// A host with two IPv4 and one IPv6 address, with the IPv6 address added first,
+/// and hosts with only one IPv4 or IPv6 address
+fn dual_stack_modem() -> Modem<SimulatedModem> {
+    let mut sim = SimulatedModem::new();
+    sim.add_host("example.com", IPV6.into());
+    sim.add_host("example.com", IPV4_A.into());
+    sim.add_host("example.com", IPV4_B.into());
+    sim.add_host("ipv4.example.com", IPV4_B.into());
+    sim.add_host("ipv6.example.com", IPV6.into());
+    modem(sim)
+}
+
+/// Start a DNS server on localhost that answers the queries for the server at the address.
+/// The queries are sent back over the channel.
+fn dns_server(
+    sim: &mut SimulatedModem,
+    server: &str,
+    answer: fn(&[u8]) -> Vec<u8>,
+) -> Receiver<Vec<u8>> {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    sim.redirect(
+        format!("{server}:53").parse().unwrap(),
+        socket.local_addr().unwrap(),
+    );
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0; 512];
+        while let Ok((length, client)) = socket.recv_from(&mut buffer) {
+            let query = buffer[..length].to_vec();
+            socket.send_to(&answer(&query), client).unwrap();
+            // The test may not look at the queries
+            let _ = sender.send(query);
+        }
+    });
+
+    receiver
+}
+
+/// The labels of a name, without the final empty label
+fn labels(name: &str) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    for label in name.split('.') {
+        encoded.push(label.len() as u8);
+        encoded.extend_from_slice(label.as_bytes());
+    }
+    encoded
+}
+
+/// The header and question of a response to the query
+fn response_header(query: &[u8], rcode: u8, answers: u16) -> Vec<u8> {
+    let mut response = query[..2].to_vec();
+    response.extend_from_slice(&[0x81, 0x80 | rcode, 0, 1]);
+    response.extend_from_slice(&answers.to_be_bytes());
+    response.extend_from_slice(&[0, 0, 0, 0]);
+    response.extend_from_slice(&query[12..]);
+    response
+}
+
+fn record(response: &mut Vec<u8>, record_type: u16, data: &[u8]) {
+    // The name points to the question
+    response.extend_from_slice(&[0xC0, 12]);
+    response.extend_from_slice(&record_type.to_be_bytes());
+    response.extend_from_slice(&[0, 1, 0, 0, 0x0E, 0x10]);
+    response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    response.extend_from_slice(data);
+}
+
+/// A CNAME to `example.com` and a PTR record to `www.example.com` that points into the CNAME
+fn compressed_answer(query: &[u8]) -> Vec<u8> {
+    let mut response = response_header(query, 0, 2);
+
+    let mut cname = labels("example.com");
+    cname.push(0);
+    // After the name, type, class, ttl and length of the record
+    let cname_position = response.len() + 12;
+    record(&mut response, 5, &cname);
+
+    let mut ptr = labels("www");
+    ptr.extend_from_slice(&(0xC000 | cname_position as u16).to_be_bytes());
+    record(&mut response, 12, &ptr);
+
+    response
+}
+
+/// The type of the question of the query
+fn query_type(query: &[u8]) -> u16 {
+    u16::from_be_bytes([query[query.len() - 4], query[query.len() - 3]])
+}
+
+/// A CNAME and the A or AAAA record of the alias
+fn address_answer(query: &[u8]) -> Vec<u8> {
+    let mut response = response_header(query, 0, 2);
+
+    let mut cname = labels("example.com");
+    cname.push(0);
+    record(&mut response, 5, &cname);
+
+    match query_type(query) {
+        28 => record(&mut response, 28, &IPV6.octets()),
+        _ => record(&mut response, 1, &IPV4_A.octets()),
+    }
+
+    response
+}
+
+fn not_found_answer(query: &[u8]) -> Vec<u8> {
+    response_header(query, 3, 0)
+}
+
+fn empty_answer(query: &[u8]) -> Vec<u8> {
+    response_header(query, 0, 0)
+}
+
+#[test]
+fn host_by_address() {
+    let mut sim = SimulatedModem::new();
+    let queries = dns_server(&mut sim, NETWORK_DNS, compressed_answer);
+    let mut modem = modem(sim);
+
+    let name = nb::block!(modem.get_host_by_address(Ipv4Addr::new(192, 0, 2, 1).into())).unwrap();
+    assert_eq!(name.as_str(), "www.example.com");
+
+    let query = queries.recv().unwrap();
+    let mut question = labels("1.2.0.192.in-addr.arpa");
+    question.extend_from_slice(&[0, 0, 12, 0, 1]);
+    assert_eq!(&query[12..], question);
+
+    // The socket is closed and LTE is turned off again
+    let transcript = modem.backend().take_transcript();
+    assert!(transcript.contains(&"AT+CFUN=21".to_string()));
+    assert!(transcript.ends_with(&["AT+CFUN=20".to_string(), "AT+CFUN=40".to_string()]));
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn host_by_ipv6_address() {
+    let mut sim = SimulatedModem::new();
+    let queries = dns_server(&mut sim, NETWORK_DNS, compressed_answer);
+    let mut modem = modem(sim);
+
+    let address = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x1);
+    nb::block!(modem.get_host_by_address(IpAddr::V6(address))).unwrap();
+
+    let query = queries.recv().unwrap();
+    let mut question =
+        labels("1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa");
+    question.extend_from_slice(&[0, 0, 12, 0, 1]);
+    assert_eq!(&query[12..], question);
+}
+
+#[test]
+fn host_by_address_not_found() {
+    for answer in [not_found_answer, empty_answer] {
+        let mut sim = SimulatedModem::new();
+        dns_server(&mut sim, NETWORK_DNS, answer);
+        let mut modem = modem(sim);
+
+        let result = nb::block!(modem.get_host_by_address(Ipv4Addr::new(192, 0, 2, 1).into()));
+        assert!(matches!(result, Err(Error::NoPtrRecord)));
+        assert_eq!(modem.backend().open_sockets(), 0);
+    }
+}
+
+#[test]
+fn host_by_address_server_error() {
+    let mut sim = SimulatedModem::new();
+    dns_server(&mut sim, NETWORK_DNS, |query| response_header(query, 2, 0));
+    let mut modem = modem(sim);
+
+    let result = nb::block!(modem.get_host_by_address(Ipv4Addr::new(192, 0, 2, 1).into()));
+    assert!(matches!(result, Err(Error::DnsServerError(2))));
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn cancel_host_by_address() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 0,2\r\nOK");
+    let mut modem = common::modem(sim);
+
+    let result = modem.get_host_by_address(Ipv4Addr::new(192, 0, 2, 1).into());
+    assert!(matches!(result, Err(nb::Error::WouldBlock)));
+    assert_eq!(modem.backend().open_sockets(), 1);
+
+    modem.cancel_dns_lookup().unwrap();
+    assert_eq!(modem.backend().open_sockets(), 0);
+    assert!(modem
+        .backend()
+        .transcript()
+        .ends_with(&["AT+CFUN=20".to_string(), "AT+CFUN=40".to_string()]));
+}
+
+#[test]
+fn host_by_name_either_is_ipv4() {
+    let mut modem = dual_stack_modem();
+
+    let address = nb::block!(modem.get_host_by_name("example.com", AddrType::Either)).unwrap();
+    assert_eq!(address, IpAddr::V4(IPV4_A));
+
+    // The sockets can't connect to IPv6 addresses, so they're only looked up when asked for
+    let result = nb::block!(modem.get_host_by_name("ipv6.example.com", AddrType::Either));
+    assert!(matches!(result, Err(Error::AddressNotFound)));
+    let address = nb::block!(modem.get_host_by_name("ipv6.example.com", AddrType::IPv6)).unwrap();
+    assert_eq!(address, IpAddr::V6(IPV6));
+}
+
+#[test]
+fn hosts_by_name() {
+    let mut modem = dual_stack_modem();
+
+    let addresses =
+        nb::block!(modem.get_hosts_by_name::<4>("example.com", AddrType::Either)).unwrap();
+    assert_eq!(addresses, [IpAddr::V4(IPV4_A), IPV4_B.into()]);
+
+    let addresses =
+        nb::block!(modem.get_hosts_by_name::<4>("example.com", AddrType::IPv6)).unwrap();
+    assert_eq!(addresses, [IpAddr::V6(IPV6)]);
+
+    // The addresses that don't fit are left out
+    let addresses =
+        nb::block!(modem.get_hosts_by_name::<1>("example.com", AddrType::Either)).unwrap();
+    assert_eq!(addresses, [IpAddr::V4(IPV4_A)]);
+
+    let addresses =
+        nb::block!(modem.get_hosts_by_name::<2>("192.0.2.3", AddrType::Either)).unwrap();
+    assert_eq!(addresses, [IpAddr::V4(Ipv4Addr::new(192, 0, 2, 3))]);
+}
+
+#[test]
+fn hosts_by_name_not_found() {
+    let mut modem = dual_stack_modem();
+
+    let result = nb::block!(modem.get_hosts_by_name::<4>("unknown.example.com", AddrType::Either));
+    assert!(matches!(result, Err(Error::AddressNotFound)));
+
+    let result = nb::block!(modem.get_hosts_by_name::<4>("ipv6.example.com", AddrType::IPv4));
+    assert!(matches!(result, Err(Error::AddressNotFound)));
+}
+
+thread_local! {
+    /// The time of the clock of the DNS cache, per test
+    static NOW: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+fn advance_clock(duration: Duration) {
+    NOW.with(|now| now.set(now.get() + duration));
+}
+
+fn cached_modem() -> Modem<SimulatedModem> {
+    let mut modem = dual_stack_modem();
+    modem.set_dns_cache(Some(DnsCacheConfig {
+        clock: || NOW.with(Cell::get),
+        ttl: Duration::from_secs(60),
+        negative_ttl: Duration::from_secs(10),
+    }));
+    modem
+}
+
+#[test]
+fn dns_cache_expires() {
+    let mut modem = cached_modem();
+
+    let addresses =
+        nb::block!(modem.get_hosts_by_name::<4>("ipv4.example.com", AddrType::Either)).unwrap();
+    assert_eq!(addresses, [IpAddr::V4(IPV4_B)]);
+
+    // The new address is not seen until the cached addresses expire
+    modem.backend().add_host("ipv4.example.com", IPV4_A.into());
+    advance_clock(Duration::from_secs(59));
+    let addresses =
+        nb::block!(modem.get_hosts_by_name::<4>("ipv4.example.com", AddrType::Either)).unwrap();
+    assert_eq!(addresses, [IpAddr::V4(IPV4_B)]);
+
+    // A different address type is cached separately
+    let addresses =
+        nb::block!(modem.get_hosts_by_name::<4>("ipv4.example.com", AddrType::IPv4)).unwrap();
+    assert_eq!(addresses, [IpAddr::V4(IPV4_B), IPV4_A.into()]);
+
+    advance_clock(Duration::from_secs(1));
+    let addresses =
+        nb::block!(modem.get_hosts_by_name::<4>("ipv4.example.com", AddrType::Either)).unwrap();
+    assert_eq!(addresses, [IpAddr::V4(IPV4_B), IPV4_A.into()]);
+}
+
+#[test]
+fn dns_cache_not_found() {
+    let mut modem = cached_modem();
+
+    let result = nb::block!(modem.get_host_by_name("new.example.com", AddrType::Either));
+    assert!(matches!(result, Err(Error::AddressNotFound)));
+
+    modem.backend().add_host("new.example.com", IPV4_A.into());
+    let result = nb::block!(modem.get_host_by_name("new.example.com", AddrType::Either));
+    assert!(matches!(result, Err(Error::AddressNotFound)));
+
+    advance_clock(Duration::from_secs(10));
+    let address = nb::block!(modem.get_host_by_name("new.example.com", AddrType::Either)).unwrap();
+    assert_eq!(address, IpAddr::V4(IPV4_A));
+}
+
+#[test]
+fn dns_cache_skips_transient_errors() {
+    let mut modem = cached_modem();
+    modem
+        .backend()
+        .add_host_error("flaky.example.com", Error::NrfSys(Errno::TimedOut));
+
+    let result = nb::block!(modem.get_host_by_name("flaky.example.com", AddrType::IPv4));
+    assert!(matches!(result, Err(Error::NrfSys(Errno::TimedOut))));
+
+    // The failure wasn't cached, so the next lookup asks the modem again
+    modem.backend().add_host("flaky.example.com", IPV4_A.into());
+    let address = nb::block!(modem.get_host_by_name("flaky.example.com", AddrType::IPv4)).unwrap();
+    assert_eq!(address, IpAddr::V4(IPV4_A));
+}
+
+#[test]
+fn dns_cache_flush() {
+    let mut modem = cached_modem();
+
+    nb::block!(modem.get_hosts_by_name::<4>("ipv4.example.com", AddrType::Either)).unwrap();
+    modem.backend().add_host("ipv4.example.com", IPV4_A.into());
+
+    modem.flush_dns_cache();
+    let addresses =
+        nb::block!(modem.get_hosts_by_name::<4>("ipv4.example.com", AddrType::Either)).unwrap();
+    assert_eq!(addresses, [IpAddr::V4(IPV4_B), IPV4_A.into()]);
+}
+
+#[test]
+fn dns_cache_ignores_case() {
+    let mut modem = cached_modem();
+
+    nb::block!(modem.get_hosts_by_name::<4>("ipv4.example.com", AddrType::Either)).unwrap();
+    modem.backend().add_host("ipv4.example.com", IPV4_A.into());
+
+    // Hostnames are the same in any case, so the cached address is used
+    let addresses =
+        nb::block!(modem.get_hosts_by_name::<4>("IPv4.Example.COM", AddrType::Either)).unwrap();
+    assert_eq!(addresses, [IpAddr::V4(IPV4_B)]);
+}
+
+#[test]
+fn dns_cache_more_addresses() {
+    let mut modem = cached_modem();
+
+    // Only the first address is known after this
+    nb::block!(modem.get_host_by_name("example.com", AddrType::Either)).unwrap();
+
+    let addresses =
+        nb::block!(modem.get_hosts_by_name::<4>("example.com", AddrType::Either)).unwrap();
+    assert_eq!(addresses, [IpAddr::V4(IPV4_A), IPV4_B.into()]);
+}
+
+#[test]
+fn dns_cache_is_off_by_default() {
+    let mut modem = dual_stack_modem();
+
+    let result = nb::block!(modem.get_host_by_name("ipv6.example.com", AddrType::Either));
+    assert!(matches!(result, Err(Error::AddressNotFound)));
+    modem.backend().add_host("ipv6.example.com", IPV4_A.into());
+
+    let address = nb::block!(modem.get_host_by_name("ipv6.example.com", AddrType::Either)).unwrap();
+    assert_eq!(address, IpAddr::V4(IPV4_A));
+}
+
+#[test]
+fn set_dns_servers() {
+    let mut modem = modem(SimulatedModem::new());
+    let primary = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 53));
+    let secondary = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 54));
+
+    modem
+        .set_dns_servers(Some(primary), Some(secondary))
+        .unwrap();
+    assert_eq!(
+        modem.dns_servers(),
+        DnsServers {
+            primary: Some(primary),
+            secondary: Some(secondary),
+        }
+    );
+
+    // The sockets can't reach IPv6 servers
+    let result = modem.set_dns_servers(Some(primary), Some(IPV6.into()));
+    assert!(matches!(result, Err(Error::InvalidConfiguration)));
+    assert_eq!(modem.dns_servers().secondary, Some(secondary));
+
+    modem.set_dns_servers(None, None).unwrap();
+    assert_eq!(modem.dns_servers(), DnsServers::default());
+}
+
+#[test]
+fn host_by_name_with_own_dns_servers() {
+    let mut sim = SimulatedModem::new();
+    let queries = dns_server(&mut sim, "10.0.0.53", address_answer);
+    // The modem would resolve it differently
+    sim.add_host("www.example.com", IPV4_B.into());
+    let mut modem = modem(sim);
+    modem
+        .set_dns_servers(Some(Ipv4Addr::new(10, 0, 0, 53).into()), None)
+        .unwrap();
+
+    let address = nb::block!(modem.get_host_by_name("www.example.com", AddrType::IPv4)).unwrap();
+    assert_eq!(address, IpAddr::V4(IPV4_A));
+
+    let query = queries.recv().unwrap();
+    assert_eq!(
+        &query[12..query.len() - 4],
+        b"\x03www\x07example\x03com\x00"
+    );
+    assert_eq!(query_type(&query), 1);
+
+    // The servers of the network are not used
+    let transcript = modem.backend().take_transcript();
+    assert!(!transcript.contains(&"AT+CGCONTRDP=0".to_string()));
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn hosts_by_name_with_own_dns_servers() {
+    let mut sim = SimulatedModem::new();
+    let queries = dns_server(&mut sim, "10.0.0.53", address_answer);
+    let mut modem = modem(sim);
+    modem
+        .set_dns_servers(Some(Ipv4Addr::new(10, 0, 0, 53).into()), None)
+        .unwrap();
+
+    let addresses =
+        nb::block!(modem.get_hosts_by_name::<4>("example.com", AddrType::Either)).unwrap();
+    assert_eq!(addresses, [IpAddr::V4(IPV4_A)]);
+    assert_eq!(query_type(&queries.recv().unwrap()), 1);
+
+    let addresses =
+        nb::block!(modem.get_hosts_by_name::<4>("example.com", AddrType::IPv6)).unwrap();
+    assert_eq!(addresses, [IpAddr::V6(IPV6)]);
+    assert_eq!(query_type(&queries.recv().unwrap()), 28);
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn host_by_name_not_found_on_own_dns_servers() {
+    let mut sim = SimulatedModem::new();
+    dns_server(&mut sim, "10.0.0.53", not_found_answer);
+    let mut modem = modem(sim);
+    modem
+        .set_dns_servers(Some(Ipv4Addr::new(10, 0, 0, 53).into()), None)
+        .unwrap();
+
+    let result = nb::block!(modem.get_hosts_by_name::<4>("unknown.example.com", AddrType::Either));
+    assert!(matches!(result, Err(Error::AddressNotFound)));
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn host_by_name_without_records_on_own_dns_servers() {
+    // No records of the family is the same as no name
+    let mut sim = SimulatedModem::new();
+    dns_server(&mut sim, "10.0.0.53", empty_answer);
+    let mut modem = modem(sim);
+    modem
+        .set_dns_servers(Some(Ipv4Addr::new(10, 0, 0, 53).into()), None)
+        .unwrap();
+
+    let result = nb::block!(modem.get_host_by_name("ipv6.example.com", AddrType::IPv4));
+    assert!(matches!(result, Err(Error::AddressNotFound)));
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn network_dns_servers() {
+    let mut sim = SimulatedModem::new();
+    sim.script(
+        "AT+CGCONTRDP=0",
+        "+CGCONTRDP: 0,,\"internet\",\"\",\"\",\"10.0.0.1\",\"10.0.0.2\",,,,,1028\r\nOK",
+    )
+    .times(1);
+    sim.script(
+        "AT+CGCONTRDP=0",
+        "+CGCONTRDP: 0,,\"internet\",\"\",\"\",\"\",\"\",,,,,1028\r\nOK",
+    );
+    let mut modem = common::modem(sim);
+
+    assert_eq!(
+        modem.network_dns_servers().unwrap(),
+        DnsServers {
+            primary: Some(Ipv4Addr::new(10, 0, 0, 1).into()),
+            secondary: Some(Ipv4Addr::new(10, 0, 0, 2).into()),
+        }
+    );
+    // A private APN may not give any
+    assert_eq!(modem.network_dns_servers().unwrap(), DnsServers::default());
+}
+
+#[test]
+fn host_by_address_with_own_dns_servers() {
+    let mut sim = SimulatedModem::new();
+    let queries = dns_server(&mut sim, "10.0.0.53", compressed_answer);
+    // The secondary server doesn't answer
+    let secondary = UdpSocket::bind("127.0.0.1:0").unwrap();
+    sim.redirect(
+        "10.0.0.54:53".parse().unwrap(),
+        secondary.local_addr().unwrap(),
+    );
+    let mut modem = modem(sim);
+    modem
+        .set_dns_servers(
+            Some(Ipv4Addr::new(10, 0, 0, 53).into()),
+            Some(Ipv4Addr::new(10, 0, 0, 54).into()),
+        )
+        .unwrap();
+
+    let name = nb::block!(modem.get_host_by_address(IPV4_A.into())).unwrap();
+    assert_eq!(name.as_str(), "www.example.com");
+    queries.recv().unwrap();
+    // Both servers have been asked
+    let mut buffer = [0; 512];
+    assert!(secondary.recv(&mut buffer).unwrap() > 12);
+
+    // The servers of the network are not used
+    let transcript = modem.backend().take_transcript();
+    assert!(!transcript.contains(&"AT+CGCONTRDP=0".to_string()));
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn ipv6_network_dns_server_is_skipped() {
+    let mut sim = SimulatedModem::new();
+    sim.script(
+        "AT+CGCONTRDP=0",
+        &format!(
+            "+CGCONTRDP: 0,,\"internet\",\"\",\"\",\"{IPV6}\",\"{NETWORK_DNS}\",,,,,1028\r\nOK"
+        ),
+    );
+    dns_server(&mut sim, NETWORK_DNS, compressed_answer);
+    let mut modem = modem(sim);
+
+    // The secondary server answers
+    let name = nb::block!(modem.get_host_by_address(IPV4_A.into())).unwrap();
+    assert_eq!(name.as_str(), "www.example.com");
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn host_by_address_without_usable_dns_server() {
+    let mut sim = SimulatedModem::new();
+    sim.script(
+        "AT+CGCONTRDP=0",
+        &format!("+CGCONTRDP: 0,,\"internet\",\"\",\"\",\"{IPV6}\",\"\",,,,,1028\r\nOK"),
+    );
+    let mut modem = modem(sim);
+
+    let result = nb::block!(modem.get_host_by_address(IPV4_A.into()));
+    assert!(matches!(
+        result,
+        Err(Error::NrfSys(Errno::AddressFamilyNotSupported))
+    ));
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn answer_from_other_port_is_ignored() {
+    let mut sim = SimulatedModem::new();
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let forger = UdpSocket::bind("127.0.0.1:0").unwrap();
+    sim.redirect(
+        "10.0.0.53:53".parse().unwrap(),
+        server.local_addr().unwrap(),
+    );
+    sim.redirect(
+        "10.0.0.53:5353".parse().unwrap(),
+        forger.local_addr().unwrap(),
+    );
+    thread::spawn(move || {
+        let mut buffer = [0; 512];
+        let (length, client) = server.recv_from(&mut buffer).unwrap();
+        let answer = address_answer(&buffer[..length]);
+
+        // The same answer from the right address, but with another address and from another port
+        let mut forged = answer.clone();
+        let address_position = forged.len() - 4;
+        forged[address_position..].copy_from_slice(&IPV4_B.octets());
+        forger.send_to(&forged, client).unwrap();
+        server.send_to(&answer, client).unwrap();
+    });
+    let mut modem = modem(sim);
+    modem
+        .set_dns_servers(Some(Ipv4Addr::new(10, 0, 0, 53).into()), None)
+        .unwrap();
+
+    let address = nb::block!(modem.get_host_by_name("www.example.com", AddrType::IPv4)).unwrap();
+    assert_eq!(address, IpAddr::V4(IPV4_A));
+}
+
+#[test]
+fn query_ids_from_random_source() {
+    let mut sim = SimulatedModem::new();
+    let queries = dns_server(&mut sim, "10.0.0.53", address_answer);
+    let mut modem = modem(sim);
+    modem
+        .set_dns_servers(Some(Ipv4Addr::new(10, 0, 0, 53).into()), None)
+        .unwrap();
+    modem.set_dns_random(Some(|| 0x5EED_1234));
+
+    nb::block!(modem.get_host_by_name("example.com", AddrType::IPv4)).unwrap();
+
+    let query = queries.recv().unwrap();
+    assert_eq!(u16::from_be_bytes([query[0], query[1]]), 0x1234);
+}
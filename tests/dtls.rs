@@ -0,0 +1,100 @@
+use nrf_modem_nal::{
+    dtls::DtlsOptions,
+    embedded_nal::{nb, SocketAddr, UdpClientStack},
+    shared::SharedModem,
+    sim::{DtlsConfiguration, SimulatedModem, TlsConfiguration},
+    tls::{PeerVerification, TlsOptions},
+    Modem,
+};
+use std::{net::UdpSocket, thread};
+
+mod common;
+
+const OPTIONS: DtlsOptions = DtlsOptions {
+    security: TlsOptions {
+        sec_tags: &[42],
+        peer_verification: PeerVerification::Required,
+        hostname: Some("coap.example.com"),
+    },
+};
+
+/// The address the code under test connects to, which is redirected to a local server
+fn server_address() -> SocketAddr {
+    "203.0.113.1:5684".parse().unwrap()
+}
+
+fn modem(sim: SimulatedModem) -> Modem<SimulatedModem> {
+    let mut sim = sim;
+    sim.script("AT+CEREG?", "+CEREG: 0,1\r\nOK");
+    common::modem(sim)
+}
+
+/// Start a UDP server on localhost that echoes every datagram back to its sender.
+/// The simulated DTLS sockets don't encrypt anything.
+fn echo_server() -> std::net::SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = socket.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let mut buffer = [0; 64];
+        while let Ok((length, sender)) = socket.recv_from(&mut buffer) {
+            socket.send_to(&buffer[..length], sender).unwrap();
+        }
+    });
+
+    address
+}
+
+#[test]
+fn dtls_echo() {
+    let mut sim = SimulatedModem::new();
+    sim.redirect(server_address(), echo_server());
+    let mut modem = modem(sim);
+    modem.backend().take_transcript();
+
+    let mut stack = modem.dtls(OPTIONS);
+    let mut socket = stack.socket().unwrap();
+    stack.connect(&mut socket, server_address()).unwrap();
+    nb::block!(stack.send(&mut socket, b"ping")).unwrap();
+
+    let mut buffer = [0; 16];
+    let (received, from) = nb::block!(stack.receive(&mut socket, &mut buffer)).unwrap();
+    assert_eq!(&buffer[..received], b"ping");
+    assert_eq!(from, server_address());
+
+    stack.close(socket).unwrap();
+
+    assert_eq!(
+        modem.backend().dtls_configurations(),
+        [DtlsConfiguration {
+            security: TlsConfiguration {
+                sec_tags: vec![42],
+                peer_verification: PeerVerification::Required,
+                hostname: Some("coap.example.com".to_string()),
+            },
+        }]
+    );
+    assert!(modem
+        .backend()
+        .take_transcript()
+        .ends_with(&["AT+CFUN=20".to_string(), "AT+CFUN=40".to_string()]));
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn dropped_dtls_socket_is_closed() {
+    let mut sim = SimulatedModem::new();
+    sim.redirect(server_address(), echo_server());
+    let modem = SharedModem::new(modem(sim));
+
+    {
+        let mut socket = modem.dtls_socket(&OPTIONS).unwrap();
+        socket
+            .with(|modem, socket| modem.dtls(OPTIONS).connect(socket, server_address()))
+            .unwrap();
+    }
+
+    let mut modem = modem.into_inner();
+    assert_eq!(modem.backend().open_sockets(), 0);
+    assert_eq!(modem.backend().transcript().last().unwrap(), "AT+CFUN=40");
+}
@@ -0,0 +1,155 @@
+use nrf_modem_nal::{
+    edrx::{EdrxAccessTechnology, EdrxConfig, EdrxCycle, EdrxStatus, PagingTimeWindow},
+    error::Error,
+    power::LtePowerConfig,
+    sim::SimulatedModem,
+    ConnectionPreference, Modem, SystemMode,
+};
+use std::time::Duration;
+
+fn modem(sim: SimulatedModem, lte_support: bool, nbiot_support: bool) -> Modem<SimulatedModem> {
+    let mode = SystemMode {
+        lte_support,
+        nbiot_support,
+        gnss_support: false,
+        preference: ConnectionPreference::None,
+    };
+    let mut modem = Modem::with_backend(sim, None, mode, LtePowerConfig::default()).unwrap();
+    modem.backend().take_transcript();
+    modem
+}
+
+#[test]
+fn edrx_for_lte_m() {
+    let mut modem = modem(SimulatedModem::new(), true, false);
+
+    modem
+        .set_edrx(EdrxConfig {
+            cycle: EdrxCycle::Seconds81_92,
+            paging_time_window: PagingTimeWindow::new(3),
+        })
+        .unwrap();
+
+    assert_eq!(
+        modem.backend().take_transcript(),
+        ["AT+CEDRXS=2,4,\"0101\"", "AT%XPTW=4,\"0011\""]
+    );
+}
+
+#[test]
+fn edrx_for_every_enabled_access_technology() {
+    let mut modem = modem(SimulatedModem::new(), true, true);
+
+    modem
+        .set_edrx(EdrxConfig {
+            cycle: EdrxCycle::Seconds163_84,
+            paging_time_window: None,
+        })
+        .unwrap();
+
+    assert_eq!(
+        modem.backend().take_transcript(),
+        ["AT+CEDRXS=2,4,\"1001\"", "AT+CEDRXS=2,5,\"1001\""]
+    );
+}
+
+#[test]
+fn edrx_cycle_not_supported_by_nbiot() {
+    let mut modem = modem(SimulatedModem::new(), false, true);
+
+    assert!(matches!(
+        modem.set_edrx(EdrxConfig {
+            cycle: EdrxCycle::Seconds5_12,
+            paging_time_window: None,
+        }),
+        Err(Error::InvalidConfiguration)
+    ));
+    assert!(modem.backend().transcript().is_empty());
+}
+
+#[test]
+fn edrx_disable() {
+    let mut modem = modem(SimulatedModem::new(), true, false);
+
+    modem.disable_edrx().unwrap();
+
+    assert_eq!(modem.backend().take_transcript(), ["AT+CEDRXS=3"]);
+}
+
+#[test]
+fn edrx_notification() {
+    let status = EdrxStatus::parse_notification("+CEDRXP: 4,\"1000\",\"0101\",\"1011\"").unwrap();
+
+    assert_eq!(
+        status,
+        EdrxStatus {
+            access_technology: Some(EdrxAccessTechnology::LteM),
+            requested_cycle: Some(EdrxCycle::Seconds143_36),
+            cycle: Some(EdrxCycle::Seconds81_92),
+            paging_time_window: PagingTimeWindow::new(11),
+        }
+    );
+    assert_eq!(
+        status.cycle.unwrap().duration(),
+        Duration::from_millis(81920)
+    );
+    assert_eq!(
+        status
+            .paging_time_window
+            .unwrap()
+            .duration(EdrxAccessTechnology::LteM),
+        Duration::from_millis(15360)
+    );
+}
+
+#[test]
+fn edrx_notification_without_edrx() {
+    let status = EdrxStatus::parse_notification("+CEDRXP: 0").unwrap();
+
+    assert_eq!(status.access_technology, None);
+    assert_eq!(status.cycle, None);
+}
+
+#[test]
+fn edrx_notification_invalid() {
+    assert!(EdrxStatus::parse_notification("+CEDRXP: 4,\"10\"").is_err());
+    assert!(EdrxStatus::parse_notification("+CEREG: 1").is_err());
+}
+
+#[test]
+fn edrx_status() {
+    let mut sim = SimulatedModem::new();
+    sim.script(
+        "AT+CEDRXRDP",
+        "+CEDRXRDP: 5,\"1001\",\"1010\",\"0001\"\r\nOK",
+    );
+    let mut modem = modem(sim, false, true);
+
+    let status = modem.edrx_status().unwrap();
+
+    assert_eq!(status.access_technology, Some(EdrxAccessTechnology::NbIot));
+    assert_eq!(status.cycle, Some(EdrxCycle::Seconds327_68));
+    assert_eq!(
+        status
+            .paging_time_window
+            .unwrap()
+            .duration(EdrxAccessTechnology::NbIot),
+        Duration::from_millis(5120)
+    );
+}
+
+#[test]
+fn edrx_cycle_durations() {
+    assert_eq!(
+        EdrxCycle::Seconds5_12.duration(),
+        Duration::from_millis(5120)
+    );
+    assert_eq!(
+        EdrxCycle::Seconds61_44.duration(),
+        Duration::from_millis(61440)
+    );
+    assert_eq!(
+        EdrxCycle::Seconds10485_76.duration(),
+        Duration::from_millis(10485760)
+    );
+}
@@ -0,0 +1,87 @@
+use nrf_modem_nal::{
+    embedded_nal::{nb, SocketAddr, TcpClientStack},
+    error::{AtError, CmeError, Errno, Error, ErrorKind},
+    sim::SimulatedModem,
+};
+use std::net::TcpListener;
+
+mod common;
+
+#[test]
+fn kinds() {
+    assert_eq!(
+        Error::NrfSys(Errno::ConnectionReset).kind(),
+        ErrorKind::ConnectionReset
+    );
+    assert_eq!(
+        Error::NrfSys(Errno::WouldBlock).kind(),
+        ErrorKind::WouldBlock
+    );
+    assert_eq!(
+        Error::NrfSys(Errno::NetworkUnreachable).kind(),
+        ErrorKind::NetworkDown
+    );
+    assert_eq!(Error::LteRegistrationDenied.kind(), ErrorKind::NetworkDown);
+    assert_eq!(Error::SocketClosed.kind(), ErrorKind::NotConnected);
+    assert_eq!(Error::AddressNotFound.kind(), ErrorKind::AddressNotFound);
+    assert_eq!(Error::UnexpectedAtResponse.kind(), ErrorKind::InvalidData);
+    assert_eq!(Error::NotSupported.kind(), ErrorKind::Unsupported);
+    assert_eq!(Error::NrfSys(Errno::Other(1000)).kind(), ErrorKind::Other);
+}
+
+#[test]
+fn display() {
+    assert_eq!(
+        Error::NrfSys(Errno::TimedOut).to_string(),
+        "modem library error 60: connection timed out"
+    );
+    assert_eq!(
+        Error::AtError(AtError::CmeError(CmeError::SimNotInserted)).to_string(),
+        "AT command failed with +CME ERROR: 10 (SIM not inserted)"
+    );
+    assert_eq!(
+        Error::BufferTooSmall(Some(64)).to_string(),
+        "buffer too small, 64 bytes are needed"
+    );
+
+    // The nested parse error of the AT response
+    let parse_error = at_commands::parser::CommandParser::parse(b"+CEREG: x")
+        .expect_identifier(b"+CEREG:")
+        .expect_int_parameter()
+        .finish()
+        .unwrap_err();
+    assert!(Error::from(parse_error)
+        .to_string()
+        .starts_with("could not parse the AT response"));
+}
+
+#[test]
+fn connection_refused_kind() {
+    // Bind and drop a listener to get a local port nobody listens on
+    let closed = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let server: SocketAddr = "203.0.113.1:7".parse().unwrap();
+
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 0,1\r\nOK");
+    sim.redirect(server, closed);
+    let mut modem = common::modem(sim);
+
+    let mut socket = TcpClientStack::socket(&mut modem).unwrap();
+    match TcpClientStack::connect(&mut modem, &mut socket, server) {
+        Err(nb::Error::Other(error)) => {
+            assert_eq!(error.kind(), ErrorKind::ConnectionRefused);
+
+            #[cfg(feature = "async")]
+            assert_eq!(
+                embedded_io_async::Error::kind(&error),
+                embedded_io_async::ErrorKind::ConnectionRefused
+            );
+        }
+        result => panic!("Unexpected result: {result:?}"),
+    }
+
+    TcpClientStack::close(&mut modem, socket).unwrap();
+}
@@ -0,0 +1,290 @@
+use nrf_modem_nal::{
+    embedded_nal::{nb, TcpClientStack, UdpClientStack},
+    error::Error,
+    pdn::{PdnActivationReason, PdnAuthentication, PdnEvent, PdnFamily, PdpContext},
+    sim::SimulatedModem,
+    tls::{PeerVerification, TlsOptions},
+};
+
+mod common;
+use common::modem;
+
+#[test]
+fn define_pdp_context() {
+    let mut modem = modem(SimulatedModem::new());
+
+    modem
+        .define_pdp_context(&PdpContext {
+            cid: 1,
+            family: PdnFamily::Ipv4v6,
+            apn: "company.apn",
+        })
+        .unwrap();
+    modem
+        .define_pdp_context(&PdpContext {
+            cid: 2,
+            family: PdnFamily::NonIp,
+            apn: "nidd.apn",
+        })
+        .unwrap();
+    modem.delete_pdp_context(2).unwrap();
+
+    assert_eq!(
+        modem.backend().take_transcript(),
+        [
+            "AT+CGDCONT=1,\"IPV4V6\",\"company.apn\"",
+            "AT+CGDCONT=2,\"Non-IP\",\"nidd.apn\"",
+            "AT+CGDCONT=2",
+        ]
+    );
+
+    let result = modem.define_pdp_context(&PdpContext {
+        cid: 1,
+        family: PdnFamily::Ipv4,
+        apn: "\"quoted\"",
+    });
+    assert!(matches!(result, Err(Error::InvalidConfiguration)));
+    assert!(modem.backend().transcript().is_empty());
+}
+
+#[test]
+fn pdp_contexts() {
+    let mut sim = SimulatedModem::new();
+    sim.script(
+        "AT+CGDCONT?",
+        "+CGDCONT: 0,\"IP\",\"internet\",\"10.0.0.2\",0,0\r\n+CGDCONT: 1,\"IPV6\",\"company.apn\",\"\",0,0\r\nOK",
+    );
+    let mut modem = modem(sim);
+
+    let mut contexts = Vec::new();
+    modem
+        .pdp_contexts(|context| {
+            contexts.push((context.cid, context.family, context.apn.to_string()))
+        })
+        .unwrap();
+
+    assert_eq!(
+        contexts,
+        [
+            (0, PdnFamily::Ipv4, "internet".to_string()),
+            (1, PdnFamily::Ipv6, "company.apn".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn pdn_authentication() {
+    let mut modem = modem(SimulatedModem::new());
+
+    modem
+        .set_pdn_authentication(
+            1,
+            PdnAuthentication::Chap {
+                username: "user",
+                password: "secret",
+            },
+        )
+        .unwrap();
+    modem
+        .set_pdn_authentication(
+            2,
+            PdnAuthentication::Pap {
+                username: "user",
+                password: "secret",
+            },
+        )
+        .unwrap();
+    modem
+        .set_pdn_authentication(1, PdnAuthentication::None)
+        .unwrap();
+
+    assert_eq!(
+        modem.backend().take_transcript(),
+        [
+            "AT+CGAUTH=1,2,\"user\",\"secret\"",
+            "AT+CGAUTH=2,1,\"user\",\"secret\"",
+            "AT+CGAUTH=1,0",
+        ]
+    );
+}
+
+#[test]
+fn activate_pdn() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 0,1\r\nOK");
+    sim.script("AT+CGACT?", "+CGACT: 0,1\r\n+CGACT: 1,0\r\nOK");
+    let mut modem = modem(sim);
+
+    nb::block!(modem.activate_pdn(1)).unwrap();
+    modem.deactivate_pdn(1).unwrap();
+
+    let mut states = Vec::new();
+    modem
+        .pdn_states(|cid, active| states.push((cid, active)))
+        .unwrap();
+    assert_eq!(states, [(0, true), (1, false)]);
+
+    // The PDN connection turned LTE on and off
+    assert_eq!(
+        modem.backend().take_transcript(),
+        [
+            "AT%XDATAPRFL=0",
+            "AT+CEPPI=1",
+            "AT+CPSMS=1",
+            "AT+CFUN=21",
+            "AT+CEREG?",
+            "AT+CGACT=1,1",
+            "AT+CGACT=0,1",
+            "AT+CFUN=20",
+            "AT+CFUN=40",
+            "AT+CGACT?",
+        ]
+    );
+}
+
+#[test]
+fn active_pdn_keeps_lte_on() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 0,1\r\nOK");
+    let mut modem = modem(sim);
+
+    let mut lte = modem.lte_socket().unwrap();
+    nb::block!(modem.lte_connect(&mut lte)).unwrap();
+    nb::block!(modem.activate_pdn(1)).unwrap();
+    modem.backend().take_transcript();
+
+    // Closing the last socket doesn't drop the PDN connection
+    modem.lte_close(lte).unwrap();
+    assert!(modem.backend().take_transcript().is_empty());
+
+    modem.deactivate_pdn(1).unwrap();
+    assert_eq!(
+        modem.backend().take_transcript(),
+        ["AT+CGACT=0,1", "AT+CFUN=20", "AT+CFUN=40"]
+    );
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn activate_pdn_denied() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 0,3\r\nOK");
+    let mut modem = modem(sim);
+
+    assert!(matches!(
+        modem.activate_pdn(1),
+        Err(nb::Error::Other(Error::LteRegistrationDenied))
+    ));
+    // LTE is turned off again, without activating the PDN connection
+    assert!(modem
+        .backend()
+        .take_transcript()
+        .ends_with(&["AT+CFUN=20".to_string(), "AT+CFUN=40".to_string()]));
+
+    assert!(matches!(
+        modem.activate_pdn(32),
+        Err(nb::Error::Other(Error::InvalidConfiguration))
+    ));
+}
+
+#[test]
+fn pdn_events() {
+    let mut modem = modem(SimulatedModem::new());
+    modem.enable_pdn_events().unwrap();
+    modem.disable_pdn_events().unwrap();
+    assert_eq!(
+        modem.backend().take_transcript(),
+        ["AT+CGEREP=1", "AT+CGEREP=0"]
+    );
+
+    assert_eq!(
+        PdnEvent::parse_notification("+CGEV: ME PDN ACT 1").unwrap(),
+        PdnEvent::Activated {
+            cid: 1,
+            reason: None
+        }
+    );
+    assert_eq!(
+        PdnEvent::parse_notification("+CGEV: ME PDN ACT 0,2\r\n").unwrap(),
+        PdnEvent::Activated {
+            cid: 0,
+            reason: Some(PdnActivationReason::SingleAddressBearersOnly)
+        }
+    );
+    assert_eq!(
+        PdnEvent::parse_notification("+CGEV: NW PDN DEACT 1").unwrap(),
+        PdnEvent::Deactivated {
+            cid: 1,
+            by_network: true
+        }
+    );
+    assert_eq!(
+        PdnEvent::parse_notification("+CGEV: ME PDN DEACT 1").unwrap(),
+        PdnEvent::Deactivated {
+            cid: 1,
+            by_network: false
+        }
+    );
+    assert_eq!(
+        PdnEvent::parse_notification("+CGEV: IPV6 0").unwrap(),
+        PdnEvent::Ipv6LinkUp { cid: 0 }
+    );
+    assert_eq!(
+        PdnEvent::parse_notification("+CGEV: NW DETACH").unwrap(),
+        PdnEvent::Detached { by_network: true }
+    );
+    assert!(matches!(
+        PdnEvent::parse_notification("+CGEV: ME PDN ACT x"),
+        Err(Error::UnexpectedAtResponse)
+    ));
+    assert!(matches!(
+        PdnEvent::parse_notification("+CEREG: 1"),
+        Err(Error::UnexpectedAtResponse)
+    ));
+}
+
+#[test]
+fn bind_to_pdn() {
+    let mut modem = modem(SimulatedModem::new());
+
+    let tcp = TcpClientStack::socket(&mut modem).unwrap();
+    modem.bind_to_pdn(&tcp, "company.apn").unwrap();
+    TcpClientStack::close(&mut modem, tcp).unwrap();
+
+    let udp = UdpClientStack::socket(&mut modem).unwrap();
+    modem.bind_to_pdn(&udp, "internet").unwrap();
+    UdpClientStack::close(&mut modem, udp).unwrap();
+
+    let mut stack = modem.tls(TlsOptions {
+        sec_tags: &[42],
+        peer_verification: PeerVerification::Required,
+        hostname: None,
+    });
+    let tls = stack.socket().unwrap();
+    stack.modem().bind_to_pdn(&tls, "company.apn").unwrap();
+    stack.close(tls).unwrap();
+
+    assert_eq!(
+        modem.backend().pdn_bindings(),
+        ["company.apn", "internet", "company.apn"]
+    );
+}
+
+#[test]
+fn bind_to_pdn_after_connect() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 0,2\r\nOK");
+    let mut modem = modem(sim);
+
+    let mut socket = TcpClientStack::socket(&mut modem).unwrap();
+    let result = TcpClientStack::connect(&mut modem, &mut socket, "203.0.113.1:7".parse().unwrap());
+    assert!(matches!(result, Err(nb::Error::WouldBlock)));
+
+    let result = modem.bind_to_pdn(&socket, "company.apn");
+    assert!(matches!(result, Err(Error::SocketAlreadyOpen)));
+    let result = modem.bind_to_pdn(&socket, "");
+    assert!(matches!(result, Err(Error::InvalidConfiguration)));
+
+    TcpClientStack::close(&mut modem, socket).unwrap();
+    assert!(modem.backend().pdn_bindings().is_empty());
+}
@@ -0,0 +1,226 @@
+use nrf_modem_nal::{
+    embedded_nal::nb,
+    error::Error,
+    power::{DataProfile, LtePowerConfig},
+    psm::PsmConfig,
+    sim::SimulatedModem,
+    ConnectionPreference, Modem, SystemMode,
+};
+use std::time::Duration;
+
+const LTE_AND_GNSS: SystemMode = SystemMode {
+    lte_support: true,
+    nbiot_support: false,
+    gnss_support: true,
+    preference: ConnectionPreference::None,
+};
+
+/// A simulated modem that is registered to the network
+fn registered() -> SimulatedModem {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 0,1\r\nOK");
+    sim
+}
+
+fn modem(sim: SimulatedModem) -> Modem<SimulatedModem> {
+    let mut modem =
+        Modem::with_backend(sim, None, LTE_AND_GNSS, LtePowerConfig::default()).unwrap();
+    modem.backend().take_transcript();
+    modem
+}
+
+#[test]
+fn new_sets_system_mode() {
+    let mut modem = Modem::with_backend(
+        SimulatedModem::new(),
+        None,
+        LTE_AND_GNSS,
+        LtePowerConfig::default(),
+    )
+    .unwrap();
+
+    assert_eq!(modem.backend().transcript(), ["AT%XSYSTEMMODE=1,0,1,0"]);
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn system_mode_not_allowed_in_active_state() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT%XSYSTEMMODE", "+CME ERROR: 518");
+
+    assert!(matches!(
+        Modem::with_backend(sim, None, LTE_AND_GNSS, LtePowerConfig::default()),
+        Err(Error::NotAllowedInActiveState)
+    ));
+}
+
+#[test]
+fn lte_turns_on_with_power_saving() {
+    let mut modem = modem(registered());
+
+    let mut lte = modem.lte_socket().unwrap();
+    nb::block!(modem.lte_connect(&mut lte)).unwrap();
+
+    assert_eq!(
+        modem.backend().take_transcript(),
+        [
+            "AT%XDATAPRFL=0",
+            "AT+CEPPI=1",
+            "AT+CPSMS=1",
+            "AT+CFUN=21",
+            "AT+CEREG?"
+        ]
+    );
+
+    modem.lte_close(lte).unwrap();
+
+    assert_eq!(
+        modem.backend().take_transcript(),
+        ["AT+CFUN=20", "AT+CFUN=40"]
+    );
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn lte_waits_for_registration() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 0,2\r\nOK").times(3);
+    sim.script("AT+CEREG?", "+CEREG: 0,1\r\nOK");
+    let mut modem = modem(sim);
+
+    let mut lte = modem.lte_socket().unwrap();
+    for _ in 0..3 {
+        assert!(matches!(
+            modem.lte_connect(&mut lte),
+            Err(nb::Error::WouldBlock)
+        ));
+        assert!(!modem.lte_is_connected(&mut lte));
+    }
+    modem.lte_connect(&mut lte).unwrap();
+    assert!(modem.lte_is_connected(&mut lte));
+
+    let transcript = modem.backend().take_transcript();
+    assert_eq!(transcript.iter().filter(|c| *c == "AT+CFUN=21").count(), 1);
+    assert_eq!(transcript.iter().filter(|c| *c == "AT+CEREG?").count(), 4);
+
+    modem.lte_close(lte).unwrap();
+}
+
+#[test]
+fn lte_registration_denied() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 0,3\r\nOK");
+    let mut modem = modem(sim);
+
+    let mut lte = modem.lte_socket().unwrap();
+    assert!(matches!(
+        modem.lte_connect(&mut lte),
+        Err(nb::Error::Other(Error::LteRegistrationDenied))
+    ));
+    modem.lte_close(lte).unwrap();
+}
+
+#[test]
+fn lte_stays_on_while_sockets_are_open() {
+    let mut modem = modem(registered());
+
+    let mut first = modem.lte_socket().unwrap();
+    let mut second = modem.lte_socket().unwrap();
+    nb::block!(modem.lte_connect(&mut first)).unwrap();
+    nb::block!(modem.lte_connect(&mut second)).unwrap();
+    modem.lte_close(first).unwrap();
+
+    let transcript = modem.backend().take_transcript();
+    assert_eq!(
+        transcript
+            .iter()
+            .filter(|c| c.starts_with("AT+CFUN"))
+            .count(),
+        1
+    );
+
+    modem.lte_close(second).unwrap();
+
+    assert_eq!(
+        modem.backend().take_transcript(),
+        ["AT+CFUN=20", "AT+CFUN=40"]
+    );
+}
+
+#[test]
+fn lte_power_config_is_applied() {
+    let mut modem = modem(registered());
+    modem
+        .set_lte_power_config(LtePowerConfig {
+            data_profile: DataProfile::HighPerformance,
+            uicc_power_saving: false,
+            psm: None,
+            extra_commands: &["AT%XBANDLOCK=0"],
+        })
+        .unwrap();
+    assert!(modem.backend().transcript().is_empty());
+
+    let mut lte = modem.lte_socket().unwrap();
+    nb::block!(modem.lte_connect(&mut lte)).unwrap();
+
+    assert_eq!(
+        modem.backend().take_transcript(),
+        [
+            "AT%XDATAPRFL=4",
+            "AT+CEPPI=0",
+            "AT+CPSMS=0",
+            "AT%XBANDLOCK=0",
+            "AT+CFUN=21",
+            "AT+CEREG?"
+        ]
+    );
+
+    modem.lte_close(lte).unwrap();
+}
+
+#[test]
+fn lte_power_config_with_psm_timers() {
+    let mut modem = modem(registered());
+    modem
+        .set_lte_power_config(LtePowerConfig {
+            psm: Some(PsmConfig {
+                periodic_tau: Some(Duration::from_secs(60 * 60)),
+                active_time: Some(Duration::from_secs(10)),
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let mut lte = modem.lte_socket().unwrap();
+    nb::block!(modem.lte_connect(&mut lte)).unwrap();
+
+    assert!(modem
+        .backend()
+        .take_transcript()
+        .contains(&"AT+CPSMS=1,,,\"00100001\",\"00000101\"".to_string()));
+
+    modem.lte_close(lte).unwrap();
+}
+
+#[test]
+fn lte_power_config_changed_while_on() {
+    let mut modem = modem(registered());
+
+    let mut lte = modem.lte_socket().unwrap();
+    nb::block!(modem.lte_connect(&mut lte)).unwrap();
+    modem.backend().take_transcript();
+
+    modem
+        .set_lte_power_config(LtePowerConfig {
+            data_profile: DataProfile::Normal,
+            ..Default::default()
+        })
+        .unwrap();
+
+    assert_eq!(
+        modem.backend().take_transcript(),
+        ["AT%XDATAPRFL=2", "AT+CEPPI=1", "AT+CPSMS=1"]
+    );
+
+    modem.lte_close(lte).unwrap();
+}
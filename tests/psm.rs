@@ -0,0 +1,138 @@
+use nrf_modem_nal::{
+    error::Error,
+    psm::{PsmConfig, PsmStatus},
+    sim::SimulatedModem,
+};
+use std::time::Duration;
+
+mod common;
+use common::modem;
+
+fn psm_command(config: PsmConfig) -> String {
+    let mut modem = modem(SimulatedModem::new());
+    modem.set_psm(config).unwrap();
+    modem.backend().take_transcript().pop().unwrap()
+}
+
+#[test]
+fn psm_without_timers() {
+    assert_eq!(psm_command(PsmConfig::default()), "AT+CPSMS=1");
+}
+
+#[test]
+fn psm_timers_are_encoded() {
+    assert_eq!(
+        psm_command(PsmConfig {
+            periodic_tau: Some(Duration::from_secs(60 * 60)),
+            active_time: Some(Duration::from_secs(10)),
+        }),
+        "AT+CPSMS=1,,,\"00100001\",\"00000101\""
+    );
+    assert_eq!(
+        psm_command(PsmConfig {
+            periodic_tau: Some(Duration::from_secs(24 * 60 * 60)),
+            active_time: Some(Duration::from_secs(2 * 60)),
+        }),
+        "AT+CPSMS=1,,,\"00111000\",\"00100010\""
+    );
+    assert_eq!(
+        psm_command(PsmConfig {
+            periodic_tau: None,
+            active_time: Some(Duration::from_secs(30 * 60)),
+        }),
+        "AT+CPSMS=1,,,,\"00111110\""
+    );
+}
+
+#[test]
+fn psm_timers_are_rounded_up() {
+    // 61 seconds can't be encoded, so the closest value above it is used: 31 * 2 seconds
+    assert_eq!(
+        psm_command(PsmConfig {
+            periodic_tau: None,
+            active_time: Some(Duration::from_secs(61)),
+        }),
+        "AT+CPSMS=1,,,,\"00011111\""
+    );
+    // Just over 5 minutes becomes 6 minutes
+    assert_eq!(
+        psm_command(PsmConfig {
+            periodic_tau: None,
+            active_time: Some(Duration::from_millis(5 * 60 * 1000 + 1)),
+        }),
+        "AT+CPSMS=1,,,,\"00100110\""
+    );
+}
+
+#[test]
+fn psm_timer_too_long() {
+    let mut modem = modem(SimulatedModem::new());
+
+    assert!(matches!(
+        modem.set_psm(PsmConfig {
+            periodic_tau: None,
+            active_time: Some(Duration::from_secs(4 * 60 * 60)),
+        }),
+        Err(Error::InvalidConfiguration)
+    ));
+    assert!(modem.backend().transcript().is_empty());
+}
+
+#[test]
+fn psm_config_is_kept_for_lte() {
+    let mut modem = modem(SimulatedModem::new());
+    let config = PsmConfig {
+        periodic_tau: Some(Duration::from_secs(60 * 60)),
+        active_time: Some(Duration::from_secs(10)),
+    };
+    modem.set_psm(config).unwrap();
+
+    assert_eq!(modem.lte_power_config().psm, Some(config));
+}
+
+#[test]
+fn psm_status_granted() {
+    let mut sim = SimulatedModem::new();
+    sim.script(
+        "AT%XMONITOR",
+        "%XMONITOR: 1,\"EDAV\",\"EDAV\",\"26295\",\"00B7\",7,4,\"00011B07\",7,2300,63,39,\"\",\
+         \"00000101\",\"00100001\",\"00111000\"\r\nOK",
+    );
+    let mut modem = modem(sim);
+
+    let status = modem.psm_status().unwrap();
+
+    assert!(status.is_enabled());
+    assert_eq!(
+        status,
+        PsmStatus {
+            periodic_tau: Some(Duration::from_secs(60 * 60)),
+            active_time: Some(Duration::from_secs(10)),
+        }
+    );
+}
+
+#[test]
+fn psm_status_legacy_tau_and_no_psm() {
+    let mut sim = SimulatedModem::new();
+    sim.script(
+        "AT%XMONITOR",
+        "%XMONITOR: 5,\"\",\"\",\"20404\",\"0C3E\",7,20,\"0102D10B\",83,6400,40,22,\"\",\
+         \"11100000\",\"11100000\",\"00101010\"\r\nOK",
+    );
+    let mut modem = modem(sim);
+
+    let status = modem.psm_status().unwrap();
+
+    assert!(!status.is_enabled());
+    assert_eq!(status.periodic_tau, Some(Duration::from_secs(10 * 60)));
+}
+
+#[test]
+fn psm_status_not_registered() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT%XMONITOR", "%XMONITOR: 0\r\nOK");
+    let mut modem = modem(sim);
+
+    assert_eq!(modem.psm_status().unwrap(), PsmStatus::default());
+}
@@ -0,0 +1,125 @@
+use nrf_modem_nal::{
+    error::Error,
+    registration::{AccessTechnology, RegistrationState, RegistrationStatus, RejectCause},
+    sim::SimulatedModem,
+};
+use std::time::Duration;
+
+mod common;
+use common::modem;
+
+#[test]
+fn registration_status() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 0,1\r\nOK").times(1);
+    sim.script(
+        "AT+CEREG?",
+        "+CEREG: 5,1,\"0140\",\"00011B07\",7,,,\"00100001\",\"00000110\"\r\nOK",
+    );
+    let mut modem = modem(sim);
+
+    let status = modem.registration_status().unwrap();
+    assert_eq!(
+        status,
+        RegistrationStatus {
+            state: RegistrationState::RegisteredHome,
+            tracking_area_code: Some(0x0140),
+            cell_id: Some(0x00011B07),
+            access_technology: Some(AccessTechnology::LteM),
+            reject_cause: None,
+            active_time: Some(Duration::from_secs(60)),
+            periodic_tau: Some(Duration::from_secs(60 * 60)),
+        }
+    );
+    assert!(status.state.is_registered());
+
+    assert_eq!(
+        modem.backend().take_transcript(),
+        ["AT+CEREG?", "AT+CEREG=5", "AT+CEREG?", "AT+CEREG=0"]
+    );
+}
+
+#[test]
+fn registration_status_keeps_mode() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 2,1,\"0140\",\"00011B07\",7\r\nOK")
+        .times(1);
+    sim.script("AT+CEREG?", "+CEREG: 5,1,\"0140\",\"00011B07\",7\r\nOK")
+        .times(1);
+    sim.script("AT+CEREG?", "+CEREG: 5,5\r\nOK");
+    let mut modem = modem(sim);
+
+    // The mode that was set is restored
+    modem.registration_status().unwrap();
+    assert_eq!(
+        modem.backend().take_transcript(),
+        ["AT+CEREG?", "AT+CEREG=5", "AT+CEREG?", "AT+CEREG=2"]
+    );
+
+    // Mode 5 is left alone
+    let status = modem.registration_status().unwrap();
+    assert_eq!(status.state, RegistrationState::RegisteredRoaming);
+    assert_eq!(
+        modem.backend().take_transcript(),
+        ["AT+CEREG?", "AT+CEREG?"]
+    );
+}
+
+#[test]
+fn registration_denied() {
+    let mut sim = SimulatedModem::new();
+    // A notification comes in before the response
+    sim.script(
+        "AT+CEREG?",
+        "+CEREG: 2,\"0140\",\"00011B07\",9\r\n+CEREG: 5,3,\"0140\",\"00011B07\",9,0,15\r\nOK",
+    );
+    let mut modem = modem(sim);
+
+    let status = modem.registration_status().unwrap();
+    assert_eq!(status.state, RegistrationState::Denied);
+    assert_eq!(status.access_technology, Some(AccessTechnology::NbIot));
+    assert_eq!(status.reject_cause, Some(RejectCause::Emm(15)));
+    assert_eq!(status.active_time, None);
+    assert!(!status.state.is_registered());
+}
+
+#[test]
+fn registration_status_not_registered() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 5,0\r\nOK");
+    let mut modem = modem(sim);
+
+    let status = modem.registration_status().unwrap();
+    assert_eq!(status.state, RegistrationState::NotRegistered);
+    assert_eq!(status.tracking_area_code, None);
+    assert_eq!(status.cell_id, None);
+}
+
+#[test]
+fn registration_notification() {
+    let status = RegistrationStatus::parse_notification(
+        "+CEREG: 5,\"0140\",\"00011B07\",7,1,42,\"11100000\",\"00000001\"\r\n",
+    )
+    .unwrap();
+
+    assert_eq!(status.state, RegistrationState::RegisteredRoaming);
+    assert_eq!(
+        status.reject_cause,
+        Some(RejectCause::ManufacturerSpecific(42))
+    );
+    // PSM is not granted
+    assert_eq!(status.active_time, None);
+    assert_eq!(status.periodic_tau, Some(Duration::from_secs(10 * 60)));
+
+    let status = RegistrationStatus::parse_notification("+CEREG: 2").unwrap();
+    assert_eq!(status.state, RegistrationState::Searching);
+
+    assert!(matches!(
+        RegistrationStatus::parse_notification("+CEREG: 7"),
+        Err(Error::UnexpectedAtResponse)
+    ));
+    assert!(matches!(
+        RegistrationStatus::parse_notification("+CSCON: 1"),
+        Err(Error::UnexpectedAtResponse)
+    ));
+}
@@ -0,0 +1,82 @@
+use nrf_modem_nal::{
+    embedded_nal::{nb, SocketAddr, TcpClientStack},
+    error::Error,
+    shared::SharedModem,
+    sim::SimulatedModem,
+};
+use std::net::TcpListener;
+
+mod common;
+
+/// The address the code under test connects to, which is redirected to a local server
+fn server_address() -> SocketAddr {
+    "203.0.113.1:7".parse().unwrap()
+}
+
+fn modem() -> SharedModem<SimulatedModem> {
+    // The listener is leaked, so connections are accepted by the OS without a server thread
+    let listener = Box::leak(Box::new(TcpListener::bind("127.0.0.1:0").unwrap()));
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 0,1\r\nOK");
+    sim.redirect(server_address(), listener.local_addr().unwrap());
+
+    SharedModem::new(common::modem(sim))
+}
+
+fn take_transcript(modem: &SharedModem<SimulatedModem>) -> Vec<String> {
+    modem.with_modem(|modem| modem.backend().take_transcript())
+}
+
+#[test]
+fn dropped_socket_turns_lte_off() {
+    let modem = modem();
+
+    {
+        let mut socket = modem.tcp_socket().unwrap();
+        socket
+            .with(|modem, socket| nb::block!(modem.connect(socket, server_address())))
+            .unwrap();
+        assert!(take_transcript(&modem).contains(&"AT+CFUN=21".to_string()));
+    }
+
+    assert_eq!(take_transcript(&modem), ["AT+CFUN=20", "AT+CFUN=40"]);
+    assert_eq!(modem.with_modem(|modem| modem.backend().open_sockets()), 0);
+}
+
+#[test]
+fn early_return_closes_socket() {
+    fn connect_and_fail(modem: &SharedModem<SimulatedModem>) -> Result<(), Error> {
+        let mut socket = modem.tcp_socket()?;
+        socket.with(|modem, socket| nb::block!(modem.connect(socket, server_address())))?;
+        Err(Error::SocketClosed)
+    }
+
+    let modem = modem();
+
+    assert!(matches!(connect_and_fail(&modem), Err(Error::SocketClosed)));
+    assert!(
+        take_transcript(&modem).ends_with(&["AT+CFUN=20".to_string(), "AT+CFUN=40".to_string()])
+    );
+
+    // LTE can be turned on again
+    let mut socket = modem.tcp_socket().unwrap();
+    socket
+        .with(|modem, socket| nb::block!(modem.connect(socket, server_address())))
+        .unwrap();
+    assert!(socket
+        .with(|modem, socket| TcpClientStack::is_connected(modem, socket))
+        .unwrap());
+    socket.close().unwrap();
+    assert_eq!(modem.with_modem(|modem| modem.backend().open_sockets()), 0);
+}
+
+#[test]
+fn unconnected_socket_is_closed() {
+    let modem = modem();
+
+    drop(modem.udp_socket().unwrap());
+    drop(modem.at_socket().unwrap());
+
+    assert!(take_transcript(&modem).is_empty());
+    assert_eq!(modem.into_inner().backend().open_sockets(), 0);
+}
@@ -0,0 +1,450 @@
+use nrf_modem_nal::{
+    embedded_nal::{nb, SocketAddr, TcpClientStack, TcpFullStack, UdpClientStack, UdpFullStack},
+    error::{Errno, Error},
+    sim::SimulatedModem,
+    Modem,
+};
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream, UdpSocket},
+    thread,
+};
+
+mod common;
+
+/// The address the code under test connects to, which is redirected to a local server
+fn server_address() -> SocketAddr {
+    "203.0.113.1:7".parse().unwrap()
+}
+
+fn modem(sim: SimulatedModem) -> Modem<SimulatedModem> {
+    let mut sim = sim;
+    sim.script("AT+CEREG?", "+CEREG: 0,1\r\nOK");
+    common::modem(sim)
+}
+
+/// Start a TCP server on localhost that echoes everything back on the first connection
+fn tcp_echo_server() -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buffer = [0; 64];
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(length) => stream.write_all(&buffer[..length]).unwrap(),
+            }
+        }
+    });
+
+    address
+}
+
+/// Start a UDP server on localhost that echoes every datagram back to its sender
+fn udp_echo_server() -> std::net::SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = socket.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let mut buffer = [0; 64];
+        while let Ok((length, sender)) = socket.recv_from(&mut buffer) {
+            socket.send_to(&buffer[..length], sender).unwrap();
+        }
+    });
+
+    address
+}
+
+#[test]
+fn tcp_echo() {
+    let mut sim = SimulatedModem::new();
+    sim.redirect(server_address(), tcp_echo_server());
+    let mut modem = modem(sim);
+
+    let mut socket = TcpClientStack::socket(&mut modem).unwrap();
+    nb::block!(TcpClientStack::connect(
+        &mut modem,
+        &mut socket,
+        server_address()
+    ))
+    .unwrap();
+    assert!(TcpClientStack::is_connected(&mut modem, &socket).unwrap());
+
+    let sent = nb::block!(TcpClientStack::send(&mut modem, &mut socket, b"hello")).unwrap();
+    assert_eq!(sent, 5);
+
+    let mut buffer = [0; 16];
+    let received = nb::block!(TcpClientStack::receive(
+        &mut modem,
+        &mut socket,
+        &mut buffer
+    ))
+    .unwrap();
+    assert_eq!(&buffer[..received], b"hello");
+
+    TcpClientStack::close(&mut modem, socket).unwrap();
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn tcp_receive_would_block_without_data() {
+    let mut sim = SimulatedModem::new();
+    sim.redirect(server_address(), tcp_echo_server());
+    let mut modem = modem(sim);
+
+    let mut socket = TcpClientStack::socket(&mut modem).unwrap();
+    nb::block!(TcpClientStack::connect(
+        &mut modem,
+        &mut socket,
+        server_address()
+    ))
+    .unwrap();
+
+    let mut buffer = [0; 16];
+    assert!(matches!(
+        TcpClientStack::receive(&mut modem, &mut socket, &mut buffer),
+        Err(nb::Error::WouldBlock)
+    ));
+
+    TcpClientStack::close(&mut modem, socket).unwrap();
+}
+
+#[test]
+fn tcp_send_before_connect() {
+    let mut modem = modem(SimulatedModem::new());
+
+    let mut socket = TcpClientStack::socket(&mut modem).unwrap();
+    assert!(matches!(
+        TcpClientStack::send(&mut modem, &mut socket, b"hello"),
+        Err(nb::Error::Other(Error::SocketClosed))
+    ));
+
+    let mut buffer = [0; 16];
+    assert!(matches!(
+        TcpClientStack::receive(&mut modem, &mut socket, &mut buffer),
+        Err(nb::Error::Other(Error::SocketClosed))
+    ));
+
+    TcpClientStack::close(&mut modem, socket).unwrap();
+}
+
+#[test]
+fn tcp_connection_refused() {
+    // Bind and drop a listener to get a local port nobody listens on
+    let closed = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let mut sim = SimulatedModem::new();
+    sim.redirect(server_address(), closed);
+    let mut modem = modem(sim);
+
+    let mut socket = TcpClientStack::socket(&mut modem).unwrap();
+    let error = match TcpClientStack::connect(&mut modem, &mut socket, server_address()) {
+        Err(nb::Error::Other(error)) => error,
+        result => panic!("Unexpected result: {result:?}"),
+    };
+    assert!(matches!(error, Error::NrfSys(Errno::ConnectionRefused)));
+    assert!(error.is_transient());
+    assert!(!TcpClientStack::is_connected(&mut modem, &socket).unwrap());
+
+    TcpClientStack::close(&mut modem, socket).unwrap();
+}
+
+#[test]
+fn tcp_connect_ipv6() {
+    let mut modem = modem(SimulatedModem::new());
+
+    // The sockets of the modem library are IPv4 only
+    let mut socket = TcpClientStack::socket(&mut modem).unwrap();
+    let result =
+        TcpClientStack::connect(&mut modem, &mut socket, "[2001:db8::1]:7".parse().unwrap());
+    assert!(matches!(
+        result,
+        Err(nb::Error::Other(Error::NrfSys(
+            Errno::AddressFamilyNotSupported
+        )))
+    ));
+
+    TcpClientStack::close(&mut modem, socket).unwrap();
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn errno_classification() {
+    assert_eq!(Errno::from_code(60), Errno::TimedOut);
+    assert_eq!(Errno::TimedOut.code(), 60);
+    assert_eq!(Errno::from_code(1000), Errno::Other(1000));
+    assert_eq!(Errno::NetworkDown.to_string(), "network is down");
+
+    assert!(Errno::NetworkDown.is_transient());
+    assert!(Errno::ConnectionReset.is_transient());
+    assert!(Errno::NoBufferSpace.is_transient());
+    assert!(!Errno::MessageTooLong.is_transient());
+    assert!(!Errno::NotSupported.is_transient());
+    assert!(!Errno::Other(1000).is_transient());
+
+    assert!(Error::NrfSys(Errno::HostUnreachable).is_transient());
+    assert!(!Error::InvalidConfiguration.is_transient());
+}
+
+#[test]
+fn udp_echo() {
+    let mut sim = SimulatedModem::new();
+    sim.redirect(server_address(), udp_echo_server());
+    let mut modem = modem(sim);
+
+    let mut socket = UdpClientStack::socket(&mut modem).unwrap();
+    UdpClientStack::connect(&mut modem, &mut socket, server_address()).unwrap();
+
+    nb::block!(UdpClientStack::send(&mut modem, &mut socket, b"ping")).unwrap();
+
+    let mut buffer = [0; 16];
+    let (received, from) = nb::block!(UdpClientStack::receive(
+        &mut modem,
+        &mut socket,
+        &mut buffer
+    ))
+    .unwrap();
+    assert_eq!(&buffer[..received], b"ping");
+    assert_eq!(from, server_address());
+
+    UdpClientStack::close(&mut modem, socket).unwrap();
+}
+
+#[test]
+fn udp_receive_before_connect() {
+    let mut modem = modem(SimulatedModem::new());
+
+    let mut socket = UdpClientStack::socket(&mut modem).unwrap();
+    let mut buffer = [0; 16];
+    assert!(matches!(
+        UdpClientStack::receive(&mut modem, &mut socket, &mut buffer),
+        Err(nb::Error::Other(Error::SocketClosed))
+    ));
+
+    UdpClientStack::close(&mut modem, socket).unwrap();
+}
+
+#[test]
+fn sockets_turn_lte_on_and_off() {
+    let mut sim = SimulatedModem::new();
+    sim.redirect(server_address(), tcp_echo_server());
+    let mut modem = modem(sim);
+    modem.backend().take_transcript();
+
+    let mut socket = TcpClientStack::socket(&mut modem).unwrap();
+    nb::block!(TcpClientStack::connect(
+        &mut modem,
+        &mut socket,
+        server_address()
+    ))
+    .unwrap();
+    assert!(modem
+        .backend()
+        .take_transcript()
+        .contains(&"AT+CFUN=21".to_string()));
+
+    TcpClientStack::close(&mut modem, socket).unwrap();
+    assert_eq!(
+        modem.backend().take_transcript(),
+        ["AT+CFUN=20", "AT+CFUN=40"]
+    );
+}
+
+/// A local port that nobody listens on
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[test]
+fn tcp_server_accepts_connection() {
+    let mut modem = modem(SimulatedModem::new());
+    modem.backend().take_transcript();
+    let port = free_port();
+
+    let mut listener = TcpClientStack::socket(&mut modem).unwrap();
+    TcpFullStack::bind(&mut modem, &mut listener, port).unwrap();
+    TcpFullStack::listen(&mut modem, &mut listener).unwrap();
+    assert!(modem
+        .backend()
+        .take_transcript()
+        .contains(&"AT+CFUN=21".to_string()));
+    assert!(matches!(
+        TcpFullStack::accept(&mut modem, &mut listener),
+        Err(nb::Error::WouldBlock)
+    ));
+
+    let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    let client_address = client.local_addr().unwrap();
+    let (mut connection, remote) =
+        nb::block!(TcpFullStack::accept(&mut modem, &mut listener)).unwrap();
+    assert_eq!(remote.port(), client_address.port());
+    assert!(TcpClientStack::is_connected(&mut modem, &connection).unwrap());
+
+    client.write_all(b"hello").unwrap();
+    let mut buffer = [0; 16];
+    let received = nb::block!(TcpClientStack::receive(
+        &mut modem,
+        &mut connection,
+        &mut buffer
+    ))
+    .unwrap();
+    assert_eq!(&buffer[..received], b"hello");
+    nb::block!(TcpClientStack::send(&mut modem, &mut connection, b"bye")).unwrap();
+    let mut buffer = [0; 3];
+    client.read_exact(&mut buffer).unwrap();
+    assert_eq!(&buffer, b"bye");
+
+    // The accepted connection keeps LTE on
+    TcpClientStack::close(&mut modem, listener).unwrap();
+    assert!(modem.backend().take_transcript().is_empty());
+    TcpClientStack::close(&mut modem, connection).unwrap();
+    assert_eq!(
+        modem.backend().take_transcript(),
+        ["AT+CFUN=20", "AT+CFUN=40"]
+    );
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn tcp_listen_needs_bind() {
+    let mut modem = modem(SimulatedModem::new());
+
+    let mut socket = TcpClientStack::socket(&mut modem).unwrap();
+    assert!(matches!(
+        TcpFullStack::listen(&mut modem, &mut socket),
+        Err(Error::SocketClosed)
+    ));
+    assert!(matches!(
+        TcpFullStack::accept(&mut modem, &mut socket),
+        Err(nb::Error::Other(Error::SocketClosed))
+    ));
+
+    TcpFullStack::bind(&mut modem, &mut socket, free_port()).unwrap();
+    assert!(matches!(
+        TcpFullStack::bind(&mut modem, &mut socket, free_port()),
+        Err(Error::SocketAlreadyOpen)
+    ));
+
+    TcpClientStack::close(&mut modem, socket).unwrap();
+}
+
+#[test]
+fn tcp_listen_after_failed_bind() {
+    let mut sim = SimulatedModem::new();
+    sim.script("AT+CEREG?", "+CEREG: 0,3\r\nOK").times(1);
+    let mut modem = modem(sim);
+
+    let mut socket = TcpClientStack::socket(&mut modem).unwrap();
+    assert!(matches!(
+        TcpFullStack::bind(&mut modem, &mut socket, free_port()),
+        Err(Error::LteRegistrationDenied)
+    ));
+    // The socket was never bound, so it can't listen
+    assert!(matches!(
+        TcpFullStack::listen(&mut modem, &mut socket),
+        Err(Error::SocketClosed)
+    ));
+
+    // Trying again counts LTE only once
+    TcpFullStack::bind(&mut modem, &mut socket, free_port()).unwrap();
+    TcpFullStack::listen(&mut modem, &mut socket).unwrap();
+
+    TcpClientStack::close(&mut modem, socket).unwrap();
+    assert!(modem
+        .backend()
+        .transcript()
+        .ends_with(&["AT+CFUN=20".to_string(), "AT+CFUN=40".to_string()]));
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn udp_reply_from_other_port() {
+    // The reply comes from another socket than the one the request went to, like with some NATs
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let server_local = server.local_addr().unwrap();
+    let replier = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let replier_local = replier.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut buffer = [0; 64];
+        let (length, sender) = server.recv_from(&mut buffer).unwrap();
+        replier.send_to(&buffer[..length], sender).unwrap();
+    });
+
+    let mut sim = SimulatedModem::new();
+    sim.redirect(server_address(), server_local);
+    let mut modem = modem(sim);
+
+    let mut socket = UdpClientStack::socket(&mut modem).unwrap();
+    UdpFullStack::bind(&mut modem, &mut socket, free_port()).unwrap();
+    nb::block!(modem.send_to(&mut socket, server_address(), b"ping")).unwrap();
+
+    let mut buffer = [0; 16];
+    let (received, from) = nb::block!(UdpClientStack::receive(
+        &mut modem,
+        &mut socket,
+        &mut buffer
+    ))
+    .unwrap();
+    assert_eq!(&buffer[..received], b"ping");
+    assert_eq!(from.port(), replier_local.port());
+
+    UdpClientStack::close(&mut modem, socket).unwrap();
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn udp_send_to_needs_bind() {
+    let mut modem = modem(SimulatedModem::new());
+
+    let mut socket = UdpClientStack::socket(&mut modem).unwrap();
+    assert!(matches!(
+        modem.send_to(&mut socket, server_address(), b"ping"),
+        Err(nb::Error::Other(Error::SocketClosed))
+    ));
+
+    UdpClientStack::close(&mut modem, socket).unwrap();
+}
+
+#[test]
+fn udp_bind_then_connect() {
+    let mut sim = SimulatedModem::new();
+    sim.redirect(server_address(), udp_echo_server());
+    let mut modem = modem(sim);
+
+    let mut socket = UdpClientStack::socket(&mut modem).unwrap();
+    UdpFullStack::bind(&mut modem, &mut socket, free_port()).unwrap();
+
+    // A bound socket has no address to send to until it's connected
+    assert!(matches!(
+        UdpClientStack::send(&mut modem, &mut socket, b"ping"),
+        Err(nb::Error::Other(Error::NrfSys(Errno::NotConnected)))
+    ));
+
+    UdpClientStack::connect(&mut modem, &mut socket, server_address()).unwrap();
+    nb::block!(UdpClientStack::send(&mut modem, &mut socket, b"ping")).unwrap();
+
+    let mut buffer = [0; 16];
+    let (received, _) = nb::block!(UdpClientStack::receive(
+        &mut modem,
+        &mut socket,
+        &mut buffer
+    ))
+    .unwrap();
+    assert_eq!(&buffer[..received], b"ping");
+
+    UdpClientStack::close(&mut modem, socket).unwrap();
+    assert_eq!(modem.backend().open_sockets(), 0);
+    // LTE was only counted once for the socket
+    assert!(modem
+        .backend()
+        .transcript()
+        .ends_with(&["AT+CFUN=20".to_string(), "AT+CFUN=40".to_string()]));
+}
@@ -0,0 +1,115 @@
+use nrf_modem_nal::{
+    embedded_nal::{nb, SocketAddr, TcpClientStack},
+    sim::{SimulatedModem, TlsConfiguration},
+    tls::{PeerVerification, TlsOptions},
+    Modem,
+};
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    thread,
+};
+
+mod common;
+
+const OPTIONS: TlsOptions = TlsOptions {
+    sec_tags: &[42, 43],
+    peer_verification: PeerVerification::Required,
+    hostname: Some("example.com"),
+};
+
+/// The address the code under test connects to, which is redirected to a local server
+fn server_address() -> SocketAddr {
+    "203.0.113.1:443".parse().unwrap()
+}
+
+fn modem(sim: SimulatedModem) -> Modem<SimulatedModem> {
+    let mut sim = sim;
+    sim.script("AT+CEREG?", "+CEREG: 0,1\r\nOK");
+    common::modem(sim)
+}
+
+/// Start a TCP server on localhost that echoes everything back on the first connection.
+/// The simulated TLS sockets don't encrypt anything.
+fn echo_server() -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buffer = [0; 64];
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(length) => stream.write_all(&buffer[..length]).unwrap(),
+            }
+        }
+    });
+
+    address
+}
+
+/// Generic code that only knows the [TcpClientStack]
+fn echo<S: TcpClientStack>(stack: &mut S, remote: SocketAddr) -> Result<[u8; 5], S::Error> {
+    let mut socket = stack.socket()?;
+    nb::block!(stack.connect(&mut socket, remote))?;
+    nb::block!(stack.send(&mut socket, b"hello"))?;
+
+    let mut buffer = [0; 5];
+    nb::block!(stack.receive(&mut socket, &mut buffer))?;
+    stack.close(socket)?;
+
+    Ok(buffer)
+}
+
+#[test]
+fn tls_echo() {
+    let mut sim = SimulatedModem::new();
+    sim.redirect(server_address(), echo_server());
+    let mut modem = modem(sim);
+
+    assert_eq!(
+        &echo(&mut modem.tls(OPTIONS), server_address()).unwrap(),
+        b"hello"
+    );
+
+    assert_eq!(
+        modem.backend().tls_configurations(),
+        [TlsConfiguration {
+            sec_tags: vec![42, 43],
+            peer_verification: PeerVerification::Required,
+            hostname: Some("example.com".to_string()),
+        }]
+    );
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn tls_socket_turns_lte_on_and_off() {
+    let mut sim = SimulatedModem::new();
+    sim.redirect(server_address(), echo_server());
+    let mut modem = modem(sim);
+    modem.backend().take_transcript();
+
+    let mut stack = modem.tls(TlsOptions {
+        sec_tags: &[1],
+        peer_verification: PeerVerification::None,
+        hostname: None,
+    });
+    let mut socket = stack.socket().unwrap();
+    assert!(!stack.is_connected(&socket).unwrap());
+    nb::block!(stack.connect(&mut socket, server_address())).unwrap();
+    assert!(stack.is_connected(&socket).unwrap());
+    assert!(stack
+        .modem()
+        .backend()
+        .take_transcript()
+        .contains(&"AT+CFUN=21".to_string()));
+
+    stack.close(socket).unwrap();
+    assert_eq!(
+        modem.backend().take_transcript(),
+        ["AT+CFUN=20", "AT+CFUN=40"]
+    );
+    assert_eq!(modem.backend().tls_configurations()[0].hostname, None);
+}
@@ -0,0 +1,258 @@
+use nrf_modem_nal::{
+    lte::ClockTime,
+    pdn::PdnEvent,
+    sim::SimulatedModem,
+    urc::{
+        CellMeasurement, CellMeasurementStatus, ModemEvent, NeighborCell, NetworkTime, ServingCell,
+        Urc, UrcKind,
+    },
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+mod common;
+use common::modem;
+
+#[test]
+fn parse_known_notifications() {
+    assert_eq!(
+        Urc::parse("+CSCON: 1").unwrap().unwrap(),
+        Urc::SignalingConnection(true)
+    );
+    assert_eq!(
+        Urc::parse("%MDMEV: ME OVERHEATED").unwrap().unwrap(),
+        Urc::ModemEvent(ModemEvent::Overheated)
+    );
+    assert_eq!(
+        Urc::parse("%MDMEV: PRACH CE-LEVEL 2").unwrap().unwrap(),
+        Urc::ModemEvent(ModemEvent::CeLevel(2))
+    );
+    assert_eq!(
+        Urc::parse("+CGEV: ME PDN ACT 0").unwrap().unwrap().kind(),
+        UrcKind::Pdn
+    );
+    assert!(matches!(
+        Urc::parse("+CEREG: 1,\"0140\",\"00011B07\",7").unwrap().unwrap(),
+        Urc::Registration(status) if status.cell_id == Some(0x00011B07)
+    ));
+
+    assert!(Urc::parse("+CSQ: 99,99").is_none());
+    assert!(Urc::parse("OK").is_none());
+    assert!(Urc::parse("%MDMEV: SOMETHING NEW").unwrap().is_err());
+}
+
+#[test]
+fn parse_network_time() {
+    assert_eq!(
+        NetworkTime::parse_notification("%XTIME: \"80\",\"81109251821408\",\"00\"").unwrap(),
+        NetworkTime {
+            time_zone: Some(8),
+            universal_time: Some(ClockTime {
+                year: 2018,
+                month: 1,
+                day: 29,
+                hour: 15,
+                minute: 28,
+                sec: 41,
+            }),
+            daylight_saving_time: Some(0),
+        }
+    );
+
+    // West of UTC, and without the universal time
+    assert_eq!(
+        NetworkTime::parse_notification("%XTIME: \"4A\",,\"10\"").unwrap(),
+        NetworkTime {
+            time_zone: Some(-24),
+            universal_time: None,
+            daylight_saving_time: Some(1),
+        }
+    );
+}
+
+#[test]
+fn parse_cell_measurement() {
+    let measurement = CellMeasurement::parse_notification(
+        "%NCELLMEAS: 0,\"00011B07\",\"26295\",\"00B7\",10512,9034,2,49,-16,107745,\
+         9034,1,45,-18,22,9034,3,40,-20,-8,107980",
+    )
+    .unwrap();
+
+    assert_eq!(measurement.status, CellMeasurementStatus::Success);
+    assert_eq!(
+        measurement.serving_cell,
+        Some(ServingCell {
+            cell_id: 0x00011B07,
+            mcc: 262,
+            mnc: 95,
+            tracking_area_code: 0x00B7,
+            timing_advance: 10512,
+            earfcn: 9034,
+            physical_cell_id: 2,
+            rsrp: 49,
+            rsrq: -16,
+            measurement_time: 107745,
+        })
+    );
+    assert_eq!(
+        measurement.neighbor_cells,
+        [
+            NeighborCell {
+                earfcn: 9034,
+                physical_cell_id: 1,
+                rsrp: 45,
+                rsrq: -18,
+                time_difference: 22,
+            },
+            NeighborCell {
+                earfcn: 9034,
+                physical_cell_id: 3,
+                rsrp: 40,
+                rsrq: -20,
+                time_difference: -8,
+            },
+        ]
+    );
+    assert_eq!(measurement.timing_advance_measurement_time, Some(107980));
+
+    let failed = CellMeasurement::parse_notification("%NCELLMEAS: 1").unwrap();
+    assert_eq!(failed.status, CellMeasurementStatus::Failed);
+    assert_eq!(failed.serving_cell, None);
+    assert!(failed.neighbor_cells.is_empty());
+}
+
+#[test]
+fn parse_cell_measurement_many_neighbors() {
+    let mut line =
+        "%NCELLMEAS: 0,\"00011B07\",\"26295\",\"00B7\",10512,9034,2,49,-16,107745".to_string();
+    for physical_cell_id in 10..20 {
+        line.push_str(&format!(",6400,{physical_cell_id},40,-20,5"));
+    }
+    line.push_str(",107980");
+
+    let measurement = CellMeasurement::parse_notification(&line).unwrap();
+
+    // Only the first neighbors fit, but the measurement time is still the last value
+    assert_eq!(measurement.neighbor_cells.len(), 8);
+    assert_eq!(measurement.neighbor_cells[0].physical_cell_id, 10);
+    assert_eq!(measurement.neighbor_cells[7].physical_cell_id, 17);
+    assert_eq!(measurement.timing_advance_measurement_time, Some(107980));
+}
+
+#[test]
+fn poll_queue() {
+    let mut modem = modem(SimulatedModem::new());
+    let mut dispatcher = modem.urc_dispatcher::<2>().unwrap();
+
+    modem.urc_poll(&mut dispatcher).unwrap();
+    assert_eq!(dispatcher.pop(), None);
+
+    modem.backend().push_notification("+CSCON: 1");
+    modem.backend().push_notification("+CGEV: ME PDN ACT 0");
+    modem.backend().push_notification("+CSCON: 0");
+    modem.urc_poll(&mut dispatcher).unwrap();
+
+    // The oldest notification is dropped when the queue is full
+    assert_eq!(dispatcher.dropped(), 1);
+    assert!(matches!(
+        dispatcher.pop(),
+        Some(Urc::Pdn(PdnEvent::Activated { cid: 0, .. }))
+    ));
+    assert_eq!(dispatcher.pop(), Some(Urc::SignalingConnection(false)));
+    assert_eq!(dispatcher.pop(), None);
+
+    modem.urc_close(dispatcher).unwrap();
+    assert_eq!(modem.backend().open_sockets(), 0);
+}
+
+#[test]
+fn poll_long_notification() {
+    let mut modem = modem(SimulatedModem::new());
+    let mut dispatcher = modem.urc_dispatcher::<2>().unwrap();
+
+    let mut line =
+        "%NCELLMEAS: 0,\"00011B07\",\"26295\",\"00B7\",10512,9034,2,49,-16,107745".to_string();
+    for physical_cell_id in 10..24 {
+        line.push_str(&format!(",1300,{physical_cell_id},40,-20,5"));
+    }
+    line.push_str(",107980");
+    assert!(line.len() > 256);
+
+    modem.backend().push_notification(&line);
+    modem.urc_poll(&mut dispatcher).unwrap();
+
+    // The whole notification is received, up to the measurement time at the end
+    let Some(Urc::CellMeasurement(measurement)) = dispatcher.pop() else {
+        panic!("expected a cell measurement");
+    };
+    assert_eq!(measurement.neighbor_cells.len(), 8);
+    assert_eq!(measurement.timing_advance_measurement_time, Some(107980));
+
+    modem.urc_close(dispatcher).unwrap();
+}
+
+static CONNECTION_CHANGES: AtomicUsize = AtomicUsize::new(0);
+
+fn count_connection_changes(urc: &Urc) {
+    assert!(matches!(urc, Urc::SignalingConnection(_)));
+    CONNECTION_CHANGES.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test]
+fn handlers() {
+    let mut modem = modem(SimulatedModem::new());
+    let mut dispatcher = modem.urc_dispatcher::<4>().unwrap();
+    dispatcher
+        .subscribe(UrcKind::SignalingConnection, count_connection_changes)
+        .unwrap();
+
+    modem.backend().push_notification("+CSCON: 1");
+    modem.backend().push_notification("%MDMEV: ME BATTERY LOW");
+    modem.backend().push_notification("+CSCON: 0");
+    modem.urc_poll(&mut dispatcher).unwrap();
+
+    // Only the notifications without a handler are queued
+    assert_eq!(CONNECTION_CHANGES.load(Ordering::SeqCst), 2);
+    assert_eq!(
+        dispatcher.pop(),
+        Some(Urc::ModemEvent(ModemEvent::BatteryLow))
+    );
+    assert_eq!(dispatcher.pop(), None);
+
+    dispatcher.unsubscribe(UrcKind::SignalingConnection);
+    modem.backend().push_notification("+CSCON: 1");
+    modem.urc_poll(&mut dispatcher).unwrap();
+    assert_eq!(CONNECTION_CHANGES.load(Ordering::SeqCst), 2);
+    assert_eq!(dispatcher.pop(), Some(Urc::SignalingConnection(true)));
+
+    modem.urc_close(dispatcher).unwrap();
+}
+
+#[test]
+fn responses_are_separated_from_notifications() {
+    let mut sim = SimulatedModem::new();
+    sim.script(
+        "AT+CEREG?",
+        "+CSCON: 1\r\n+CEREG: 5,1\r\nOK\r\n%MDMEV: SEARCH STATUS 2",
+    );
+    let mut modem = modem(sim);
+    let mut dispatcher = modem.urc_dispatcher::<4>().unwrap();
+
+    let mut lines = Vec::new();
+    modem
+        .urc_send_command(&mut dispatcher, "AT+CEREG?", |line| {
+            lines.push(line.to_string())
+        })
+        .unwrap();
+
+    // The response looks like a `+CEREG` notification, but belongs to the command
+    assert_eq!(lines, ["+CEREG: 5,1"]);
+    assert_eq!(dispatcher.pop(), Some(Urc::SignalingConnection(true)));
+    assert_eq!(
+        dispatcher.pop(),
+        Some(Urc::ModemEvent(ModemEvent::SearchDone))
+    );
+    assert_eq!(dispatcher.pop(), None);
+
+    modem.urc_close(dispatcher).unwrap();
+    assert_eq!(modem.backend().transcript(), ["AT+CEREG?"]);
+}